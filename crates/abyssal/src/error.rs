@@ -1,7 +1,9 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::Path};
 
 use argon2::password_hash;
-use rocket::serde::json::Json;
+use rocket::{data::ByteUnit, serde::json::Json};
+
+use crate::types::Uuid;
 
 #[abyssal_macros::make_error]
 pub enum Error {
@@ -36,7 +38,211 @@ pub enum Error {
     UnknownPermission(String),
 
     #[error(format = "IO error: {0:?}", arc, from, code = "server.io")]
-    Io(std::io::Error)
+    Io(std::io::Error),
+
+    #[error(format = "Metadata database error: {0:?}", arc, from, code = "server.metadata")]
+    Metadata(sled::Error),
+
+    #[error(format = "Serialization error: {0:?}", arc, from, code = "server.serialization")]
+    Serialization(serde_json::Error),
+
+    #[error(format = "Insufficient permissions to perform this action", code = "auth.forbidden", status = 403)]
+    Forbidden,
+
+    #[error(format = "Unknown root directory: {0}", code = "root.not_found", status = 404)]
+    RootNotFound(Uuid),
+
+    #[error(format = "Path not found: {0}", code = "path.not_found", status = 404)]
+    PathNotFound(String),
+
+    #[error(format = "Path is outside of its root directory: {0}", code = "path.outside_root", status = 403)]
+    PathOutsideRoot(String),
+
+    #[error(format = "Path is not a directory: {0}", code = "path.not_directory", status = 400)]
+    NotADirectory(String),
+
+    #[error(format = "Path is not a file: {0}", code = "path.not_file", status = 400)]
+    NotAFile(String),
+
+    #[error(format = "Path already exists: {0}", code = "path.conflict", status = 409)]
+    PathConflict(String),
+
+    #[error(format = "Path can't be moved, renamed or deleted: {0}", code = "path.protected", status = 403)]
+    ProtectedPath(String),
+
+    #[error(format = "Can't move or copy a directory into itself: {0}", code = "path.into_itself", status = 400)]
+    PathIntoItself(String),
+
+    #[error(format = "Directory is not empty: {0}", code = "path.not_empty", status = 409)]
+    DirectoryNotEmpty(String),
+
+    #[error(format = "Unknown trash item: {0}", code = "trash.not_found", status = 404)]
+    TrashItemNotFound(Uuid),
+
+    #[error(format = "Unknown job: {0}", code = "job.not_found", status = 404)]
+    JobNotFound(Uuid),
+
+    #[error(format = "Job has already finished: {0}", code = "job.finished", status = 409)]
+    JobFinished(Uuid),
+
+    #[error(format = "Job is still running: {0}", code = "job.running", status = 409)]
+    JobRunning(Uuid),
+
+    #[error(format = "Job was cancelled", code = "job.cancelled", status = 409)]
+    JobCancelled,
+
+    #[error(format = "Invalid message: {0}", code = "watch.invalid_message", status = 400)]
+    InvalidMessage(String),
+
+    #[error(format = "Too many subscriptions (at most {0} per connection)", code = "watch.too_many_subscriptions", status = 400)]
+    TooManySubscriptions(usize),
+
+    #[error(format = "Unknown upload session: {0}", code = "upload.not_found", status = 404)]
+    UploadNotFound(Uuid),
+
+    #[error(format = "Upload exceeds the size limit of {0}", code = "upload.too_large", status = 413)]
+    UploadTooLarge(ByteUnit),
+
+    #[error(format = "Chunk offset does not match the number of bytes received (expected {0})", code = "upload.offset_mismatch", status = 409)]
+    UploadOffsetMismatch(u64),
+
+    #[error(format = "Upload is incomplete: received {0} of {1} bytes", code = "upload.incomplete", status = 409)]
+    UploadIncomplete(u64, u64),
+
+    #[error(format = "Incorrect or missing password", code = "auth.password", status = 401)]
+    IncorrectPassword,
+
+    #[error(format = "Files with this extension are not accepted here: {0}", code = "upload.extension_not_allowed", status = 415)]
+    ExtensionNotAllowed(String),
+
+    #[error(format = "Invalid file name: {0}", code = "path.invalid_name", status = 400)]
+    InvalidFileName(String),
+
+    #[error(format = "Unknown upload target: {0}", code = "upload_target.not_found", status = 404)]
+    UploadTargetNotFound(Uuid),
+
+    #[error(format = "This upload target has expired", code = "upload_target.expired", status = 410)]
+    UploadTargetExpired,
+
+    #[error(format = "This upload target is not accepting any more files", code = "upload_target.full", status = 409)]
+    UploadTargetFull,
+
+    #[error(format = "Root directory modification is disabled by the server configuration", code = "root.modification_disabled", status = 403)]
+    RootModificationDisabled,
+
+    #[error(format = "Root directory <{0}> is declared in the server configuration and cannot be modified", code = "root.managed", status = 409)]
+    ManagedRoot(String),
+
+    #[error(format = "A root directory named <{0}> already exists", code = "root.name_taken", status = 409)]
+    RootNameTaken(String),
+
+    #[error(format = "Unknown or invalid invite code", code = "invite.not_found", status = 404)]
+    InviteNotFound,
+
+    #[error(format = "Invite has expired, been revoked, or has no uses left", code = "invite.expired", status = 410)]
+    InviteExpired,
+
+    #[error(format = "Invalid username: {0}", code = "auth.invalid_username", status = 400)]
+    InvalidUsername(String),
+
+    #[error(format = "Username is already taken: {0}", code = "auth.username_taken", status = 409)]
+    UsernameTaken(String),
+
+    #[error(format = "Unknown share: {0}", code = "share.not_found", status = 404)]
+    ShareNotFound(Uuid),
+
+    #[error(format = "Share has expired", code = "share.expired", status = 410)]
+    ShareExpired,
+
+    #[error(format = "Share has reached its download limit", code = "share.exhausted", status = 410)]
+    ShareExhausted,
+
+    #[error(format = "OIDC login is not configured", code = "oidc.disabled", status = 404)]
+    OidcDisabled,
+
+    #[error(format = "OIDC provider error: {0}", code = "oidc.provider", status = 502)]
+    OidcProvider(String),
+
+    #[error(format = "Unknown or expired OIDC login", code = "oidc.login_expired", status = 400)]
+    OidcLoginExpired,
+
+    #[error(format = "OIDC login was started by a different client", code = "oidc.state_mismatch", status = 400)]
+    OidcStateMismatch,
+
+    #[error(format = "Invalid OIDC ID token: {0}", code = "oidc.invalid_token", status = 401)]
+    OidcInvalidToken(String),
+
+    #[error(format = "Session has expired", code = "auth.session_expired", status = 401)]
+    SessionExpired,
+
+    #[error(format = "Unknown session: {0}", code = "auth.session_not_found", status = 404)]
+    SessionNotFound(String),
+
+    #[error(format = "Unknown application: {0}", code = "application.not_found", status = 404)]
+    ApplicationNotFound(Uuid),
+
+    #[error(format = "Unknown personal access token: {0}", code = "auth.token_not_found", status = 404)]
+    PersonalTokenNotFound(String),
+
+    #[error(format = "Expiry must be in the future", code = "auth.invalid_expiry", status = 400)]
+    InvalidExpiry,
+
+    #[error(format = "This account has been disabled", code = "auth.disabled", status = 403)]
+    AccountDisabled,
+
+    #[error(format = "Unknown user: {0}", code = "user.not_found", status = 404)]
+    UserNotFound(Uuid),
+
+    #[error(format = "User <{0}> has a home directory, which renaming them would orphan", code = "user.has_home", status = 409)]
+    UserHasHome(String),
+
+    #[error(format = "User still has unfinished jobs: {0}", code = "user.active_jobs", status = 409)]
+    UserHasActiveJobs(Uuid),
+
+    #[error(format = "Unknown group: {0}", code = "group.not_found", status = 404)]
+    GroupNotFound(String),
+
+    #[error(format = "A group named <{0}> already exists", code = "group.name_taken", status = 409)]
+    GroupNameTaken(String),
+
+    #[error(format = "Invalid group name: {0}", code = "group.invalid_name", status = 400)]
+    InvalidGroupName(String),
+
+    #[error(format = "Invalid email address: {0}", code = "user.invalid_email", status = 400)]
+    InvalidEmail(String),
+
+    #[error(format = "Too many failed login attempts, retry in {0} seconds", code = "auth.throttled", status = 429, header = "Retry-After")]
+    TooManyAttempts(u64),
+
+    #[error(format = "Two-factor authentication is already enabled", code = "auth.totp_enabled", status = 409)]
+    TotpAlreadyEnabled,
+
+    #[error(format = "Two-factor authentication is not enabled", code = "auth.totp_disabled", status = 409)]
+    TotpNotEnabled,
+
+    #[error(format = "Incorrect two-factor code", code = "auth.totp_code", status = 401)]
+    IncorrectTotpCode,
+
+    #[error(format = "Unknown or expired login challenge", code = "auth.challenge_expired", status = 401)]
+    LoginChallengeExpired,
+
+    #[error(format = "Passkeys are not configured", code = "webauthn.disabled", status = 404)]
+    WebauthnDisabled,
+
+    #[error(format = "WebAuthn error: {0:?}", arc, from, code = "webauthn.failed", status = 400)]
+    Webauthn(webauthn_rs::prelude::WebauthnError),
+
+    #[error(format = "Unknown or expired WebAuthn ceremony", code = "webauthn.ceremony_expired", status = 400)]
+    WebauthnCeremonyExpired,
+
+    #[error(format = "Unknown passkey: {0}", code = "webauthn.passkey_not_found", status = 404)]
+    PasskeyNotFound(String),
+
+    #[error(format = "This passkey is already registered", code = "webauthn.passkey_registered", status = 409)]
+    PasskeyAlreadyRegistered,
+
+    #[error(format = "Missing or incorrect CSRF token", code = "auth.csrf", status = 403)]
+    CsrfMismatch
 }
 
 impl Error {
@@ -48,6 +254,38 @@ impl Error {
     pub fn unknown_permission(permission: impl Into<String>) -> Self {
         Self::UnknownPermission(permission.into())
     }
+
+    pub fn path_not_found(path: impl AsRef<Path>) -> Self {
+        Self::PathNotFound(path.as_ref().to_string_lossy().to_string())
+    }
+
+    pub fn path_outside_root(path: impl AsRef<Path>) -> Self {
+        Self::PathOutsideRoot(path.as_ref().to_string_lossy().to_string())
+    }
+
+    pub fn not_a_directory(path: impl AsRef<Path>) -> Self {
+        Self::NotADirectory(path.as_ref().to_string_lossy().to_string())
+    }
+
+    pub fn path_conflict(path: impl AsRef<Path>) -> Self {
+        Self::PathConflict(path.as_ref().to_string_lossy().to_string())
+    }
+
+    pub fn not_a_file(path: impl AsRef<Path>) -> Self {
+        Self::NotAFile(path.as_ref().to_string_lossy().to_string())
+    }
+
+    pub fn protected_path(path: impl AsRef<Path>) -> Self {
+        Self::ProtectedPath(path.as_ref().to_string_lossy().to_string())
+    }
+
+    pub fn path_into_itself(path: impl AsRef<Path>) -> Self {
+        Self::PathIntoItself(path.as_ref().to_string_lossy().to_string())
+    }
+
+    pub fn directory_not_empty(path: impl AsRef<Path>) -> Self {
+        Self::DirectoryNotEmpty(path.as_ref().to_string_lossy().to_string())
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -38,11 +38,12 @@ async fn launch_inner() -> Rocket<Build> {
             .expect("Should be able to create the filesystem root");
     }
 
-    if !config.filesystem().filesystem().join(".abyssal").is_dir() {
-        std::fs::create_dir_all(config.filesystem().filesystem().join(".abyssal"))
+    if !config.filesystem().metadata_dir().is_dir() {
+        std::fs::create_dir_all(config.filesystem().metadata_dir())
             .expect("Should be able to create the .abyssal directory");
     }
 
+    let events = util::EventBus::default();
     rocket::custom(rocket_config)
         .manage(
             mongodb::Client::with_uri_str(config.database().url())
@@ -54,11 +55,16 @@ async fn launch_inner() -> Rocket<Build> {
             sled::Config::default()
                 .mode(sled::Mode::HighThroughput)
                 .use_compression(true)
-                .path(config.filesystem().filesystem().join(".abyssal/meta.db"))
+                .path(config.filesystem().metadata_dir().join("meta.db"))
                 .open()
                 .expect("Should be able to open/create meta.db")
         )
         .manage(openapi_spec)
+        .manage(events.clone())
+        .manage(util::JobRegistry::new(events))
+        .manage(util::UploadLocks::default())
+        .manage(util::OidcDiscovery::default())
+        .manage(util::FsWatcher::new().expect("Should be able to start the filesystem watcher"))
         .mount("/api", routes)
         .mount(
             "/api/doc/openapi",
@@ -108,6 +114,10 @@ async fn launch_inner() -> Rocket<Build> {
             })
         }))
         .attach(util::generate_resources())
+        .attach(util::session_cleanup())
+        .attach(util::trash_cleanup())
+        .attach(util::upload_cleanup())
+        .attach(util::job_supervisor())
 }
 
 #[launch]
@@ -0,0 +1,76 @@
+use chrono::{DateTime, Utc};
+use getset::CloneGetters;
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{models::Job, types::Uuid};
+
+/// How a share was accessed
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareAccess {
+    List,
+    Download,
+}
+
+/// What happened. Paths are root-relative unless stated otherwise.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum EventPayload {
+    /// One of the caller's upload sessions was finalized
+    UploadCompleted {
+        upload: Uuid,
+        root: Uuid,
+        path: String,
+        size: u64,
+    },
+
+    /// A file was anonymously uploaded into one of the caller's upload targets
+    DropReceived {
+        target: Uuid,
+        root: Uuid,
+        path: String,
+        size: u64,
+    },
+
+    /// One of the caller's jobs changed status or made progress
+    JobUpdated { job: Box<Job> },
+
+    /// One of the caller's shares was accessed
+    ShareAccessed {
+        share: Uuid,
+        access: ShareAccess,
+
+        /// Accessed path, relative to the shared item
+        path: String,
+    },
+
+    /// Sessions or personal access tokens of the caller were revoked, by their session IDs.
+    /// The event stream is closed after this if its own session is among them.
+    SessionsRevoked { sessions: Vec<String> },
+
+    /// The caller's own or group permissions changed, so cached permissions should be reloaded
+    PermissionsChanged,
+
+    /// Events were dropped because the client didn't keep up
+    Lagged { missed: u64 },
+}
+
+/// Message sent over the event stream
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters)]
+#[getset(get_clone = "pub")]
+pub struct Event {
+    id: Uuid,
+    timestamp: DateTime<Utc>,
+    payload: EventPayload,
+}
+
+impl Event {
+    pub fn new(payload: EventPayload) -> Self {
+        Self {
+            id: Uuid::new(),
+            timestamp: Utc::now(),
+            payload,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use bson::doc;
+use getset::{CloneGetters, WithSetters};
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{Model, User, UserMethods},
+    types::{PermissionSet, Uuid},
+    util::Collection,
+};
+
+/// Named set of permissions shared by its members. Users reference groups by name.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct Group {
+    #[serde(default)]
+    id: Uuid,
+
+    name: String,
+
+    #[serde(default)]
+    display_name: Option<String>,
+
+    /// OIDC groups (from the provider's groups claim) whose members also belong to this group
+    #[serde(default)]
+    oidc_groups: Vec<String>,
+
+    #[serde(default)]
+    permissions: PermissionSet,
+}
+
+impl Model for Group {
+    fn collection() -> &'static str {
+        "auth.groups"
+    }
+
+    fn model_id(&self) -> Uuid {
+        self.id()
+    }
+}
+
+impl Group {
+    pub fn new(name: impl Into<String>, display_name: Option<impl Into<String>>) -> Self {
+        Self {
+            id: Uuid::new(),
+            name: name.into(),
+            display_name: display_name.map(|v| v.into()),
+            oidc_groups: Vec::new(),
+            permissions: PermissionSet::new(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+pub trait GroupCollectionExt {
+    async fn by_name(&self, name: impl Into<String> + Send + Sync) -> crate::Result<Option<Group>>;
+
+    /// All groups `user` belongs to
+    async fn memberships(&self, user: &User) -> crate::Result<Vec<Group>>;
+
+    /// `user`'s own permissions combined with those of all of their groups
+    async fn effective_permissions(&self, user: &User) -> crate::Result<PermissionSet>;
+
+    /// Loads the owner a share or upload target acts for, with their effective permissions.
+    /// Disabled owners are treated as missing, so their resources stop working.
+    async fn effective_owner(
+        &self,
+        users: &Collection<User>,
+        owner: Uuid,
+    ) -> crate::Result<Option<User>>;
+}
+
+#[rocket::async_trait]
+impl GroupCollectionExt for Collection<Group> {
+    async fn by_name(&self, name: impl Into<String> + Send + Sync) -> crate::Result<Option<Group>> {
+        Ok(self.find_one(doc! {"name": name.into()}).await?)
+    }
+
+    async fn memberships(&self, user: &User) -> crate::Result<Vec<Group>> {
+        let oidc_groups = match user {
+            User::Oidc(oidc) => oidc.oidc_groups(),
+            _ => Vec::new(),
+        };
+        let mut cursor = self
+            .find(doc! {"$or": [
+                {"name": {"$in": user.groups()}},
+                {"oidc_groups": {"$in": oidc_groups}},
+            ]})
+            .await?;
+        let mut groups = Vec::new();
+        while cursor.advance().await? {
+            groups.push(cursor.deserialize_current()?);
+        }
+
+        Ok(groups)
+    }
+
+    async fn effective_permissions(&self, user: &User) -> crate::Result<PermissionSet> {
+        Ok(self
+            .memberships(user)
+            .await?
+            .iter()
+            .fold(user.permissions(), |permissions, group| {
+                permissions.union(&group.permissions())
+            }))
+    }
+
+    async fn effective_owner(
+        &self,
+        users: &Collection<User>,
+        owner: Uuid,
+    ) -> crate::Result<Option<User>> {
+        match users.get(owner).await? {
+            Some(owner) if !owner.disabled() => {
+                let permissions = self.effective_permissions(&owner).await?;
+                Ok(Some(owner.with_permissions(permissions)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use getset::{CloneGetters, WithSetters};
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::Model,
+    types::{Permission, PermissionCapability, RootTopLevel, Uuid},
+};
+
+/// Invite code creating a local user with access to a root directory when redeemed
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct Invite {
+    #[serde(default)]
+    id: Uuid,
+
+    /// Secret code used to redeem the invite
+    code: String,
+    owner: Uuid,
+    root: Uuid,
+    top_level: RootTopLevel,
+    capability: PermissionCapability,
+    created: DateTime<Utc>,
+
+    #[serde(default)]
+    expires: Option<DateTime<Utc>>,
+
+    /// Number of times this invite may be redeemed (`None` for unlimited)
+    #[serde(default)]
+    max_uses: Option<u64>,
+
+    #[serde(default)]
+    uses: u64,
+
+    #[serde(default)]
+    revoked: bool,
+
+    /// Users created through this invite
+    #[serde(default)]
+    redeemed_by: Vec<Uuid>,
+}
+
+impl Model for Invite {
+    fn collection() -> &'static str {
+        "auth.invites"
+    }
+
+    fn model_id(&self) -> Uuid {
+        self.id()
+    }
+}
+
+impl Invite {
+    pub fn new(
+        owner: impl Into<Uuid>,
+        root: impl Into<Uuid>,
+        top_level: RootTopLevel,
+        capability: PermissionCapability,
+    ) -> Self {
+        let mut code_bytes = [0u8; 24];
+        OsRng.fill_bytes(&mut code_bytes);
+
+        Self {
+            id: Uuid::new(),
+            code: base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(code_bytes),
+            owner: owner.into(),
+            root: root.into(),
+            top_level,
+            capability,
+            created: Utc::now(),
+            expires: None,
+            max_uses: None,
+            uses: 0,
+            revoked: false,
+            redeemed_by: Vec::new(),
+        }
+    }
+
+    /// Whether this invite can still be redeemed
+    pub fn is_usable(&self) -> bool {
+        !self.revoked
+            && self.expires.is_none_or(|expires| expires > Utc::now())
+            && self.max_uses.is_none_or(|max| self.uses < max)
+    }
+
+    /// Permission granted to users redeeming this invite
+    pub fn permission(&self) -> Permission {
+        Permission::RootDirectory {
+            root: self.root(),
+            top_level: self.top_level(),
+            capability: self.capability(),
+        }
+    }
+}
+
+/// Anonymous view of an [`Invite`], shown before redeeming it
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct PublicInvite {
+    pub root: Uuid,
+    pub top_level: RootTopLevel,
+    pub capability: PermissionCapability,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl From<Invite> for PublicInvite {
+    fn from(value: Invite) -> Self {
+        Self {
+            root: value.root(),
+            top_level: value.top_level(),
+            capability: value.capability(),
+            expires: value.expires(),
+        }
+    }
+}
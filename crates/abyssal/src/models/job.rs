@@ -0,0 +1,139 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use getset::{CloneGetters, WithSetters};
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{models::MetaRecord, types::Uuid};
+
+/// How long finished jobs are kept around for their owners to inspect
+const JOB_RETENTION: TimeDelta = TimeDelta::days(7);
+
+/// What a job was asked to do. Paths are root-relative.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum JobOperation {
+    Copy {
+        root: Uuid,
+        path: String,
+        destination_root: Uuid,
+
+        /// Path of the copy
+        destination: String,
+    },
+    Move {
+        root: Uuid,
+        path: String,
+        destination_root: Uuid,
+
+        /// Path the entry is moved to
+        destination: String,
+    },
+    Delete {
+        root: Uuid,
+        path: String,
+
+        /// Deleted immediately instead of being moved to the trash
+        permanent: bool,
+    },
+    Archive {
+        root: Uuid,
+        paths: Vec<String>,
+
+        /// Path of the created zip archive
+        destination: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting for other jobs to finish
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+
+    /// The server stopped while the job was running
+    Interrupted,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, Default)]
+pub struct JobProgress {
+    /// Number of files to process, once known
+    pub files_total: Option<u64>,
+    pub files_done: u64,
+
+    /// Number of bytes to process, once known
+    pub bytes_total: Option<u64>,
+    pub bytes_done: u64,
+
+    /// Root-relative path of the item being processed
+    pub current: Option<String>,
+}
+
+/// Long-running filesystem operation, executed in the background
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct Job {
+    #[serde(default)]
+    id: Uuid,
+
+    /// User who started the job
+    owner: Uuid,
+    operation: JobOperation,
+    status: JobStatus,
+
+    #[serde(default)]
+    progress: JobProgress,
+
+    /// Why the job failed
+    #[serde(default)]
+    error: Option<String>,
+
+    created: DateTime<Utc>,
+
+    #[serde(default)]
+    started: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    finished: Option<DateTime<Utc>>,
+}
+
+impl MetaRecord for Job {
+    fn tree() -> &'static str {
+        "jobs"
+    }
+
+    fn record_id(&self) -> String {
+        self.id.to_string()
+    }
+}
+
+impl Job {
+    pub fn new(owner: impl Into<Uuid>, operation: JobOperation) -> Self {
+        Self {
+            id: Uuid::new(),
+            owner: owner.into(),
+            operation,
+            status: JobStatus::Queued,
+            progress: JobProgress::default(),
+            error: None,
+            created: Utc::now(),
+            started: None,
+            finished: None,
+        }
+    }
+
+    /// Whether this job finished long enough ago to be forgotten
+    pub fn is_stale(&self) -> bool {
+        self.finished
+            .is_some_and(|finished| finished + JOB_RETENTION <= Utc::now())
+    }
+}
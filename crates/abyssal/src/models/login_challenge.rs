@@ -0,0 +1,56 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine as _;
+use chrono::{DateTime, TimeDelta, Utc};
+use getset::{CloneGetters, WithSetters};
+use serde::{Deserialize, Serialize};
+
+use crate::{models::MetaRecord, types::Uuid};
+
+/// Time allowed between a password login and completing its second factor
+const CHALLENGE_LIFETIME: TimeDelta = TimeDelta::minutes(5);
+
+/// Incorrect codes allowed before a challenge is discarded
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Pending login awaiting a second factor, keyed by its (secret) challenge token
+#[derive(Serialize, Deserialize, Clone, Debug, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct LoginChallenge {
+    id: String,
+    user: Uuid,
+    created: DateTime<Utc>,
+
+    #[serde(default)]
+    attempts: u32,
+}
+
+impl MetaRecord for LoginChallenge {
+    fn tree() -> &'static str {
+        "login_challenges"
+    }
+
+    fn record_id(&self) -> String {
+        self.id()
+    }
+}
+
+impl LoginChallenge {
+    pub fn new(user: impl Into<Uuid>) -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self {
+            id: base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(bytes),
+            user: user.into(),
+            created: Utc::now(),
+            attempts: 0,
+        }
+    }
+
+    pub fn expires(&self) -> DateTime<Utc> {
+        self.created + CHALLENGE_LIFETIME
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires() <= Utc::now() || self.attempts >= MAX_ATTEMPTS
+    }
+}
@@ -0,0 +1,294 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use getset::CloneGetters;
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::MetaRecord,
+    types::{Uuid, config::LoginThrottleConfig},
+    util::MetaTree,
+};
+
+/// What a failed login (or share/upload target password) is counted against
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "value")]
+pub enum ThrottleSubject {
+    Username(String),
+    Ip(IpAddr),
+    Share(Uuid),
+    UploadTarget(Uuid),
+}
+
+impl ThrottleSubject {
+    pub fn key(&self) -> String {
+        match self {
+            ThrottleSubject::Username(name) => format!("username:{name}"),
+            ThrottleSubject::Ip(ip) => format!("ip:{ip}"),
+            ThrottleSubject::Share(id) => format!("share:{id}"),
+            ThrottleSubject::UploadTarget(id) => format!("upload_target:{id}"),
+        }
+    }
+}
+
+/// Failed login counter for a single [`ThrottleSubject`]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters)]
+#[getset(get_clone = "pub")]
+pub struct LoginThrottle {
+    subject: ThrottleSubject,
+
+    /// Failed logins since the counter was last reset
+    failures: u32,
+    last_failure: DateTime<Utc>,
+
+    #[serde(default)]
+    locked_until: Option<DateTime<Utc>>,
+}
+
+impl MetaRecord for LoginThrottle {
+    fn tree() -> &'static str {
+        "login_throttles"
+    }
+
+    fn record_id(&self) -> String {
+        self.subject.key()
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(subject: ThrottleSubject) -> Self {
+        Self {
+            subject,
+            failures: 0,
+            last_failure: Utc::now(),
+            locked_until: None,
+        }
+    }
+
+    /// Whether enough time has passed since the last failure for the counter to reset
+    pub fn is_stale(&self, config: &LoginThrottleConfig) -> bool {
+        self.locked_until.is_none_or(|until| until <= Utc::now())
+            && self.last_failure + TimeDelta::seconds(config.reset_after() as i64) <= Utc::now()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked_until.is_some_and(|until| until > Utc::now())
+    }
+
+    /// Earliest time another login may be attempted, if that's in the future
+    pub fn retry_at(&self, config: &LoginThrottleConfig) -> Option<DateTime<Utc>> {
+        if self.is_locked() {
+            return self.locked_until;
+        }
+        if self.is_stale(config) || self.failures <= config.free_attempts() {
+            return None;
+        }
+
+        let doublings = (self.failures - config.free_attempts() - 1).min(32);
+        let delay = config
+            .base_delay()
+            .saturating_mul(1u64 << doublings)
+            .min(config.max_delay());
+        Some(self.last_failure + TimeDelta::seconds(delay as i64)).filter(|at| *at > Utc::now())
+    }
+
+    /// Seconds until another login may be attempted (rounded up), if throttled
+    pub fn retry_after(&self, config: &LoginThrottleConfig) -> Option<u64> {
+        self.retry_at(config)
+            .map(|at| ((at - Utc::now()).num_milliseconds().max(0) as u64).div_ceil(1000))
+    }
+
+    pub fn with_failure(mut self, config: &LoginThrottleConfig) -> Self {
+        if self.is_stale(config) {
+            self.failures = 0;
+            self.locked_until = None;
+        }
+
+        self.failures = self.failures.saturating_add(1);
+        self.last_failure = Utc::now();
+        if config.lockout_threshold() > 0 && self.failures >= config.lockout_threshold() {
+            self.locked_until =
+                Some(self.last_failure + TimeDelta::seconds(config.lockout_duration() as i64));
+        }
+        self
+    }
+}
+
+pub trait LoginThrottleTreeExt {
+    /// Fails with [`crate::Error::TooManyAttempts`] if any of `subjects` is currently throttled
+    fn check(
+        &self,
+        subjects: &[ThrottleSubject],
+        config: &LoginThrottleConfig,
+    ) -> crate::Result<()>;
+
+    fn record_failure(
+        &self,
+        subjects: &[ThrottleSubject],
+        config: &LoginThrottleConfig,
+    ) -> crate::Result<()>;
+}
+
+impl LoginThrottleTreeExt for MetaTree<LoginThrottle> {
+    fn check(
+        &self,
+        subjects: &[ThrottleSubject],
+        config: &LoginThrottleConfig,
+    ) -> crate::Result<()> {
+        if !config.enabled() {
+            return Ok(());
+        }
+
+        let mut retry_after = None;
+        for subject in subjects {
+            if let Some(throttle) = self.get(subject.key())?
+                && let Some(seconds) = throttle.retry_after(config)
+            {
+                retry_after = retry_after.max(Some(seconds));
+            }
+        }
+
+        match retry_after {
+            Some(seconds) => Err(crate::Error::TooManyAttempts(seconds)),
+            None => Ok(()),
+        }
+    }
+
+    fn record_failure(
+        &self,
+        subjects: &[ThrottleSubject],
+        config: &LoginThrottleConfig,
+    ) -> crate::Result<()> {
+        if !config.enabled() {
+            return Ok(());
+        }
+
+        for subject in subjects {
+            let throttle = self
+                .get(subject.key())?
+                .unwrap_or_else(|| LoginThrottle::new(subject.clone()));
+            let _ = self.save(throttle.with_failure(config))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn config(lockout_threshold: u32) -> LoginThrottleConfig {
+        serde_json::from_value(json!({
+            "free_attempts": 2,
+            "base_delay": 1,
+            "max_delay": 10,
+            "lockout_threshold": lockout_threshold,
+            "lockout_duration": 600,
+            "reset_after": 3600,
+        }))
+        .unwrap()
+    }
+
+    fn throttle(failures: u32, since_last: TimeDelta) -> LoginThrottle {
+        LoginThrottle {
+            subject: ThrottleSubject::Username(String::from("alice")),
+            failures,
+            last_failure: Utc::now() - since_last,
+            locked_until: None,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_after_free_attempts_up_to_the_maximum() {
+        let config = config(0);
+        let delays = (1..=8)
+            .map(|failures| {
+                let throttle = throttle(failures, TimeDelta::zero());
+                throttle
+                    .retry_at(&config)
+                    .map(|at| (at - throttle.last_failure).num_seconds())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [
+                None,
+                None,
+                Some(1),
+                Some(2),
+                Some(4),
+                Some(8),
+                Some(10),
+                Some(10)
+            ]
+        );
+        assert_eq!(
+            throttle(40, TimeDelta::zero()).retry_after(&config),
+            Some(10)
+        );
+    }
+
+    #[test]
+    fn delay_passes() {
+        let config = config(0);
+        assert!(
+            throttle(5, TimeDelta::seconds(3))
+                .retry_at(&config)
+                .is_some()
+        );
+        assert!(
+            throttle(5, TimeDelta::seconds(5))
+                .retry_at(&config)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn locks_out_at_the_threshold() {
+        let config = config(4);
+        let mut throttle = LoginThrottle::new(ThrottleSubject::Username(String::from("alice")));
+        for _ in 0..3 {
+            throttle = throttle.with_failure(&config);
+            assert!(!throttle.is_locked());
+        }
+
+        throttle = throttle.with_failure(&config);
+        assert!(throttle.is_locked());
+        assert_eq!(throttle.retry_at(&config), throttle.locked_until());
+        assert!(
+            throttle
+                .retry_after(&config)
+                .is_some_and(|seconds| seconds > 590)
+        );
+    }
+
+    #[test]
+    fn resets_once_stale() {
+        let config = config(4);
+        let stale = throttle(10, TimeDelta::seconds(3600));
+        assert!(stale.is_stale(&config));
+        assert!(stale.retry_at(&config).is_none());
+
+        let throttle = stale.with_failure(&config);
+        assert_eq!(throttle.failures(), 1);
+        assert!(!throttle.is_locked());
+    }
+
+    #[test]
+    fn lockouts_outlast_the_reset_window() {
+        let config = config(4);
+        let mut locked = throttle(4, TimeDelta::seconds(3600));
+        locked.locked_until = Some(Utc::now() + TimeDelta::seconds(60));
+        assert!(!locked.is_stale(&config));
+        assert!(locked.with_failure(&config).is_locked());
+
+        let mut expired = throttle(4, TimeDelta::seconds(3600));
+        expired.locked_until = Some(Utc::now() - TimeDelta::seconds(60));
+        assert!(expired.is_stale(&config));
+        let throttle = expired.with_failure(&config);
+        assert_eq!((throttle.failures(), throttle.is_locked()), (1, false));
+    }
+}
@@ -10,11 +10,56 @@ pub trait Model: Serialize + DeserializeOwned + Clone + Debug + Send + Sync {
     }
 }
 
+/// Record persisted in the local sled metadata database (`.abyssal/meta.db`) rather than MongoDB
+pub trait MetaRecord: Serialize + DeserializeOwned + Clone + Debug + Send + Sync {
+    fn tree() -> &'static str;
+    fn record_id(&self) -> String;
+}
+
 pub mod user;
-pub use user::{User, UserKind, UserMethods, GenericUser};
+pub use user::{ApplicationUser, LocalUser, User, UserKind, UserMethods, GenericUser};
 
 pub mod token;
-pub use token::Token;
+pub use token::{PersonalAccess, Token, TokenCollectionExt};
 
 pub mod root_directory;
-pub use root_directory::{RootDirectory, RootDirectoryCollectionExt};
\ No newline at end of file
+pub use root_directory::{RootDirectory, RootDirectoryCollectionExt};
+
+pub mod upload;
+pub use upload::UploadSession;
+
+pub mod upload_target;
+pub use upload_target::{PublicUploadTarget, UploadTarget, UploadTargetInfo};
+
+pub mod invite;
+pub use invite::{Invite, PublicInvite};
+
+pub mod share;
+pub use share::{PublicShare, Share, ShareInfo};
+
+pub mod oidc_login;
+pub use oidc_login::OidcLogin;
+
+pub mod group;
+pub use group::{Group, GroupCollectionExt};
+
+pub mod login_throttle;
+pub use login_throttle::{LoginThrottle, LoginThrottleTreeExt, ThrottleSubject};
+
+pub mod totp;
+pub use totp::TotpSettings;
+
+pub mod login_challenge;
+pub use login_challenge::LoginChallenge;
+
+pub mod passkey;
+pub use passkey::{CeremonyState, PasskeyCeremony, StoredPasskey};
+
+pub mod trash;
+pub use trash::TrashItem;
+
+pub mod job;
+pub use job::{Job, JobOperation, JobProgress, JobStatus};
+
+pub mod event;
+pub use event::{Event, EventPayload, ShareAccess};
@@ -0,0 +1,65 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine as _;
+use chrono::{DateTime, TimeDelta, Utc};
+use getset::CloneGetters;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::models::MetaRecord;
+
+/// Time allowed between starting an OIDC login and completing it
+pub const LOGIN_LIFETIME: TimeDelta = TimeDelta::minutes(10);
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Pending OIDC authorization-code login, keyed by its `state` parameter
+#[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
+#[getset(get_clone = "pub")]
+pub struct OidcLogin {
+    state: String,
+    nonce: String,
+
+    /// PKCE code verifier, only ever sent to the token endpoint
+    verifier: String,
+    created: DateTime<Utc>,
+}
+
+impl MetaRecord for OidcLogin {
+    fn tree() -> &'static str {
+        "oidc_logins"
+    }
+
+    fn record_id(&self) -> String {
+        self.state()
+    }
+}
+
+impl OidcLogin {
+    pub fn new() -> Self {
+        Self {
+            state: random_token(),
+            nonce: random_token(),
+            verifier: random_token(),
+            created: Utc::now(),
+        }
+    }
+
+    /// PKCE `S256` code challenge derived from the verifier
+    pub fn challenge(&self) -> String {
+        base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(self.verifier.as_bytes()))
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.created + LOGIN_LIFETIME <= Utc::now()
+    }
+}
+
+impl Default for OidcLogin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
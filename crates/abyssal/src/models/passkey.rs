@@ -0,0 +1,106 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine as _;
+use chrono::{DateTime, TimeDelta, Utc};
+use getset::{CloneGetters, WithSetters};
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::{
+    AuthenticationResult, CredentialID, Passkey, PasskeyAuthentication, PasskeyRegistration,
+};
+
+use crate::{models::MetaRecord, types::Uuid};
+
+/// Time allowed to complete a registration/authentication ceremony
+const CEREMONY_LIFETIME: TimeDelta = TimeDelta::minutes(5);
+
+/// WebAuthn credential registered to a local user
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct StoredPasskey {
+    /// Base64url-encoded credential ID
+    id: String,
+    name: String,
+    created: DateTime<Utc>,
+
+    #[serde(default)]
+    last_used: Option<DateTime<Utc>>,
+
+    #[schemars(with = "serde_json::Value")]
+    passkey: Passkey,
+}
+
+impl StoredPasskey {
+    pub fn new(name: impl Into<String>, passkey: Passkey) -> Self {
+        Self {
+            id: Self::credential_id(passkey.cred_id()),
+            name: name.into(),
+            created: Utc::now(),
+            last_used: None,
+            passkey,
+        }
+    }
+
+    pub fn credential_id(id: &CredentialID) -> String {
+        base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(id.as_ref())
+    }
+
+    /// Records a successful authentication (updating the credential's signature counter)
+    pub fn with_authentication(mut self, result: &AuthenticationResult) -> Self {
+        let _ = self.passkey.update_credential(result);
+        self.last_used = Some(Utc::now());
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+pub enum CeremonyState {
+    Registration {
+        /// Name to give the passkey once registered
+        name: String,
+        state: PasskeyRegistration,
+    },
+    Authentication {
+        state: PasskeyAuthentication,
+
+        /// Login challenge this authentication completes, if used as a second factor
+        challenge: Option<String>,
+    },
+}
+
+/// In-progress WebAuthn ceremony, keyed by a random ID handed to the client
+#[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
+#[getset(get_clone = "pub")]
+pub struct PasskeyCeremony {
+    id: String,
+    user: Uuid,
+    created: DateTime<Utc>,
+    state: CeremonyState,
+}
+
+impl MetaRecord for PasskeyCeremony {
+    fn tree() -> &'static str {
+        "webauthn_ceremonies"
+    }
+
+    fn record_id(&self) -> String {
+        self.id()
+    }
+}
+
+impl PasskeyCeremony {
+    pub fn new(user: impl Into<Uuid>, state: CeremonyState) -> Self {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        Self {
+            id: base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(bytes),
+            user: user.into(),
+            created: Utc::now(),
+            state,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.created + CEREMONY_LIFETIME <= Utc::now()
+    }
+}
@@ -5,7 +5,11 @@ use getset::{CloneGetters, WithSetters};
 use rocket_okapi::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::{models::Model, types::Uuid, util::Collection};
+use crate::{
+    models::Model,
+    types::{Config, Uuid, filesystem::normalize_relative},
+    util::Collection,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
 #[getset(get_clone = "pub", set_with = "pub")]
@@ -19,6 +23,10 @@ pub struct RootDirectory {
     display_name: Option<String>,
 
     path: PathBuf,
+
+    /// Declared in the configuration file (and therefore read-only through the API)
+    #[serde(default)]
+    managed: bool,
 }
 
 impl Model for RootDirectory {
@@ -40,9 +48,100 @@ impl RootDirectory {
         Self {
             id: Uuid::new(),
             name: name.into(),
-            display_name: display_name.and_then(|v| Some(v.into())),
+            display_name: display_name.map(|v| v.into()),
             path: path.as_ref().to_path_buf(),
+            managed: false,
+        }
+    }
+
+    /// Absolute location of this root on disk (root paths are relative to the configured filesystem)
+    pub fn base_path(&self, config: &Config) -> PathBuf {
+        config
+            .filesystem()
+            .filesystem()
+            .join(self.path.strip_prefix("/").unwrap_or(&self.path))
+    }
+
+    /// Ensures this root points at an existing directory outside of the metadata directory
+    pub async fn validate_path(&self, config: &Config) -> crate::Result<()> {
+        let base = tokio::fs::canonicalize(self.base_path(config))
+            .await
+            .map_err(|_| crate::Error::path_not_found(self.path()))?;
+        let filesystem = tokio::fs::canonicalize(config.filesystem().filesystem()).await?;
+        let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+        if !base.starts_with(filesystem) || base.starts_with(metadata_dir) {
+            Err(crate::Error::path_outside_root(self.path()))
+        } else if !tokio::fs::metadata(&base).await?.is_dir() {
+            Err(crate::Error::not_a_directory(self.path()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Resolves a client-supplied path to an absolute path inside this root.
+    /// The final component is not followed if it is a symlink, but anything
+    /// resolving outside of the root (or into the metadata directory) is rejected.
+    pub async fn resolve(&self, config: &Config, path: impl AsRef<Path>) -> crate::Result<PathBuf> {
+        let relative = normalize_relative(path.as_ref())?;
+        let base = tokio::fs::canonicalize(self.base_path(config))
+            .await
+            .map_err(|_| crate::Error::path_not_found("/"))?;
+        let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+        let contained = |candidate: &Path| {
+            candidate.starts_with(&base) && !candidate.starts_with(&metadata_dir)
+        };
+
+        let Some(name) = relative.file_name() else {
+            return Ok(base);
+        };
+
+        let parent = tokio::fs::canonicalize(base.join(relative.parent().unwrap_or(Path::new(""))))
+            .await
+            .map_err(|_| crate::Error::path_not_found(path.as_ref()))?;
+        if !contained(&parent) {
+            return Err(crate::Error::path_outside_root(path.as_ref()));
+        }
+
+        let target = parent.join(name);
+        if let Ok(resolved) = tokio::fs::canonicalize(&target).await
+            && !contained(&resolved)
+        {
+            return Err(crate::Error::path_outside_root(path.as_ref()));
+        }
+
+        Ok(target)
+    }
+
+    /// Root-relative paths an `absolute` path returned by [`RootDirectory::resolve`] actually
+    /// refers to once symlinks are followed: the entry itself, and its target if it exists.
+    /// Scopes have to be checked against these, since symlinks may lead out of them.
+    pub async fn canonical_relative(
+        &self,
+        config: &Config,
+        absolute: impl AsRef<Path>,
+    ) -> crate::Result<Vec<PathBuf>> {
+        let absolute = absolute.as_ref();
+        let base = tokio::fs::canonicalize(self.base_path(config))
+            .await
+            .map_err(|_| crate::Error::path_not_found("/"))?;
+        let mut relative = Vec::with_capacity(2);
+        for candidate in [
+            Some(absolute.to_path_buf()),
+            tokio::fs::canonicalize(absolute).await.ok(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let path = candidate
+                .strip_prefix(&base)
+                .map_err(|_| crate::Error::path_outside_root(absolute))?
+                .to_path_buf();
+            if !relative.contains(&path) {
+                relative.push(path);
+            }
         }
+
+        Ok(relative)
     }
 }
 
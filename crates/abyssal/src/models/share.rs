@@ -0,0 +1,155 @@
+use chrono::{DateTime, Utc};
+use getset::{CloneGetters, WithSetters};
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::Model,
+    types::{EntryKind, Uuid},
+    util::PasswordProtected,
+};
+
+/// Public, read-only link to a file or folder within a root
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct Share {
+    #[serde(default)]
+    id: Uuid,
+
+    root: Uuid,
+
+    /// Shared file or folder, relative to the root
+    path: String,
+
+    /// What `path` pointed to when the share was created
+    kind: EntryKind,
+    owner: Uuid,
+
+    #[serde(default)]
+    name: Option<String>,
+    created: DateTime<Utc>,
+
+    #[serde(default)]
+    expires: Option<DateTime<Utc>>,
+
+    /// Hashed password required to access the share
+    #[serde(default)]
+    password: Option<String>,
+
+    /// Maximum number of file downloads allowed through this share
+    #[serde(default)]
+    max_downloads: Option<u64>,
+
+    #[serde(default)]
+    downloads: u64,
+}
+
+impl Model for Share {
+    fn collection() -> &'static str {
+        "resources.shares"
+    }
+
+    fn model_id(&self) -> Uuid {
+        self.id()
+    }
+}
+
+impl PasswordProtected for Share {
+    fn password_hash(&self) -> Option<String> {
+        self.password()
+    }
+
+    fn with_password_hash(self, hash: Option<String>) -> Self {
+        self.with_password(hash)
+    }
+}
+
+impl Share {
+    pub fn new(
+        root: impl Into<Uuid>,
+        path: impl Into<String>,
+        kind: EntryKind,
+        owner: impl Into<Uuid>,
+    ) -> Self {
+        Self {
+            id: Uuid::new(),
+            root: root.into(),
+            path: path.into(),
+            kind,
+            owner: owner.into(),
+            name: None,
+            created: Utc::now(),
+            expires: None,
+            password: None,
+            max_downloads: None,
+            downloads: 0,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= Utc::now())
+    }
+
+    pub fn remaining_downloads(&self) -> Option<u64> {
+        self.max_downloads
+            .map(|max| max.saturating_sub(self.downloads))
+    }
+}
+
+/// Owner-facing view of a [`Share`]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct ShareInfo {
+    pub id: Uuid,
+    pub root: Uuid,
+    pub path: String,
+    pub kind: EntryKind,
+    pub owner: Uuid,
+    pub name: Option<String>,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub has_password: bool,
+    pub max_downloads: Option<u64>,
+    pub downloads: u64,
+}
+
+impl From<Share> for ShareInfo {
+    fn from(value: Share) -> Self {
+        Self {
+            id: value.id(),
+            root: value.root(),
+            path: value.path(),
+            kind: value.kind(),
+            owner: value.owner(),
+            name: value.name(),
+            created: value.created(),
+            expires: value.expires(),
+            has_password: value.password().is_some(),
+            max_downloads: value.max_downloads(),
+            downloads: value.downloads(),
+        }
+    }
+}
+
+/// Anonymous view of a [`Share`]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct PublicShare {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub kind: EntryKind,
+    pub expires: Option<DateTime<Utc>>,
+    pub requires_password: bool,
+    pub remaining_downloads: Option<u64>,
+}
+
+impl From<Share> for PublicShare {
+    fn from(value: Share) -> Self {
+        Self {
+            id: value.id(),
+            name: value.name(),
+            kind: value.kind(),
+            expires: value.expires(),
+            requires_password: value.password().is_some(),
+            remaining_downloads: value.remaining_downloads(),
+        }
+    }
+}
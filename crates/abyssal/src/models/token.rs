@@ -1,12 +1,29 @@
-use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+use base64::Engine as _;
+use bson::{Document, doc};
+use chrono::{DateTime, TimeDelta, Utc};
 use getset::CloneGetters;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::{
-    models::{Model, User},
-    types::Uuid,
+    models::{EventPayload, Model, User},
+    types::{PermissionSet, Uuid, config::AuthConfig},
+    util::{Collection, EventBus},
 };
 
+/// Settings of a personal access token: a named, explicitly expiring token with narrowed permissions
+#[derive(Serialize, Deserialize, Clone, Debug, rocket_okapi::JsonSchema, CloneGetters)]
+#[getset(get_clone = "pub")]
+pub struct PersonalAccess {
+    name: String,
+    expires: DateTime<Utc>,
+
+    /// Permissions this token is limited to (intersected with its user's own permissions)
+    permissions: PermissionSet,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, rocket_okapi::JsonSchema, CloneGetters)]
 #[getset(get_clone = "pub")]
 pub struct Token {
@@ -16,6 +33,10 @@ pub struct Token {
     user: Uuid,
     created: DateTime<Utc>,
     refreshed: DateTime<Utc>,
+
+    /// Set for personal access tokens, which aren't subject to session lifetimes
+    #[serde(default)]
+    personal: Option<PersonalAccess>,
 }
 
 impl Model for Token {
@@ -35,9 +56,30 @@ impl Token {
             user: user.into(),
             created: Utc::now(),
             refreshed: Utc::now(),
+            personal: None,
         }
     }
 
+    pub fn new_personal(
+        user: impl Into<Uuid>,
+        name: impl Into<String>,
+        expires: DateTime<Utc>,
+        permissions: PermissionSet,
+    ) -> Self {
+        Self {
+            personal: Some(PersonalAccess {
+                name: name.into(),
+                expires,
+                permissions,
+            }),
+            ..Self::new(user)
+        }
+    }
+
+    pub fn is_personal(&self) -> bool {
+        self.personal.is_some()
+    }
+
     pub fn refresh_token(mut self) -> Self {
         self.refreshed = Utc::now();
         self
@@ -49,4 +91,68 @@ impl Token {
     ) -> crate::Result<Option<User>> {
         collection.get(self.user()).await
     }
+
+    /// Identifies this token's session without revealing the token itself
+    pub fn session_id(&self) -> String {
+        base64::prelude::BASE64_URL_SAFE_NO_PAD
+            .encode(&Sha256::digest(self.id.to_string().as_bytes())[..16])
+    }
+
+    /// CSRF token of cookie sessions, which only the holder of this token can derive
+    pub fn csrf_token(&self) -> String {
+        base64::prelude::BASE64_URL_SAFE_NO_PAD
+            .encode(Sha256::digest(format!("csrf:{}", self.id).as_bytes()))
+    }
+
+    /// When this token expires (for sessions, if it isn't used again before then)
+    pub fn expires(&self, config: &AuthConfig) -> Option<DateTime<Utc>> {
+        if let Some(personal) = &self.personal {
+            return Some(personal.expires);
+        }
+
+        let lifetime = (config.session_lifetime() > 0)
+            .then(|| self.created + TimeDelta::seconds(config.session_lifetime() as i64));
+        let idle = (config.session_idle_timeout() > 0)
+            .then(|| self.refreshed + TimeDelta::seconds(config.session_idle_timeout() as i64));
+        match (lifetime, idle) {
+            (Some(lifetime), Some(idle)) => Some(lifetime.min(idle)),
+            (lifetime, idle) => lifetime.or(idle),
+        }
+    }
+
+    pub fn is_expired(&self, config: &AuthConfig) -> bool {
+        self.expires(config)
+            .is_some_and(|expires| expires <= Utc::now())
+    }
+}
+
+#[rocket::async_trait]
+pub trait TokenCollectionExt {
+    /// Deletes the tokens matching `filter`, letting their users know which sessions were revoked
+    async fn revoke(&self, filter: Document, events: &EventBus) -> crate::Result<()>;
+}
+
+#[rocket::async_trait]
+impl TokenCollectionExt for Collection<Token> {
+    async fn revoke(&self, filter: Document, events: &EventBus) -> crate::Result<()> {
+        let mut cursor = self.find(filter).await?;
+        let mut ids = Vec::new();
+        let mut revoked = HashMap::<Uuid, Vec<String>>::new();
+        while cursor.advance().await? {
+            let token = cursor.deserialize_current()?;
+            revoked
+                .entry(token.user())
+                .or_default()
+                .push(token.session_id());
+            ids.push(token.id());
+        }
+
+        if !ids.is_empty() {
+            let _ = self.delete_many(doc! {"id": {"$in": ids}}).await?;
+        }
+        for (user, sessions) in revoked {
+            events.publish(user, EventPayload::SessionsRevoked { sessions });
+        }
+        Ok(())
+    }
 }
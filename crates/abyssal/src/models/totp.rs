@@ -0,0 +1,198 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use getset::{CloneGetters, WithSetters};
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::models::User;
+
+/// Name shown for accounts in authenticator apps
+const TOTP_ISSUER: &str = "Abyssal";
+
+/// Number of recovery codes generated at a time
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Unambiguous characters recovery codes are made of
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// TOTP (RFC 6238) second factor of a local user
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct TotpSettings {
+    /// Base32-encoded shared secret
+    secret: String,
+
+    /// Unconfirmed secrets are pending enrollment, and not yet required to log in
+    confirmed: bool,
+    created: DateTime<Utc>,
+
+    /// Time step of the last accepted code, so codes can't be replayed
+    #[serde(default)]
+    last_step: Option<u64>,
+
+    /// Argon2 hashes of the unused recovery codes
+    #[serde(default)]
+    recovery_codes: Vec<String>,
+}
+
+impl TotpSettings {
+    pub fn generate() -> Self {
+        let mut secret = [0u8; 20];
+        OsRng.fill_bytes(&mut secret);
+        Self {
+            secret: Secret::Raw(secret.to_vec()).to_encoded().to_string(),
+            confirmed: false,
+            created: Utc::now(),
+            last_step: None,
+            recovery_codes: Vec::new(),
+        }
+    }
+
+    fn totp(&self, account: impl Into<String>) -> crate::Result<TOTP> {
+        let secret = Secret::Encoded(self.secret.clone())
+            .to_bytes()
+            .map_err(|e| anyhow::anyhow!("invalid TOTP secret: {e:?}"))?;
+        Ok(TOTP::new_unchecked(
+            Algorithm::SHA1,
+            6,
+            1,
+            30,
+            secret,
+            Some(TOTP_ISSUER.to_string()),
+            account.into().replace(':', "_"),
+        ))
+    }
+
+    /// `otpauth://` URI to render as a QR code for authenticator apps
+    pub fn provisioning_uri(&self, account: impl Into<String>) -> crate::Result<String> {
+        Ok(self.totp(account)?.get_url())
+    }
+
+    /// Checks `code` against the current time step (± one step of skew).
+    /// Returns the updated settings if it is valid and hasn't been used before.
+    pub fn verify_code(&self, code: impl AsRef<str>) -> crate::Result<Option<Self>> {
+        self.verify_code_at(code, Utc::now().timestamp().max(0) as u64)
+    }
+
+    /// [`TotpSettings::verify_code`] at `now` (in seconds since the Unix epoch)
+    fn verify_code_at(&self, code: impl AsRef<str>, now: u64) -> crate::Result<Option<Self>> {
+        let totp = self.totp("")?;
+        let current = now / totp.step;
+        let code = code.as_ref().trim();
+        for step in current.saturating_sub(1)..=current + 1 {
+            if self.last_step.is_some_and(|last| step <= last) {
+                continue;
+            }
+            if totp.generate(step * totp.step) == code {
+                return Ok(Some(Self {
+                    last_step: Some(step),
+                    ..self.clone()
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Replaces the recovery codes, returning the new (unhashed) codes
+    pub fn regenerate_recovery_codes(self) -> crate::Result<(Self, Vec<String>)> {
+        let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        let mut hashed = Vec::with_capacity(RECOVERY_CODE_COUNT);
+        for _ in 0..RECOVERY_CODE_COUNT {
+            let mut bytes = [0u8; 10];
+            OsRng.fill_bytes(&mut bytes);
+            let code = bytes
+                .iter()
+                .map(|b| RECOVERY_CODE_ALPHABET[*b as usize % RECOVERY_CODE_ALPHABET.len()] as char)
+                .collect::<String>();
+            hashed.push(User::hash_value(code.clone())?);
+            codes.push(code);
+        }
+
+        Ok((self.with_recovery_codes(hashed), codes))
+    }
+
+    /// Consumes a recovery code, returning the updated settings if it was valid
+    pub fn use_recovery_code(&self, code: impl AsRef<str>) -> crate::Result<Option<Self>> {
+        let code = code.as_ref().trim().to_lowercase().replace('-', "");
+        for (index, hashed) in self.recovery_codes.iter().enumerate() {
+            if User::verify_value(code.clone(), hashed.clone())? {
+                let mut remaining = self.recovery_codes.clone();
+                remaining.remove(index);
+                return Ok(Some(self.clone().with_recovery_codes(remaining)));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 6238 test secret (`12345678901234567890`), whose SHA-1 codes at 59 and
+    /// 1111111109 seconds are `94287082` and `07081804` (truncated to 6 digits here)
+    fn settings() -> TotpSettings {
+        TotpSettings {
+            secret: String::from("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"),
+            confirmed: true,
+            created: Utc::now(),
+            last_step: None,
+            recovery_codes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn accepts_the_current_code() {
+        let verified = settings().verify_code_at("287082", 59).unwrap().unwrap();
+        assert_eq!(verified.last_step(), Some(1));
+
+        let verified = settings().verify_code_at(" 081804 ", 1111111109).unwrap();
+        assert_eq!(verified.unwrap().last_step(), Some(1111111109 / 30));
+
+        assert!(settings().verify_code_at("287083", 59).unwrap().is_none());
+        assert!(settings().verify_code_at("081804", 59).unwrap().is_none());
+    }
+
+    #[test]
+    fn allows_one_step_of_drift() {
+        // The code of step 1 (30..60s), checked from the steps around it
+        for now in [0, 29, 30, 59, 60, 89] {
+            assert!(
+                settings().verify_code_at("287082", now).unwrap().is_some(),
+                "rejected at {now}s"
+            );
+        }
+        for now in [90, 120] {
+            assert!(
+                settings().verify_code_at("287082", now).unwrap().is_none(),
+                "accepted at {now}s"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_replayed_and_older_codes() {
+        let verified = settings().verify_code_at("287082", 59).unwrap().unwrap();
+        assert!(verified.verify_code_at("287082", 59).unwrap().is_none());
+        assert!(verified.verify_code_at("287082", 70).unwrap().is_none());
+
+        // A code from before the last accepted one, still within the allowed drift
+        let totp = settings().totp("").unwrap();
+        let previous = totp.generate(0);
+        let verified = settings()
+            .verify_code_at(totp.generate(60), 59)
+            .unwrap()
+            .unwrap();
+        assert_eq!(verified.last_step(), Some(2));
+        assert!(verified.verify_code_at(previous, 59).unwrap().is_none());
+        assert!(
+            verified
+                .verify_code_at(totp.generate(90), 75)
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,418 @@
+use std::{future::Future, path::PathBuf};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use getset::CloneGetters;
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::MetaRecord,
+    types::{Config, DirectoryEntry, EntryKind, Uuid},
+    util::{
+        MetaTree, RootPath,
+        fs_ops::{move_entry, remove_entry, staging_path},
+    },
+};
+
+/// Deleted file or directory, kept in its root's trash until restored or purged
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters)]
+#[getset(get_clone = "pub")]
+pub struct TrashItem {
+    #[serde(default)]
+    id: Uuid,
+
+    root: Uuid,
+
+    /// Path the entry was deleted from, relative to its root
+    path: String,
+    kind: EntryKind,
+
+    /// Size of the entry itself (not including the contents of directories)
+    size: u64,
+
+    /// User who deleted the entry
+    deleted_by: Uuid,
+    deleted: DateTime<Utc>,
+}
+
+impl MetaRecord for TrashItem {
+    fn tree() -> &'static str {
+        "trash"
+    }
+
+    fn record_id(&self) -> String {
+        self.id.to_string()
+    }
+}
+
+impl TrashItem {
+    pub fn new(
+        root: impl Into<Uuid>,
+        path: impl Into<String>,
+        kind: EntryKind,
+        size: u64,
+        deleted_by: impl Into<Uuid>,
+    ) -> Self {
+        Self {
+            id: Uuid::new(),
+            root: root.into(),
+            path: path.into(),
+            kind,
+            size,
+            deleted_by: deleted_by.into(),
+            deleted: Utc::now(),
+        }
+    }
+
+    /// Directory holding the trashed entries of `root`
+    pub fn trash_dir(config: &Config, root: &Uuid) -> PathBuf {
+        config
+            .filesystem()
+            .metadata_dir()
+            .join("trash")
+            .join(root.to_string())
+    }
+
+    /// Location of the trashed entry itself
+    pub fn location(&self, config: &Config) -> PathBuf {
+        Self::trash_dir(config, &self.root).join(self.id.to_string())
+    }
+
+    /// When this item will be purged, if the trash has a retention period
+    pub fn expires(&self, config: &Config) -> Option<DateTime<Utc>> {
+        match config.filesystem().trash().retention() {
+            0 => None,
+            retention => Some(self.deleted + TimeDelta::seconds(retention as i64)),
+        }
+    }
+
+    pub fn is_expired(&self, config: &Config) -> bool {
+        self.expires(config).is_some_and(|at| at <= Utc::now())
+    }
+
+    /// Moves the entry at `path` into the trash, recording it in `trash` first so
+    /// nothing ends up in the trash without a record
+    pub async fn trash(
+        config: &Config,
+        trash: &MetaTree<TrashItem>,
+        root: &Uuid,
+        path: &RootPath,
+        deleted_by: impl Into<Uuid>,
+    ) -> crate::Result<Self> {
+        let entry = DirectoryEntry::read(&path.absolute, &path.relative)
+            .await
+            .map_err(|_| crate::Error::path_not_found(path.display()))?;
+        let item = Self::new(root.clone(), entry.path, entry.kind, entry.size, deleted_by);
+        let _ = trash.save(item.clone())?;
+
+        let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+        let moved = match tokio::fs::create_dir_all(Self::trash_dir(config, root)).await {
+            Ok(()) => {
+                move_entry(
+                    &path.absolute,
+                    item.location(config),
+                    metadata_dir,
+                    path.display(),
+                )
+                .await
+            }
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = moved {
+            let _ = trash.delete(item.record_id())?;
+            return Err(err);
+        }
+
+        Ok(item)
+    }
+
+    /// Puts new content at `target`, where an entry already exists, by awaiting `place`.
+    /// The existing entry is set aside meanwhile (and put back if `place` fails), then moved
+    /// into the trash of `root` once it has been replaced, or deleted if the trash is disabled.
+    pub async fn replace(
+        config: &Config,
+        trash: &MetaTree<TrashItem>,
+        root: &Uuid,
+        target: &RootPath,
+        deleted_by: impl Into<Uuid>,
+        place: impl Future<Output = crate::Result<()>>,
+    ) -> crate::Result<()> {
+        let aside = RootPath {
+            relative: target.relative.clone(),
+            absolute: staging_path(&target.absolute),
+        };
+        tokio::fs::rename(&target.absolute, &aside.absolute)
+            .await
+            .map_err(|_| crate::Error::path_not_found(target.display()))?;
+        if let Err(err) = place.await {
+            let _ = tokio::fs::rename(&aside.absolute, &target.absolute).await;
+            return Err(err);
+        }
+
+        if config.filesystem().trash().enabled() {
+            Self::trash(config, trash, root, &aside, deleted_by)
+                .await
+                .map(|_| ())
+        } else {
+            remove_entry(&aside.absolute, true, target.display()).await
+        }
+    }
+
+    /// Moves the trashed entry back to `target` and forgets it. An existing entry at `target`
+    /// is only replaced with `replace`, in which case it is moved into the trash in turn.
+    pub async fn restore(
+        &self,
+        config: &Config,
+        trash: &MetaTree<TrashItem>,
+        target: &RootPath,
+        replace: bool,
+        restored_by: impl Into<Uuid>,
+    ) -> crate::Result<()> {
+        let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+        let restored = move_entry(
+            self.location(config),
+            &target.absolute,
+            metadata_dir,
+            target.display(),
+        );
+        if replace {
+            Self::replace(config, trash, &self.root, target, restored_by, restored).await?;
+        } else {
+            restored.await?;
+        }
+
+        let _ = trash.delete(self.record_id())?;
+        Ok(())
+    }
+
+    /// Deletes the trashed entry and its record
+    pub async fn purge(&self, config: &Config, trash: &MetaTree<TrashItem>) -> crate::Result<()> {
+        match remove_entry(self.location(config), true, &self.path).await {
+            Ok(()) | Err(crate::Error::PathNotFound(_)) => {}
+            Err(err) => return Err(err),
+        }
+        let _ = trash.delete(self.record_id())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Root directory `data` in a temporary filesystem, with its own trash database
+    struct Fixture {
+        base: PathBuf,
+        config: Config,
+        trash: MetaTree<TrashItem>,
+        root: Uuid,
+        user: Uuid,
+    }
+
+    impl Fixture {
+        async fn new(trash_enabled: bool) -> Self {
+            let base = std::env::temp_dir().join(format!("abyssal-trash-{}", Uuid::new()));
+            tokio::fs::create_dir_all(base.join(".abyssal"))
+                .await
+                .unwrap();
+            tokio::fs::create_dir_all(base.join("data")).await.unwrap();
+            let config = serde_json::from_value(json!({
+                "filesystem": {"filesystem": base, "trash": {"enabled": trash_enabled}}
+            }))
+            .unwrap();
+            let db = sled::Config::new().temporary(true).open().unwrap();
+            Self {
+                base,
+                config,
+                trash: MetaTree::new(&db).unwrap(),
+                root: Uuid::new(),
+                user: Uuid::new(),
+            }
+        }
+
+        fn path(&self, relative: impl AsRef<Path>) -> RootPath {
+            RootPath {
+                relative: relative.as_ref().to_path_buf(),
+                absolute: self.base.join("data").join(relative),
+            }
+        }
+
+        async fn write(&self, relative: &str, contents: &str) -> RootPath {
+            let path = self.path(relative);
+            tokio::fs::write(&path.absolute, contents).await.unwrap();
+            path
+        }
+
+        async fn read(&self, relative: &str) -> String {
+            tokio::fs::read_to_string(self.path(relative).absolute)
+                .await
+                .unwrap()
+        }
+
+        async fn trash(&self, path: &RootPath) -> TrashItem {
+            TrashItem::trash(
+                &self.config,
+                &self.trash,
+                &self.root,
+                path,
+                self.user.clone(),
+            )
+            .await
+            .unwrap()
+        }
+
+        /// Names in the root directory, including any entries set aside during a replacement
+        async fn names(&self) -> Vec<String> {
+            let mut names = Vec::new();
+            let mut reader = tokio::fs::read_dir(self.base.join("data")).await.unwrap();
+            while let Some(entry) = reader.next_entry().await.unwrap() {
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+            names.sort();
+            names
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.base);
+        }
+    }
+
+    #[tokio::test]
+    async fn trashes_and_restores_entries() {
+        let fixture = Fixture::new(true).await;
+        let path = fixture.write("report", "contents").await;
+        let item = fixture.trash(&path).await;
+        assert_eq!(item.path(), "/report");
+        assert!(fixture.names().await.is_empty());
+        assert!(
+            tokio::fs::try_exists(item.location(&fixture.config))
+                .await
+                .unwrap()
+        );
+
+        item.restore(
+            &fixture.config,
+            &fixture.trash,
+            &path,
+            false,
+            fixture.user.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fixture.read("report").await, "contents");
+        assert!(fixture.trash.all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn restoring_over_an_entry_requires_replacing_it() {
+        let fixture = Fixture::new(true).await;
+        let path = fixture.write("report", "old").await;
+        let item = fixture.trash(&path).await;
+        fixture.write("report", "new").await;
+
+        let conflict = item
+            .restore(
+                &fixture.config,
+                &fixture.trash,
+                &path,
+                false,
+                fixture.user.clone(),
+            )
+            .await;
+        assert!(matches!(conflict, Err(crate::Error::PathConflict(_))));
+        assert_eq!(fixture.read("report").await, "new");
+        assert!(fixture.trash.get(item.record_id()).unwrap().is_some());
+
+        item.restore(
+            &fixture.config,
+            &fixture.trash,
+            &path,
+            true,
+            fixture.user.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(fixture.read("report").await, "old");
+        assert_eq!(fixture.names().await, ["report"]);
+
+        // The replaced entry took the restored one's place in the trash
+        let trashed = fixture.trash.all().unwrap();
+        assert_eq!(trashed.len(), 1);
+        assert_ne!(trashed[0].id(), item.id());
+        assert_eq!(trashed[0].path(), "/report");
+        let replaced = tokio::fs::read_to_string(trashed[0].location(&fixture.config));
+        assert_eq!(replaced.await.unwrap(), "new");
+    }
+
+    #[tokio::test]
+    async fn replacing_rolls_back_when_placing_fails() {
+        let fixture = Fixture::new(true).await;
+        let target = fixture.write("report", "old").await;
+
+        let failed = TrashItem::replace(
+            &fixture.config,
+            &fixture.trash,
+            &fixture.root,
+            &target,
+            fixture.user.clone(),
+            async { Err(crate::Error::path_conflict("/report")) },
+        )
+        .await;
+        assert!(matches!(failed, Err(crate::Error::PathConflict(_))));
+        assert_eq!(fixture.read("report").await, "old");
+        assert_eq!(fixture.names().await, ["report"]);
+        assert!(fixture.trash.all().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replacing_deletes_without_a_trash() {
+        let fixture = Fixture::new(false).await;
+        let target = fixture.write("report", "old").await;
+
+        TrashItem::replace(
+            &fixture.config,
+            &fixture.trash,
+            &fixture.root,
+            &target,
+            fixture.user.clone(),
+            async { Ok(tokio::fs::write(&target.absolute, "new").await?) },
+        )
+        .await
+        .unwrap();
+        assert_eq!(fixture.read("report").await, "new");
+        assert_eq!(fixture.names().await, ["report"]);
+        assert!(fixture.trash.all().unwrap().is_empty());
+        assert!(
+            !tokio::fs::try_exists(TrashItem::trash_dir(&fixture.config, &fixture.root))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn expires_after_the_retention_period() {
+        let config = |retention: u64| -> Config {
+            serde_json::from_value(json!({"filesystem": {"trash": {"retention": retention}}}))
+                .unwrap()
+        };
+        let deleted_ago = |seconds: i64| TrashItem {
+            deleted: Utc::now() - TimeDelta::seconds(seconds),
+            ..TrashItem::new(Uuid::new(), "/report", EntryKind::File, 0, Uuid::new())
+        };
+
+        let week = config(7 * 24 * 60 * 60);
+        assert!(!deleted_ago(60).is_expired(&week));
+        assert!(!deleted_ago(7 * 24 * 60 * 60 - 60).is_expired(&week));
+        assert!(deleted_ago(7 * 24 * 60 * 60).is_expired(&week));
+
+        let forever = config(0);
+        assert_eq!(deleted_ago(365 * 24 * 60 * 60).expires(&forever), None);
+        assert!(!deleted_ago(365 * 24 * 60 * 60).is_expired(&forever));
+    }
+}
@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use getset::{CloneGetters, WithSetters};
+use rocket::data::ByteUnit;
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::MetaRecord,
+    types::{Config, Uuid},
+    util::MetaTree,
+};
+
+/// In-progress resumable upload, staged in the metadata directory until finalized
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct UploadSession {
+    #[serde(default)]
+    id: Uuid,
+
+    user: Uuid,
+    root: Uuid,
+
+    /// Destination path, relative to the root
+    path: String,
+
+    /// Total size of the file, as declared when the session was created
+    size: u64,
+
+    /// Bytes received so far (the offset of the next chunk)
+    received: u64,
+
+    /// Replace an existing file at `path` when finalizing
+    overwrite: bool,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+}
+
+impl MetaRecord for UploadSession {
+    fn tree() -> &'static str {
+        "uploads"
+    }
+
+    fn record_id(&self) -> String {
+        self.id().to_string()
+    }
+}
+
+impl UploadSession {
+    pub fn new(
+        user: impl Into<Uuid>,
+        root: impl Into<Uuid>,
+        path: impl Into<String>,
+        size: u64,
+        overwrite: bool,
+    ) -> Self {
+        Self {
+            id: Uuid::new(),
+            user: user.into(),
+            root: root.into(),
+            path: path.into(),
+            size,
+            received: 0,
+            overwrite,
+            created: Utc::now(),
+            updated: Utc::now(),
+        }
+    }
+
+    /// Directory holding the partial data of every upload session
+    pub fn staging_dir(config: &Config) -> PathBuf {
+        config.filesystem().metadata_dir().join("uploads")
+    }
+
+    /// Location of this session's partial data
+    pub fn staging_path(&self, config: &Config) -> PathBuf {
+        Self::staging_dir(config).join(format!("{}.part", self.id()))
+    }
+
+    /// Size limit applying to this upload, based on the destination's extension
+    pub fn limit(&self, config: &Config) -> ByteUnit {
+        let limits = config.server().limits();
+        match Path::new(&self.path).extension() {
+            Some(extension) => limits.extension_limit(extension.to_string_lossy()),
+            None => limits.files(),
+        }
+    }
+
+    /// Whether this session has gone without data for longer than the configured expiry
+    pub fn is_expired(&self, config: &Config) -> bool {
+        match config.filesystem().upload_expiry() {
+            0 => false,
+            expiry => self.updated + TimeDelta::seconds(expiry as i64) <= Utc::now(),
+        }
+    }
+
+    /// Deletes this session along with its partial data
+    pub async fn discard(
+        &self,
+        config: &Config,
+        uploads: &MetaTree<UploadSession>,
+    ) -> crate::Result<()> {
+        let _ = tokio::fs::remove_file(self.staging_path(config)).await;
+        let _ = uploads.delete(self.record_id())?;
+        Ok(())
+    }
+
+    pub fn record_chunk(self, length: u64) -> Self {
+        let received = self.received + length;
+        self.with_received(received).with_updated(Utc::now())
+    }
+}
@@ -0,0 +1,185 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use getset::{CloneGetters, WithSetters};
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{models::Model, types::Uuid, util::PasswordProtected};
+
+/// Public drop link allowing anonymous uploads into a directory of a root
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
+#[getset(get_clone = "pub", set_with = "pub")]
+pub struct UploadTarget {
+    #[serde(default)]
+    id: Uuid,
+
+    root: Uuid,
+
+    /// Destination directory, relative to the root
+    path: String,
+    owner: Uuid,
+
+    #[serde(default)]
+    name: Option<String>,
+    created: DateTime<Utc>,
+
+    #[serde(default)]
+    expires: Option<DateTime<Utc>>,
+
+    /// Maximum number of bytes accepted across all uploads
+    #[serde(default)]
+    max_bytes: Option<u64>,
+
+    /// Maximum number of files accepted
+    #[serde(default)]
+    max_files: Option<u64>,
+
+    /// Hashed password required to upload
+    #[serde(default)]
+    password: Option<String>,
+
+    /// Extensions (without the leading `.`) accepted by this target, or `None` to accept any
+    #[serde(default)]
+    allowed_extensions: Option<Vec<String>>,
+
+    #[serde(default)]
+    bytes_received: u64,
+
+    #[serde(default)]
+    files_received: u64,
+}
+
+impl Model for UploadTarget {
+    fn collection() -> &'static str {
+        "resources.upload_targets"
+    }
+
+    fn model_id(&self) -> Uuid {
+        self.id()
+    }
+}
+
+impl PasswordProtected for UploadTarget {
+    fn password_hash(&self) -> Option<String> {
+        self.password()
+    }
+
+    fn with_password_hash(self, hash: Option<String>) -> Self {
+        self.with_password(hash)
+    }
+}
+
+impl UploadTarget {
+    pub fn new(root: impl Into<Uuid>, path: impl Into<String>, owner: impl Into<Uuid>) -> Self {
+        Self {
+            id: Uuid::new(),
+            root: root.into(),
+            path: path.into(),
+            owner: owner.into(),
+            name: None,
+            created: Utc::now(),
+            expires: None,
+            max_bytes: None,
+            max_files: None,
+            password: None,
+            allowed_extensions: None,
+            bytes_received: 0,
+            files_received: 0,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= Utc::now())
+    }
+
+    pub fn remaining_bytes(&self) -> Option<u64> {
+        self.max_bytes
+            .map(|max| max.saturating_sub(self.bytes_received))
+    }
+
+    pub fn remaining_files(&self) -> Option<u64> {
+        self.max_files
+            .map(|max| max.saturating_sub(self.files_received))
+    }
+
+    pub fn allows_extension(&self, name: impl AsRef<Path>) -> bool {
+        match self.allowed_extensions.clone() {
+            None => true,
+            Some(allowed) => {
+                let extension = name
+                    .as_ref()
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                allowed
+                    .iter()
+                    .any(|candidate| candidate.trim_start_matches('.').to_lowercase() == extension)
+            }
+        }
+    }
+}
+
+/// Owner-facing view of an [`UploadTarget`]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct UploadTargetInfo {
+    pub id: Uuid,
+    pub root: Uuid,
+    pub path: String,
+    pub owner: Uuid,
+    pub name: Option<String>,
+    pub created: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub max_bytes: Option<u64>,
+    pub max_files: Option<u64>,
+    pub has_password: bool,
+    pub allowed_extensions: Option<Vec<String>>,
+    pub bytes_received: u64,
+    pub files_received: u64,
+}
+
+impl From<UploadTarget> for UploadTargetInfo {
+    fn from(value: UploadTarget) -> Self {
+        Self {
+            id: value.id(),
+            root: value.root(),
+            path: value.path(),
+            owner: value.owner(),
+            name: value.name(),
+            created: value.created(),
+            expires: value.expires(),
+            max_bytes: value.max_bytes(),
+            max_files: value.max_files(),
+            has_password: value.password().is_some(),
+            allowed_extensions: value.allowed_extensions(),
+            bytes_received: value.bytes_received(),
+            files_received: value.files_received(),
+        }
+    }
+}
+
+/// Anonymous view of an [`UploadTarget`]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct PublicUploadTarget {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub expires: Option<DateTime<Utc>>,
+    pub requires_password: bool,
+    pub allowed_extensions: Option<Vec<String>>,
+    pub remaining_bytes: Option<u64>,
+    pub remaining_files: Option<u64>,
+}
+
+impl From<UploadTarget> for PublicUploadTarget {
+    fn from(value: UploadTarget) -> Self {
+        Self {
+            id: value.id(),
+            name: value.name(),
+            expires: value.expires(),
+            requires_password: value.password().is_some(),
+            allowed_extensions: value.allowed_extensions(),
+            remaining_bytes: value.remaining_bytes(),
+            remaining_files: value.remaining_files(),
+        }
+    }
+}
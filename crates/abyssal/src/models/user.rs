@@ -1,5 +1,3 @@
-use std::str::FromStr;
-
 use argon2::{
     Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
     password_hash::{
@@ -8,12 +6,10 @@ use argon2::{
     },
 };
 use base64::Engine as _;
-use bson::doc;
 use getset::{CloneGetters, WithSetters};
 use okapi::openapi3::{Object, SecurityRequirement, SecurityScheme, SecuritySchemeData};
 use rocket::{
     Request,
-    http::Status,
     request::{self, FromRequest},
 };
 use rocket_okapi::{
@@ -26,9 +22,9 @@ use spire_enum::prelude::{delegate_impl, delegated_enum};
 use strum::Display;
 
 use crate::{
-    models::Model,
+    models::{Model, StoredPasskey, TotpSettings},
     types::{PermissionSet, Uuid},
-    util::Collection,
+    util::AuthContext,
 };
 
 #[derive(
@@ -50,11 +46,29 @@ pub struct LocalUser {
     name: String,
     password: String,
 
+    #[serde(default)]
+    display_name: Option<String>,
+
+    #[serde(default)]
+    email: Option<String>,
+
+    /// Second factor required at login, once confirmed
+    #[serde(default)]
+    totp: Option<TotpSettings>,
+
+    /// WebAuthn credentials, usable to log in directly or as a second factor
+    #[serde(default)]
+    passkeys: Vec<StoredPasskey>,
+
     #[serde(default)]
     groups: Vec<String>,
 
     #[serde(default)]
     permissions: PermissionSet,
+
+    /// Disabled users can't log in or authenticate requests
+    #[serde(default)]
+    disabled: bool,
 }
 
 impl LocalUser {
@@ -63,8 +77,13 @@ impl LocalUser {
             id: Uuid::new(),
             name,
             password,
+            display_name: None,
+            email: None,
+            totp: None,
+            passkeys: Vec::new(),
             groups: Vec::new(),
             permissions: PermissionSet::new(),
+            disabled: false,
         }
     }
 }
@@ -76,6 +95,10 @@ pub struct OidcUser {
     id: Uuid,
     name: String,
 
+    /// Issuer & subject (`sub` claim) identifying this user at the OIDC provider
+    issuer: String,
+    subject: String,
+
     #[serde(default)]
     groups: Vec<String>,
 
@@ -84,6 +107,25 @@ pub struct OidcUser {
 
     #[serde(default)]
     permissions: PermissionSet,
+
+    /// Disabled users can't log in or authenticate requests
+    #[serde(default)]
+    disabled: bool,
+}
+
+impl OidcUser {
+    pub(self) fn new(name: String, issuer: String, subject: String) -> Self {
+        Self {
+            id: Uuid::new(),
+            name,
+            issuer,
+            subject,
+            groups: Vec::new(),
+            oidc_groups: Vec::new(),
+            permissions: PermissionSet::new(),
+            disabled: false,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, CloneGetters, WithSetters)]
@@ -101,6 +143,10 @@ pub struct ApplicationUser {
 
     #[serde(default)]
     permissions: PermissionSet,
+
+    /// Disabled users can't log in or authenticate requests
+    #[serde(default)]
+    disabled: bool,
 }
 
 impl ApplicationUser {
@@ -113,6 +159,7 @@ impl ApplicationUser {
             client_secret,
             groups: Vec::new(),
             permissions: PermissionSet::new(),
+            disabled: false,
         }
     }
 }
@@ -122,10 +169,13 @@ pub trait UserMethods {
     fn name(&self) -> String;
     fn groups(&self) -> Vec<String>;
     fn permissions(&self) -> PermissionSet;
+    fn disabled(&self) -> bool;
 
     fn with_id(self, id: Uuid) -> Self;
     fn with_name(self, name: String) -> Self;
     fn with_groups(self, groups: Vec<String>) -> Self;
+    fn with_permissions(self, permissions: PermissionSet) -> Self;
+    fn with_disabled(self, disabled: bool) -> Self;
 
     fn with_group(self, group: impl Into<String>) -> Self;
 
@@ -147,9 +197,12 @@ impl UserMethods for User {
     fn name(&self) -> String;
     fn groups(&self) -> Vec<String>;
     fn permissions(&self) -> PermissionSet;
+    fn disabled(&self) -> bool;
     fn with_id(self, id: Uuid) -> Self;
     fn with_name(self, name: String) -> Self;
     fn with_groups(self, groups: Vec<String>) -> Self;
+    fn with_permissions(self, permissions: PermissionSet) -> Self;
+    fn with_disabled(self, disabled: bool) -> Self;
 
     fn with_group(self, group: impl Into<String>) -> Self {
         let mut current_groups = self.groups();
@@ -188,7 +241,7 @@ impl User {
         }
     }
 
-    fn hash_value(value: impl Into<String>) -> crate::Result<String> {
+    pub(crate) fn hash_value(value: impl Into<String>) -> crate::Result<String> {
         let salt = SaltString::generate(&mut OsRng);
         let argon2 = Argon2::default();
         Ok(argon2
@@ -196,7 +249,10 @@ impl User {
             .to_string())
     }
 
-    fn verify_value(value: impl Into<String>, hashed: impl Into<String>) -> crate::Result<bool> {
+    pub(crate) fn verify_value(
+        value: impl Into<String>,
+        hashed: impl Into<String>,
+    ) -> crate::Result<bool> {
         let value = value.into();
         let hashed = hashed.into();
         let parsed_hash = PasswordHash::new(hashed.as_str())?;
@@ -217,25 +273,68 @@ impl User {
         Ok(LocalUser::new(username, hashed_password).into())
     }
 
+    pub fn create_oidc(
+        username: impl Into<String>,
+        issuer: impl Into<String>,
+        subject: impl Into<String>,
+        oidc_groups: Vec<String>,
+    ) -> Self {
+        OidcUser::new(username.into(), issuer.into(), subject.into())
+            .with_oidc_groups(oidc_groups)
+            .into()
+    }
+
+    fn generate_client_secret() -> crate::Result<(String, String)> {
+        let mut client_secret_bytes = [0u8; 64];
+        OsRng.fill_bytes(&mut client_secret_bytes);
+
+        let client_secret = base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(client_secret_bytes);
+        let hashed_secret = Self::hash_value(client_secret.clone())?;
+        Ok((client_secret, hashed_secret))
+    }
+
     pub fn create_application(
         name: impl Into<String>,
         owner: impl Into<Uuid>,
     ) -> crate::Result<(Self, String)> {
         let mut client_id_bytes = [0u8; 32];
-        let mut client_secret_bytes = [0u8; 64];
-
         OsRng.fill_bytes(&mut client_id_bytes);
-        OsRng.fill_bytes(&mut client_secret_bytes);
 
         let client_id = base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(client_id_bytes);
-        let client_secret = base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(client_secret_bytes);
-        let hashed_secret = Self::hash_value(client_secret.clone())?;
+        let (client_secret, hashed_secret) = Self::generate_client_secret()?;
         Ok((
             ApplicationUser::new(name.into(), owner.into(), client_id, hashed_secret).into(),
             client_secret,
         ))
     }
 
+    /// Replaces an application's client secret, returning the new (unhashed) secret
+    pub fn rotate_client_secret(self) -> crate::Result<(Self, String)> {
+        match self {
+            User::Application(user) => {
+                let (client_secret, hashed_secret) = Self::generate_client_secret()?;
+                Ok((user.with_client_secret(hashed_secret).into(), client_secret))
+            }
+            _ => Err(crate::Error::invalid_user_type([UserKind::Application])),
+        }
+    }
+
+    /// Confirmed TOTP settings of a local user, if they have two-factor authentication enabled
+    pub fn totp(&self) -> Option<TotpSettings> {
+        match self {
+            User::Local(user) => user.totp().filter(|totp| totp.confirmed()),
+            _ => None,
+        }
+    }
+
+    /// Passkeys registered to a local user
+    pub fn passkeys(&self) -> Vec<StoredPasskey> {
+        match self {
+            User::Local(user) => user.passkeys(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn verify_password(&self, password: impl Into<String>) -> crate::Result<bool> {
         let password = password.into();
         let hashed = match self.clone() {
@@ -267,70 +366,15 @@ impl User {
             ])),
         }
     }
-
-    async fn from_request_inner(req: &Request<'_>) -> crate::Result<Self> {
-        if let Some(authorization) = req
-            .headers()
-            .get_one("Authorization")
-            .map(|v| v.to_string())
-        {
-            match authorization.split_once(" ") {
-                Some(("Token", token)) => {
-                    let tokens = Collection::<crate::models::Token>::from_request(req)
-                        .await
-                        .unwrap();
-                    let users = Collection::<crate::models::User>::from_request(req)
-                        .await
-                        .unwrap();
-                    if let Ok(Some(existing_token)) = tokens.get(Uuid::from_str(token)?).await {
-                        if let Ok(Some(existing_user)) = existing_token.resolve_user(users).await {
-                            let refreshed = existing_token.refresh_token();
-                            let _ = tokens.save(refreshed).await?;
-                            Ok(existing_user)
-                        } else {
-                            let _ = tokens.delete(existing_token.id()).await?;
-                            Err(crate::Error::MissingAuthorization)
-                        }
-                    } else {
-                        Err(crate::Error::MissingAuthorization)
-                    }
-                }
-                Some(("Application", app_auth)) => {
-                    let users = Collection::<crate::models::User>::from_request(req)
-                        .await
-                        .unwrap();
-                    if let Some((client_id, client_secret)) = app_auth.split_once(":") {
-                        if let Ok(Some(existing_user)) =
-                            users.find_one(doc! {"client_id": client_id}).await
-                        {
-                            if existing_user.verify_client_secret(client_secret.to_string())? {
-                                Ok(existing_user)
-                            } else {
-                                Err(crate::Error::MissingAuthorization)
-                            }
-                        } else {
-                            Err(crate::Error::MissingAuthorization)
-                        }
-                    } else {
-                        Err(crate::Error::MissingAuthorization)
-                    }
-                }
-                _ => Err(crate::Error::MissingAuthorization),
-            }
-        } else {
-            Err(crate::Error::MissingAuthorization)
-        }
-    }
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for User {
     type Error = crate::Error;
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        match Self::from_request_inner(req).await {
-            Ok(resolved) => request::Outcome::Success(resolved),
-            Err(err) => request::Outcome::Error((Status::new(err.metadata().status), err)),
-        }
+        AuthContext::from_request(req)
+            .await
+            .map(|context| context.user())
     }
 }
 
@@ -341,7 +385,7 @@ impl<'a> OpenApiFromRequest<'a> for User {
         _required: bool,
     ) -> rocket_okapi::Result<RequestHeaderInput> {
         let security_scheme = SecurityScheme {
-            description: Some("Authorization header containing either `Token <session token>` or `Application <client_id>:<client_secret>".to_owned()),
+            description: Some("Authorization header containing either `Token <session token>` or `Application <client_id>:<client_secret>`, or the `abyssal_session` cookie set on login (unsafe methods then require the `X-CSRF-Token` header to match the `abyssal_csrf` cookie)".to_owned()),
             data: SecuritySchemeData::Http { scheme: "Token | Application".to_string(), bearer_format: Some("Token | Application".to_string()) },
             extensions: Object::default()
         };
@@ -360,18 +404,33 @@ pub struct GenericUser {
     pub id: Uuid,
     pub kind: UserKind,
     pub name: String,
+    pub display_name: Option<String>,
+    pub email: Option<String>,
+
+    /// Whether logging in requires a TOTP code
+    pub totp_enabled: bool,
     pub groups: Vec<String>,
-    pub permissions: PermissionSet
+    pub permissions: PermissionSet,
+    pub disabled: bool
 }
 
 impl From<User> for GenericUser {
     fn from(value: User) -> Self {
+        let (display_name, email) = match &value {
+            User::Local(user) => (user.display_name(), user.email()),
+            _ => (None, None),
+        };
+        let totp_enabled = value.totp().is_some();
         Self {
             id: value.id(),
             kind: value.kind(),
             name: value.name(),
+            display_name,
+            email,
+            totp_enabled,
             groups: value.groups(),
-            permissions: value.permissions()
+            permissions: value.permissions(),
+            disabled: value.disabled()
         }
     }
 }
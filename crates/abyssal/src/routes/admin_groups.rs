@@ -0,0 +1,322 @@
+use bson::doc;
+use rocket::{State, delete, get, patch, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{EventPayload, GenericUser, Group, GroupCollectionExt, User, UserMethods},
+    types::{Permission, Uuid},
+    util::{Collection, EventBus, check_administrator, fs_ops::file_name},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateGroupRequest {
+    pub name: String,
+
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// OIDC groups whose members should belong to this group
+    #[serde(default)]
+    pub oidc_groups: Vec<String>,
+
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UpdateGroupRequest {
+    /// Renaming a group also renames it for all of its members
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub display_name: Option<Option<String>>,
+
+    #[serde(default)]
+    pub oidc_groups: Option<Vec<String>>,
+}
+
+async fn check_name(groups: &Collection<Group>, name: &str) -> crate::Result<String> {
+    let name = file_name(name).map_err(|_| crate::Error::InvalidGroupName(name.to_string()))?;
+    if groups.by_name(&name).await?.is_some() {
+        Err(crate::Error::GroupNameTaken(name))
+    } else {
+        Ok(name)
+    }
+}
+
+async fn load_group(id: Uuid, groups: &Collection<Group>) -> crate::Result<Group> {
+    groups
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::GroupNotFound(id.to_string()))
+}
+
+/// Users belonging to `group`, directly or through their OIDC groups
+async fn members(group: &Group, users: &Collection<User>) -> crate::Result<Vec<User>> {
+    let mut cursor = users
+        .find(doc! {"$or": [
+            {"groups": group.name()},
+            {"kind": "oidc", "oidc_groups": {"$in": group.oidc_groups()}},
+        ]})
+        .sort(doc! {"name": 1})
+        .await?;
+    let mut members = Vec::new();
+    while cursor.advance().await? {
+        members.push(cursor.deserialize_current()?);
+    }
+
+    Ok(members)
+}
+
+/// Lets the members of `group` know their effective permissions changed
+async fn notify_members(
+    group: &Group,
+    users: &Collection<User>,
+    events: &EventBus,
+) -> crate::Result<()> {
+    let members = members(group, users).await?;
+    events.publish_all(
+        members.iter().map(|member| member.id()),
+        EventPayload::PermissionsChanged,
+    );
+    Ok(())
+}
+
+#[openapi(tag = "Administration")]
+#[get("/")]
+async fn list_groups(user: User, groups: Collection<Group>) -> crate::ApiResult<Vec<Group>> {
+    check_administrator(&user)?;
+    let mut cursor = groups.find(doc! {}).sort(doc! {"name": 1}).await?;
+    let mut found = Vec::new();
+    while cursor.advance().await? {
+        found.push(cursor.deserialize_current()?);
+    }
+
+    Ok(Json(found))
+}
+
+#[openapi(tag = "Administration")]
+#[get("/<id>")]
+async fn get_group(id: Uuid, user: User, groups: Collection<Group>) -> crate::ApiResult<Group> {
+    check_administrator(&user)?;
+    Ok(Json(load_group(id, &groups).await?))
+}
+
+#[openapi(tag = "Administration")]
+#[post("/", data = "<body>")]
+async fn create_group(
+    body: Json<CreateGroupRequest>,
+    user: User,
+    groups: Collection<Group>,
+) -> crate::ApiResult<Group> {
+    check_administrator(&user)?;
+    let name = check_name(&groups, &body.name).await?;
+    let group =
+        Group::new(name, body.display_name.clone()).with_oidc_groups(body.oidc_groups.clone());
+    for permission in body.permissions.clone() {
+        group.permissions().set_permission(permission);
+    }
+
+    let _ = groups.save(group.clone()).await?;
+    Ok(Json(group))
+}
+
+#[openapi(tag = "Administration")]
+#[patch("/<id>", data = "<body>")]
+async fn update_group(
+    id: Uuid,
+    body: Json<UpdateGroupRequest>,
+    user: User,
+    groups: Collection<Group>,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<Group> {
+    check_administrator(&user)?;
+    let mut group = load_group(id, &groups).await?;
+
+    if let Some(name) = body.name.clone()
+        && name != group.name()
+    {
+        let name = check_name(&groups, &name).await?;
+        let _ = users
+            .update_many(
+                doc! {"groups": group.name()},
+                doc! {"$set": {"groups.$": &name}},
+            )
+            .await?;
+        group = group.with_name(name);
+    }
+    if let Some(display_name) = body.display_name.clone() {
+        group = group.with_display_name(display_name);
+    }
+
+    // Changing OIDC groups affects both former and new members
+    let mut affected = Vec::new();
+    if let Some(oidc_groups) = body.oidc_groups.clone() {
+        affected = members(&group, &users).await?;
+        group = group.with_oidc_groups(oidc_groups);
+    }
+
+    let _ = groups.save(group.clone()).await?;
+    if body.oidc_groups.is_some() {
+        affected.extend(members(&group, &users).await?);
+        let mut ids = affected
+            .iter()
+            .map(|member| member.id())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids.dedup();
+        events.publish_all(ids, EventPayload::PermissionsChanged);
+    }
+    Ok(Json(group))
+}
+
+/// Deletes a group, removing it from all of its members
+#[openapi(tag = "Administration")]
+#[delete("/<id>")]
+async fn delete_group(
+    id: Uuid,
+    user: User,
+    groups: Collection<Group>,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::Result<()> {
+    check_administrator(&user)?;
+    let group = load_group(id, &groups).await?;
+    let former = members(&group, &users).await?;
+    let _ = users
+        .update_many(
+            doc! {"groups": group.name()},
+            doc! {"$pull": {"groups": group.name()}},
+        )
+        .await?;
+    let _ = groups.delete(group.id()).await?;
+    events.publish_all(
+        former.iter().map(|member| member.id()),
+        EventPayload::PermissionsChanged,
+    );
+    Ok(())
+}
+
+/// Grants a permission to all members, replacing any existing permission of the same kind for the same root
+#[openapi(tag = "Administration")]
+#[post("/<id>/permissions", data = "<permission>")]
+async fn add_group_permission(
+    id: Uuid,
+    permission: Json<Permission>,
+    user: User,
+    groups: Collection<Group>,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<Group> {
+    check_administrator(&user)?;
+    let group = load_group(id, &groups).await?;
+    group.permissions().set_permission(permission.into_inner());
+    let _ = groups.save(group.clone()).await?;
+    notify_members(&group, &users, events).await?;
+    Ok(Json(group))
+}
+
+#[openapi(tag = "Administration")]
+#[post("/<id>/permissions/remove", data = "<permission>")]
+async fn remove_group_permission(
+    id: Uuid,
+    permission: Json<Permission>,
+    user: User,
+    groups: Collection<Group>,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<Group> {
+    check_administrator(&user)?;
+    let group = load_group(id, &groups).await?;
+    group
+        .permissions()
+        .remove_permission(permission.into_inner());
+    let _ = groups.save(group.clone()).await?;
+    notify_members(&group, &users, events).await?;
+    Ok(Json(group))
+}
+
+/// Lists the group's members, including users belonging to it through their OIDC groups
+#[openapi(tag = "Administration")]
+#[get("/<id>/members")]
+async fn list_members(
+    id: Uuid,
+    user: User,
+    groups: Collection<Group>,
+    users: Collection<User>,
+) -> crate::ApiResult<Vec<GenericUser>> {
+    check_administrator(&user)?;
+    let group = load_group(id, &groups).await?;
+    Ok(Json(
+        members(&group, &users)
+            .await?
+            .into_iter()
+            .map(GenericUser::from)
+            .collect(),
+    ))
+}
+
+#[openapi(tag = "Administration")]
+#[post("/<id>/members/<user_id>")]
+async fn add_member(
+    id: Uuid,
+    user_id: Uuid,
+    user: User,
+    groups: Collection<Group>,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    let group = load_group(id, &groups).await?;
+    let mut member = users
+        .get(user_id.clone())
+        .await?
+        .ok_or(crate::Error::UserNotFound(user_id))?;
+    if !member.groups().contains(&group.name()) {
+        member = member.with_group(group.name());
+        let _ = users.save(member.clone()).await?;
+        events.publish(member.id(), EventPayload::PermissionsChanged);
+    }
+
+    Ok(Json(member.into()))
+}
+
+#[openapi(tag = "Administration")]
+#[delete("/<id>/members/<user_id>")]
+async fn remove_member(
+    id: Uuid,
+    user_id: Uuid,
+    user: User,
+    groups: Collection<Group>,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    let group = load_group(id, &groups).await?;
+    let member = users
+        .get(user_id.clone())
+        .await?
+        .ok_or(crate::Error::UserNotFound(user_id))?
+        .without_group(group.name());
+    let _ = users.save(member.clone()).await?;
+    events.publish(member.id(), EventPayload::PermissionsChanged);
+    Ok(Json(member.into()))
+}
+
+export_routes![
+    list_groups,
+    get_group,
+    create_group,
+    update_group,
+    delete_group,
+    add_group_permission,
+    remove_group_permission,
+    list_members,
+    add_member,
+    remove_member
+];
@@ -0,0 +1,486 @@
+use bson::{Document, doc};
+use chrono::{DateTime, Utc};
+use rocket::{State, delete, get, patch, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{
+        EventPayload, GenericUser, Group, GroupCollectionExt, Invite, Job, LoginThrottle,
+        MetaRecord, RootDirectory, Share, ThrottleSubject, Token, TokenCollectionExt,
+        UploadSession, UploadTarget, User, UserKind, UserMethods,
+    },
+    types::{Config, Permission, RootTopLevel, Uuid},
+    util::{Collection, EventBus, MetaTree, check_administrator, fs_ops::file_name},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UserListing {
+    /// Total number of matching users (before pagination)
+    pub total: u64,
+    pub offset: u64,
+    pub users: Vec<GenericUser>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "kind")]
+enum CreateUserRequest {
+    Local {
+        name: String,
+        password: String,
+    },
+
+    /// Pre-provisions an OIDC user, so permissions can be assigned before their first login
+    Oidc {
+        name: String,
+        subject: String,
+
+        /// Defaults to the configured provider's issuer
+        #[serde(default)]
+        issuer: Option<String>,
+    },
+    Application {
+        name: String,
+        owner: Uuid,
+    },
+}
+
+/// The created user, alongside an application's client secret (only ever returned once)
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreatedUser {
+    pub user: GenericUser,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UpdateUserRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Names of the groups to place the user in, all of which must exist
+    #[serde(default)]
+    pub groups: Option<Vec<String>>,
+
+    /// Disabling a user also revokes all of their sessions
+    #[serde(default)]
+    pub disabled: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct ResetPasswordRequest {
+    pub password: String,
+}
+
+/// A username or IP address currently prevented from attempting logins
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct LoginLockout {
+    pub subject: ThrottleSubject,
+    pub failures: u32,
+    pub last_failure: DateTime<Utc>,
+
+    /// Set if the subject is fully locked out, rather than just delayed
+    pub locked_until: Option<DateTime<Utc>>,
+
+    /// Earliest time another login may be attempted
+    pub retry_at: DateTime<Utc>,
+}
+
+/// Administrators may not disable or delete themselves, to avoid locking everyone out
+fn check_not_self(user: &User, target: &User) -> crate::Result<()> {
+    if user.id() == target.id() {
+        Err(crate::Error::Forbidden)
+    } else {
+        Ok(())
+    }
+}
+
+async fn check_name(users: &Collection<User>, name: &str) -> crate::Result<String> {
+    let name = file_name(name).map_err(|_| crate::Error::InvalidUsername(name.to_string()))?;
+    if users.find_one(doc! {"name": &name}).await?.is_some() {
+        Err(crate::Error::UsernameTaken(name))
+    } else {
+        Ok(name)
+    }
+}
+
+async fn load_user(id: Uuid, users: &Collection<User>) -> crate::Result<User> {
+    users
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::UserNotFound(id))
+}
+
+async fn revoke_sessions(
+    user: &User,
+    tokens: &Collection<Token>,
+    events: &EventBus,
+) -> crate::Result<()> {
+    tokens
+        .revoke(doc! {"user": user.id(), "personal": null}, events)
+        .await
+}
+
+fn escape_regex(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|c| match c {
+            '\\' | '.' | '+' | '*' | '?' | '(' | ')' | '|' | '[' | ']' | '{' | '}' | '^' | '$' => {
+                vec!['\\', c]
+            }
+            _ => vec![c],
+        })
+        .collect()
+}
+
+/// Lists users, optionally filtered by kind and a case-insensitive name search
+#[openapi(tag = "Administration")]
+#[get("/?<search>&<kind>&<offset>&<limit>")]
+async fn list_users(
+    search: Option<String>,
+    kind: Option<String>,
+    offset: Option<u64>,
+    limit: Option<i64>,
+    user: User,
+    users: Collection<User>,
+) -> crate::ApiResult<UserListing> {
+    check_administrator(&user)?;
+
+    let mut filter = Document::new();
+    if let Some(search) = search {
+        filter.insert(
+            "name",
+            doc! {"$regex": escape_regex(&search), "$options": "i"},
+        );
+    }
+    if let Some(kind) = kind {
+        filter.insert("kind", kind);
+    }
+
+    let total = users.count_documents(filter.clone()).await?;
+    let offset = offset.unwrap_or(0);
+    let mut find = users.find(filter).sort(doc! {"name": 1}).skip(offset);
+    if let Some(limit) = limit {
+        find = find.limit(limit);
+    }
+
+    let mut cursor = find.await?;
+    let mut found = Vec::new();
+    while cursor.advance().await? {
+        found.push(cursor.deserialize_current()?.into());
+    }
+
+    Ok(Json(UserListing {
+        total,
+        offset,
+        users: found,
+    }))
+}
+
+#[openapi(tag = "Administration")]
+#[get("/<id>")]
+async fn get_user(id: Uuid, user: User, users: Collection<User>) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    Ok(Json(load_user(id, &users).await?.into()))
+}
+
+#[openapi(tag = "Administration")]
+#[post("/", data = "<body>")]
+async fn create_user(
+    body: Json<CreateUserRequest>,
+    user: User,
+    users: Collection<User>,
+    config: &State<Config>,
+) -> crate::ApiResult<CreatedUser> {
+    check_administrator(&user)?;
+
+    let (created, client_secret) = match body.into_inner() {
+        CreateUserRequest::Local { name, password } => {
+            let name = check_name(&users, &name).await?;
+            (User::create_local(name, password)?, None)
+        }
+        CreateUserRequest::Oidc {
+            name,
+            subject,
+            issuer,
+        } => {
+            let issuer = issuer
+                .or_else(|| config.authentication().oidc().map(|oidc| oidc.issuer()))
+                .ok_or(crate::Error::OidcDisabled)?;
+            let name = check_name(&users, &name).await?;
+            (User::create_oidc(name, issuer, subject, Vec::new()), None)
+        }
+        CreateUserRequest::Application { name, owner } => {
+            let name = check_name(&users, &name).await?;
+            let owner = load_user(owner, &users).await?;
+            if owner.kind() == UserKind::Application {
+                return Err(crate::Error::invalid_user_type([
+                    UserKind::Local,
+                    UserKind::Oidc,
+                ]));
+            }
+
+            let (application, secret) = User::create_application(name, owner.id())?;
+            (application, Some(secret))
+        }
+    };
+
+    let _ = users.save(created.clone()).await?;
+    Ok(Json(CreatedUser {
+        user: created.into(),
+        client_secret,
+    }))
+}
+
+/// Home directories are named after their user, so renaming a user who has one would orphan it
+async fn check_renamable(
+    target: &User,
+    groups: &Collection<Group>,
+    roots: &Collection<RootDirectory>,
+    config: &Config,
+) -> crate::Result<()> {
+    let permissions: Vec<Permission> = groups.effective_permissions(target).await?.into();
+    for permission in permissions {
+        if let Permission::RootDirectory {
+            root,
+            top_level: top_level @ RootTopLevel::Home { .. },
+            ..
+        } = permission
+            && let Some(root) = roots.get(root).await?
+            && let Ok(home) = root.resolve(config, top_level.scope(target.name())?).await
+            && tokio::fs::try_exists(home).await?
+        {
+            return Err(crate::Error::UserHasHome(target.name()));
+        }
+    }
+    Ok(())
+}
+
+/// Updates a user. Users with a home directory can't be renamed.
+#[openapi(tag = "Administration")]
+#[patch("/<id>", data = "<body>")]
+#[allow(clippy::too_many_arguments)]
+async fn update_user(
+    id: Uuid,
+    body: Json<UpdateUserRequest>,
+    user: User,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    roots: Collection<RootDirectory>,
+    tokens: Collection<Token>,
+    events: &State<EventBus>,
+    config: &State<Config>,
+) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    let mut target = load_user(id, &users).await?;
+
+    if let Some(name) = body.name.clone()
+        && name != target.name()
+    {
+        let name = check_name(&users, &name).await?;
+        check_renamable(&target, &groups, &roots, config.inner()).await?;
+        target = target.with_name(name);
+    }
+    if let Some(names) = body.groups.clone() {
+        for name in &names {
+            if groups.by_name(name).await?.is_none() {
+                return Err(crate::Error::GroupNotFound(name.clone()));
+            }
+        }
+        target = target.with_groups(names);
+    }
+    if let Some(disabled) = body.disabled {
+        if disabled {
+            check_not_self(&user, &target)?;
+            revoke_sessions(&target, &tokens, events).await?;
+        }
+        target = target.with_disabled(disabled);
+    }
+
+    let _ = users.save(target.clone()).await?;
+    if body.groups.is_some() {
+        events.publish(target.id(), EventPayload::PermissionsChanged);
+    }
+    Ok(Json(target.into()))
+}
+
+/// Deletes a user, along with their sessions, tokens, applications, shares, upload targets,
+/// invites, upload sessions and finished jobs. Users with unfinished jobs can't be deleted.
+#[openapi(tag = "Administration")]
+#[delete("/<id>")]
+#[allow(clippy::too_many_arguments)]
+async fn delete_user(
+    id: Uuid,
+    user: User,
+    users: Collection<User>,
+    tokens: Collection<Token>,
+    shares: Collection<Share>,
+    targets: Collection<UploadTarget>,
+    invites: Collection<Invite>,
+    uploads: MetaTree<UploadSession>,
+    jobs: MetaTree<Job>,
+    events: &State<EventBus>,
+    config: &State<Config>,
+) -> crate::Result<()> {
+    check_administrator(&user)?;
+    let target = load_user(id, &users).await?;
+    check_not_self(&user, &target)?;
+
+    let owned_jobs = jobs
+        .all()?
+        .into_iter()
+        .filter(|job| job.owner() == target.id())
+        .collect::<Vec<_>>();
+    if let Some(job) = owned_jobs.iter().find(|job| !job.status().is_finished()) {
+        return Err(crate::Error::UserHasActiveJobs(job.id()));
+    }
+
+    tokens.revoke(doc! {"user": target.id()}, events).await?;
+    let _ = shares.delete_many(doc! {"owner": target.id()}).await?;
+    let _ = targets.delete_many(doc! {"owner": target.id()}).await?;
+    let _ = invites.delete_many(doc! {"owner": target.id()}).await?;
+    for session in uploads.all()? {
+        if session.user() == target.id() {
+            session.discard(config.inner(), &uploads).await?;
+        }
+    }
+    for job in owned_jobs {
+        let _ = jobs.delete(job.record_id())?;
+    }
+    let _ = users
+        .delete_many(doc! {"kind": "application", "owner": target.id()})
+        .await?;
+    let _ = users.delete(target.id()).await?;
+    Ok(())
+}
+
+/// Sets a local user's password, revoking their sessions
+#[openapi(tag = "Administration")]
+#[post("/<id>/password", data = "<body>")]
+async fn reset_password(
+    id: Uuid,
+    body: Json<ResetPasswordRequest>,
+    user: User,
+    users: Collection<User>,
+    tokens: Collection<Token>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    let target = load_user(id, &users)
+        .await?
+        .with_password(body.password.clone())?;
+    let _ = users.save(target.clone()).await?;
+    revoke_sessions(&target, &tokens, events).await?;
+    Ok(Json(target.into()))
+}
+
+/// Turns off a local user's two-factor authentication, e.g. if they lost their authenticator
+#[openapi(tag = "Administration")]
+#[post("/<id>/totp/reset")]
+async fn reset_totp(
+    id: Uuid,
+    user: User,
+    users: Collection<User>,
+) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    let User::Local(target) = load_user(id, &users).await? else {
+        return Err(crate::Error::invalid_user_type([UserKind::Local]));
+    };
+
+    let target: User = target.with_totp(None).into();
+    let _ = users.save(target.clone()).await?;
+    Ok(Json(target.into()))
+}
+
+/// Grants a permission, replacing any existing permission of the same kind for the same root
+#[openapi(tag = "Administration")]
+#[post("/<id>/permissions", data = "<permission>")]
+async fn add_permission(
+    id: Uuid,
+    permission: Json<Permission>,
+    user: User,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    let target = load_user(id, &users).await?;
+    target.permissions().set_permission(permission.into_inner());
+    let _ = users.save(target.clone()).await?;
+    events.publish(target.id(), EventPayload::PermissionsChanged);
+    Ok(Json(target.into()))
+}
+
+#[openapi(tag = "Administration")]
+#[post("/<id>/permissions/remove", data = "<permission>")]
+async fn remove_permission(
+    id: Uuid,
+    permission: Json<Permission>,
+    user: User,
+    users: Collection<User>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<GenericUser> {
+    check_administrator(&user)?;
+    let target = load_user(id, &users).await?;
+    target
+        .permissions()
+        .remove_permission(permission.into_inner());
+    let _ = users.save(target.clone()).await?;
+    events.publish(target.id(), EventPayload::PermissionsChanged);
+    Ok(Json(target.into()))
+}
+
+/// Lists usernames and IP addresses currently throttled or locked out due to failed logins
+#[openapi(tag = "Administration")]
+#[get("/lockouts")]
+async fn list_lockouts(
+    user: User,
+    throttles: MetaTree<LoginThrottle>,
+    config: &State<Config>,
+) -> crate::ApiResult<Vec<LoginLockout>> {
+    check_administrator(&user)?;
+    let throttle_config = config.authentication().login_throttle();
+    Ok(Json(
+        throttles
+            .all()?
+            .into_iter()
+            .filter_map(|throttle| {
+                throttle
+                    .retry_at(&throttle_config)
+                    .map(|retry_at| LoginLockout {
+                        subject: throttle.subject(),
+                        failures: throttle.failures(),
+                        last_failure: throttle.last_failure(),
+                        locked_until: throttle.locked_until().filter(|_| throttle.is_locked()),
+                        retry_at,
+                    })
+            })
+            .collect(),
+    ))
+}
+
+/// Clears a username's or IP address's failed logins, lifting any lockout
+#[openapi(tag = "Administration")]
+#[post("/lockouts/clear", data = "<subject>")]
+async fn clear_lockout(
+    subject: Json<ThrottleSubject>,
+    user: User,
+    throttles: MetaTree<LoginThrottle>,
+) -> crate::Result<()> {
+    check_administrator(&user)?;
+    let _ = throttles.delete(subject.key())?;
+    Ok(())
+}
+
+export_routes![
+    list_users,
+    get_user,
+    create_user,
+    update_user,
+    delete_user,
+    reset_password,
+    reset_totp,
+    add_permission,
+    remove_permission,
+    list_lockouts,
+    clear_lockout
+];
@@ -0,0 +1,232 @@
+use bson::doc;
+use rocket::{delete, get, patch, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{ApplicationUser, User, UserKind, UserMethods},
+    types::{Permission, PermissionSet, Uuid},
+    util::{AuthContext, Collection},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct ApplicationInfo {
+    pub id: Uuid,
+    pub name: String,
+    pub owner: Uuid,
+    pub client_id: String,
+    pub permissions: PermissionSet,
+}
+
+impl From<ApplicationUser> for ApplicationInfo {
+    fn from(value: ApplicationUser) -> Self {
+        Self {
+            id: value.id(),
+            name: value.name(),
+            owner: value.owner(),
+            client_id: value.client_id(),
+            permissions: value.permissions(),
+        }
+    }
+}
+
+/// Application info alongside its client secret, which is only ever returned once
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct ApplicationCredentials {
+    pub application: ApplicationInfo,
+    pub client_secret: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateApplicationRequest {
+    pub name: String,
+
+    /// Permissions to grant, which must be covered by the owner's own permissions
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UpdateApplicationRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Replaces the application's permissions
+    #[serde(default)]
+    pub permissions: Option<Vec<Permission>>,
+}
+
+/// Builds an application's permission set, ensuring `owner` holds everything it grants
+fn delegated_permissions(
+    owner: &User,
+    name: &str,
+    permissions: Vec<Permission>,
+) -> crate::Result<PermissionSet> {
+    let granted = PermissionSet::new();
+    for permission in permissions {
+        if !owner.permissions().covers(&permission, owner.name(), name) {
+            return Err(crate::Error::Forbidden);
+        }
+        granted.set_permission(permission);
+    }
+
+    Ok(granted)
+}
+
+/// Applications are owned by local/OIDC users, and can only be managed through their sessions
+/// (not by applications or personal access tokens, which could otherwise extend their own access)
+fn session_owner(auth: &AuthContext) -> crate::Result<User> {
+    let _ = auth.session()?;
+    let user = auth.user();
+    match user.kind() {
+        UserKind::Application => Err(crate::Error::invalid_user_type([
+            UserKind::Local,
+            UserKind::Oidc,
+        ])),
+        _ => Ok(user),
+    }
+}
+
+async fn check_name(users: &Collection<User>, name: &str) -> crate::Result<()> {
+    if users.find_one(doc! {"name": name}).await?.is_some() {
+        Err(crate::Error::UsernameTaken(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+async fn load_application(
+    id: Uuid,
+    user: &User,
+    users: &Collection<User>,
+) -> crate::Result<ApplicationUser> {
+    match users.get(id.clone()).await? {
+        Some(User::Application(application)) if application.owner() == user.id() => Ok(application),
+        _ => Err(crate::Error::ApplicationNotFound(id)),
+    }
+}
+
+#[openapi(tag = "Applications")]
+#[get("/")]
+async fn list_applications(
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<Vec<ApplicationInfo>> {
+    let user = session_owner(&auth)?;
+    let mut cursor = users
+        .find(doc! {"kind": "application", "owner": user.id()})
+        .await?;
+    let mut applications = Vec::new();
+    while cursor.advance().await? {
+        if let User::Application(application) = cursor.deserialize_current()? {
+            applications.push(application.into());
+        }
+    }
+
+    Ok(Json(applications))
+}
+
+#[openapi(tag = "Applications")]
+#[get("/<id>")]
+async fn get_application(
+    id: Uuid,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<ApplicationInfo> {
+    let user = session_owner(&auth)?;
+    Ok(Json(load_application(id, &user, &users).await?.into()))
+}
+
+#[openapi(tag = "Applications")]
+#[post("/", data = "<body>")]
+async fn create_application(
+    body: Json<CreateApplicationRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<ApplicationCredentials> {
+    let user = session_owner(&auth)?;
+    check_name(&users, &body.name).await?;
+
+    let permissions = delegated_permissions(&user, &body.name, body.permissions.clone())?;
+    let (created, client_secret) = User::create_application(body.name.clone(), user.id())?;
+    let User::Application(application) = created else {
+        return Err(crate::Error::invalid_user_type([UserKind::Application]));
+    };
+    let application = application.with_permissions(permissions);
+    let _ = users.save(application.clone().into()).await?;
+    Ok(Json(ApplicationCredentials {
+        application: application.into(),
+        client_secret,
+    }))
+}
+
+#[openapi(tag = "Applications")]
+#[patch("/<id>", data = "<body>")]
+async fn update_application(
+    id: Uuid,
+    body: Json<UpdateApplicationRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<ApplicationInfo> {
+    let user = session_owner(&auth)?;
+    let mut application = load_application(id, &user, &users).await?;
+
+    if let Some(name) = body.name.clone()
+        && name != application.name()
+    {
+        check_name(&users, &name).await?;
+        application = application.with_name(name);
+    }
+    if let Some(permissions) = body.permissions.clone() {
+        let permissions = delegated_permissions(&user, &application.name(), permissions)?;
+        application = application.with_permissions(permissions);
+    }
+
+    let _ = users.save(application.clone().into()).await?;
+    Ok(Json(application.into()))
+}
+
+/// Replaces an application's client secret, invalidating the previous one
+#[openapi(tag = "Applications")]
+#[post("/<id>/rotate")]
+async fn rotate_application_secret(
+    id: Uuid,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<ApplicationCredentials> {
+    let user = session_owner(&auth)?;
+    let application = load_application(id, &user, &users).await?;
+    let (rotated, client_secret) = User::from(application).rotate_client_secret()?;
+    let _ = users.save(rotated.clone()).await?;
+    let User::Application(application) = rotated else {
+        return Err(crate::Error::invalid_user_type([UserKind::Application]));
+    };
+
+    Ok(Json(ApplicationCredentials {
+        application: application.into(),
+        client_secret,
+    }))
+}
+
+#[openapi(tag = "Applications")]
+#[delete("/<id>")]
+async fn delete_application(
+    id: Uuid,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::Result<()> {
+    let user = session_owner(&auth)?;
+    let application = load_application(id, &user, &users).await?;
+    let _ = users.delete(application.id()).await?;
+    Ok(())
+}
+
+export_routes![
+    list_applications,
+    get_application,
+    create_application,
+    update_application,
+    rotate_application_secret,
+    delete_application
+];
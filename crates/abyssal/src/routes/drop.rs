@@ -0,0 +1,202 @@
+use std::path::Path;
+
+use bson::doc;
+use rocket::{
+    State,
+    data::{ByteUnit, Data},
+    get, post,
+    serde::json::Json,
+};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{
+        EventPayload, Group, GroupCollectionExt, LoginThrottle, PublicUploadTarget, RootDirectory,
+        ThrottleSubject, UploadSession, UploadTarget, User,
+    },
+    types::{Config, Uuid},
+    util::{
+        AccessPassword, Collection, EditAccess, EventBus, MetaTree, RootAccess,
+        fs_ops::{file_name, move_file, numbered_name},
+    },
+};
+
+/// Upper bound on `name (n).ext` alternatives tried before giving up
+const MAX_RENAME_ATTEMPTS: usize = 1000;
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct DropReceipt {
+    /// Name the file was stored under (may differ from the requested name on conflicts)
+    pub name: String,
+    pub size: u64,
+}
+
+/// Loads a target that is still accepting uploads, checking its password
+async fn open_target(
+    id: Uuid,
+    password: AccessPassword,
+    targets: &Collection<UploadTarget>,
+    throttles: &MetaTree<LoginThrottle>,
+    config: &Config,
+) -> crate::Result<UploadTarget> {
+    let target = targets
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::UploadTargetNotFound(id))?;
+    if target.is_expired() {
+        return Err(crate::Error::UploadTargetExpired);
+    }
+
+    password.verify(
+        &target,
+        ThrottleSubject::UploadTarget(target.id()),
+        throttles,
+        config,
+    )?;
+    Ok(target)
+}
+
+#[openapi(tag = "Upload Targets")]
+#[get("/<id>")]
+async fn get_drop(
+    id: Uuid,
+    targets: Collection<UploadTarget>,
+) -> crate::ApiResult<PublicUploadTarget> {
+    let target = targets
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::UploadTargetNotFound(id))?;
+    if target.is_expired() {
+        Err(crate::Error::UploadTargetExpired)
+    } else {
+        Ok(Json(target.into()))
+    }
+}
+
+/// Moves a received file into the target's directory, numbering its name on conflicts.
+/// Returns the name it was stored under along with its root-relative path.
+async fn store_drop(
+    staging: &Path,
+    name: &str,
+    target: &UploadTarget,
+    access: &RootAccess<EditAccess>,
+) -> crate::Result<(String, String)> {
+    for attempt in 0..MAX_RENAME_ATTEMPTS {
+        let candidate = numbered_name(name, attempt);
+        let destination = access
+            .resolve(Path::new(&target.path()).join(&candidate))
+            .await?;
+        match move_file(staging, &destination.absolute, false, destination.display()).await {
+            Ok(()) => return Ok((candidate, destination.display())),
+            Err(crate::Error::PathConflict(_)) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Err(crate::Error::path_conflict(name))
+}
+
+/// Anonymously uploads a single file (sent as the raw request body) into an upload target.
+/// Existing files are never replaced: conflicting names are suffixed with a number instead.
+#[openapi(tag = "Upload Targets")]
+#[post("/<id>?<name>", data = "<file>")]
+#[allow(clippy::too_many_arguments)]
+async fn drop_file(
+    id: Uuid,
+    name: String,
+    file: Data<'_>,
+    password: AccessPassword,
+    targets: Collection<UploadTarget>,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    roots: Collection<RootDirectory>,
+    throttles: MetaTree<LoginThrottle>,
+    events: &State<EventBus>,
+    config: &State<Config>,
+) -> crate::ApiResult<DropReceipt> {
+    let target = open_target(id, password, &targets, &throttles, config.inner()).await?;
+    if target.remaining_files() == Some(0) {
+        return Err(crate::Error::UploadTargetFull);
+    }
+
+    let name = file_name(name)?;
+    if !target.allows_extension(&name) {
+        return Err(crate::Error::ExtensionNotAllowed(name));
+    }
+
+    // Uploads are written with the owner's access, so targets stop working if the owner loses it
+    let (Some(owner), Some(root)) = (
+        groups.effective_owner(&users, target.owner()).await?,
+        roots.get(target.root()).await?,
+    ) else {
+        return Err(crate::Error::UploadTargetNotFound(target.id()));
+    };
+    let access = RootAccess::<EditAccess>::new(owner, root, config.inner().clone())?;
+
+    let limits = config.server().limits();
+    let mut limit = match Path::new(&name).extension() {
+        Some(extension) => limits.extension_limit(extension.to_string_lossy()),
+        None => limits.files(),
+    };
+    if let Some(remaining) = target.remaining_bytes() {
+        limit = limit.min(ByteUnit::from(remaining));
+    }
+
+    tokio::fs::create_dir_all(UploadSession::staging_dir(config.inner())).await?;
+    let staging = UploadSession::staging_dir(config.inner()).join(format!("{}.part", Uuid::new()));
+    let received = file.open(limit).into_file(&staging).await?;
+    if !received.is_complete() {
+        let _ = tokio::fs::remove_file(&staging).await;
+        return Err(crate::Error::UploadTooLarge(limit));
+    }
+
+    // Claim the file & bytes atomically, so concurrent drops can't exceed the target's limits
+    let size = received.n.written;
+    let mut filter = doc! {"id": target.id()};
+    if let Some(max) = target.max_files() {
+        filter.insert("files_received", doc! {"$lt": max as i64});
+    }
+    if let Some(max) = target.max_bytes() {
+        let Some(allowed) = max.checked_sub(size) else {
+            let _ = tokio::fs::remove_file(&staging).await;
+            return Err(crate::Error::UploadTargetFull);
+        };
+        filter.insert("bytes_received", doc! {"$lte": allowed as i64});
+    }
+    let claimed = targets
+        .update_one(
+            filter,
+            doc! {"$inc": {"bytes_received": size as i64, "files_received": 1i64}},
+        )
+        .await?;
+    if claimed.modified_count == 0 {
+        let _ = tokio::fs::remove_file(&staging).await;
+        return Err(crate::Error::UploadTargetFull);
+    }
+
+    let stored = store_drop(&staging, &name, &target, &access).await;
+    if stored.is_err() {
+        let _ = tokio::fs::remove_file(&staging).await;
+        let _ = targets
+            .update_one(
+                doc! {"id": target.id()},
+                doc! {"$inc": {"bytes_received": -(size as i64), "files_received": -1i64}},
+            )
+            .await?;
+    }
+    let (stored, path) = stored?;
+
+    events.publish(
+        target.owner(),
+        EventPayload::DropReceived {
+            target: target.id(),
+            root: target.root(),
+            path,
+            size,
+        },
+    );
+    Ok(Json(DropReceipt { name: stored, size }))
+}
+
+export_routes![get_drop, drop_file];
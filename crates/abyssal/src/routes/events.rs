@@ -0,0 +1,60 @@
+use rocket::{
+    State,
+    futures::{SinkExt, StreamExt},
+    get,
+};
+use rocket_okapi::openapi;
+use rocket_ws::{Message, WebSocket};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    export_routes,
+    models::{Event, EventPayload, UserMethods},
+    util::{AuthContext, EventBus, MessageChannel, json_message},
+};
+
+/// WebSocket streaming events concerning the caller: completed uploads, job progress, accesses to
+/// their shares, revoked sessions and permission changes. Messages from the client are ignored.
+/// The connection is closed once the session it was opened with is revoked. Connections
+/// authenticated by the session cookie must be opened from this server's own origin.
+#[openapi(tag = "Events")]
+#[get("/")]
+async fn events(
+    ws: WebSocket,
+    auth: AuthContext,
+    events: &State<EventBus>,
+) -> MessageChannel<Event> {
+    let session = auth.token().map(|token| token.session_id());
+    let mut receiver = events.subscribe(&auth.user().id());
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            loop {
+                tokio::select! {
+                    message = stream.next() => match message {
+                        Some(Ok(Message::Close(_))) | None => break,
+                        Some(Ok(_)) => {}
+                        Some(Err(err)) => return Err(err),
+                    },
+                    event = receiver.recv() => {
+                        let event = match event {
+                            Ok(event) => event,
+                            Err(RecvError::Lagged(missed)) => Event::new(EventPayload::Lagged { missed }),
+                            Err(RecvError::Closed) => break,
+                        };
+                        stream.send(json_message(&event)).await?;
+                        if let EventPayload::SessionsRevoked { sessions } = event.payload()
+                            && session.as_ref().is_some_and(|session| sessions.contains(session))
+                        {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    })
+    .into()
+}
+
+export_routes![events];
@@ -0,0 +1,676 @@
+use std::path::{Path, PathBuf};
+
+use rocket::{State, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{Job, JobOperation, RootDirectory, TrashItem, UserMethods},
+    types::{
+        Config, ConflictStrategy, DirectoryEntry, Uuid,
+        filesystem::{display_relative, normalize_relative},
+    },
+    util::{
+        AccessLevel, Collection, EditAccess, JobRegistry, JobTask, ManageAccess, MetaTree,
+        ReadAccess, Replacement, RootAccess, RootPath,
+        fs_ops::{copy_entry, file_name, move_entry, place_staged, remove_entry, staging_path},
+    },
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateDirectoryRequest {
+    pub path: String,
+
+    /// Also create missing parent directories, and don't fail if the directory already exists
+    #[serde(default)]
+    pub parents: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct RenameRequest {
+    pub path: String,
+
+    /// New name of the entry, within the same directory
+    pub name: String,
+
+    #[serde(default)]
+    pub conflict: ConflictStrategy,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct TransferRequest {
+    /// Entry to move or copy
+    pub path: String,
+
+    /// Root to move or copy into, if not the same root
+    #[serde(default)]
+    pub destination_root: Option<Uuid>,
+
+    /// Directory to move or copy into
+    pub destination: String,
+
+    /// Name of the entry at its destination, if not the same name
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub conflict: ConflictStrategy,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct DeleteRequest {
+    pub path: String,
+
+    /// Delete directories along with their contents (otherwise only empty directories can be deleted)
+    #[serde(default)]
+    pub recursive: bool,
+
+    /// Delete the entry immediately instead of moving it to the trash
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct DeleteJobRequest {
+    pub path: String,
+
+    /// Delete the entry immediately instead of moving it to the trash
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct ArchiveRequest {
+    /// Entries to include in the archive (their names must be unique)
+    pub paths: Vec<String>,
+
+    /// Directory to create the archive in
+    pub destination: String,
+
+    /// Name of the archive, defaults to the name of the first entry followed by `.zip`
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub conflict: ConflictStrategy,
+}
+
+/// Resolves the root a move/copy should go to, checking that the caller may edit it
+async fn destination_access<L: AccessLevel>(
+    access: &RootAccess<L>,
+    destination_root: Option<Uuid>,
+    roots: &Collection<RootDirectory>,
+    config: &Config,
+) -> crate::Result<RootAccess<EditAccess>> {
+    let root = match destination_root {
+        Some(id) if id != access.root().id() => roots
+            .get(id.clone())
+            .await?
+            .ok_or(crate::Error::RootNotFound(id))?,
+        _ => access.root(),
+    };
+    RootAccess::new(access.user(), root, config.clone())
+}
+
+/// Authorized source and destination of a move/copy
+struct TransferPlan {
+    source: RootPath,
+    target: RootPath,
+    destination_root: Uuid,
+
+    /// Whether an existing entry at `target` has to be replaced (and trashed)
+    replace: bool,
+}
+
+/// Checks that `directory` exists and is a directory
+async fn check_directory(directory: &RootPath) -> crate::Result<()> {
+    match tokio::fs::metadata(&directory.absolute).await {
+        Ok(metadata) if metadata.is_dir() => Ok(()),
+        Ok(_) => Err(crate::Error::not_a_directory(directory.display())),
+        Err(_) => Err(crate::Error::path_not_found(directory.display())),
+    }
+}
+
+/// Plans moving or copying an entry, authorizing the source with `L` and the destination with `Edit`
+async fn plan_transfer<L: AccessLevel>(
+    access: &RootAccess<L>,
+    body: TransferRequest,
+    copy: bool,
+    roots: &Collection<RootDirectory>,
+    config: &Config,
+) -> crate::Result<TransferPlan> {
+    let source = access.resolve(&body.path).await?;
+    if !copy {
+        access.check_modifiable(&source).await?;
+    }
+    let metadata = tokio::fs::symlink_metadata(&source.absolute)
+        .await
+        .map_err(|_| crate::Error::path_not_found(source.display()))?;
+    let name = match body.name {
+        Some(name) => file_name(name)?,
+        None => source
+            .relative
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| access.root().name()),
+    };
+
+    let destination = destination_access(access, body.destination_root, roots, config).await?;
+    let directory = destination.resolve(&body.destination).await?;
+    check_directory(&directory).await?;
+    if metadata.is_dir()
+        && tokio::fs::canonicalize(&directory.absolute)
+            .await?
+            .starts_with(&source.absolute)
+    {
+        return Err(crate::Error::path_into_itself(source.display()));
+    }
+
+    let (target, replace) = destination
+        .resolve_target(&directory.relative, &name, &body.conflict)
+        .await?;
+    if replace && target.absolute == source.absolute {
+        return Err(crate::Error::path_conflict(target.display()));
+    }
+
+    Ok(TransferPlan {
+        source,
+        target,
+        destination_root: destination.root().id(),
+        replace,
+    })
+}
+
+/// Moves or copies an entry within this request, moving an entry it replaces into the trash
+async fn transfer<L: AccessLevel>(
+    access: &RootAccess<L>,
+    body: TransferRequest,
+    copy: bool,
+    roots: &Collection<RootDirectory>,
+    trash: &MetaTree<TrashItem>,
+    config: &Config,
+) -> crate::Result<DirectoryEntry> {
+    let TransferPlan {
+        source,
+        target,
+        destination_root,
+        replace,
+    } = plan_transfer(access, body, copy, roots, config).await?;
+
+    let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+    if !replace {
+        if copy {
+            copy_entry(
+                &source.absolute,
+                &target.absolute,
+                metadata_dir,
+                target.display(),
+            )
+            .await?;
+        } else {
+            move_entry(
+                &source.absolute,
+                &target.absolute,
+                metadata_dir,
+                target.display(),
+            )
+            .await?;
+        }
+    } else if copy {
+        // The replaced entry is only set aside once the (possibly slow) copy is complete
+        let staging = staging_path(&target.absolute);
+        copy_entry(&source.absolute, &staging, metadata_dir, target.display()).await?;
+        let placed = TrashItem::replace(
+            config,
+            trash,
+            &destination_root,
+            &target,
+            access.user().id(),
+            place_staged(&staging, &target.absolute, Path::new(&target.display())),
+        )
+        .await;
+        if placed.is_err() {
+            let _ = remove_entry(&staging, true, &staging).await;
+        }
+        placed?;
+    } else {
+        TrashItem::replace(
+            config,
+            trash,
+            &destination_root,
+            &target,
+            access.user().id(),
+            move_entry(
+                &source.absolute,
+                &target.absolute,
+                metadata_dir,
+                target.display(),
+            ),
+        )
+        .await?;
+    }
+
+    DirectoryEntry::read(&target.absolute, &target.relative).await
+}
+
+/// Starts moving or copying an entry in the background
+#[allow(clippy::too_many_arguments)]
+async fn start_transfer_job<L: AccessLevel>(
+    access: &RootAccess<L>,
+    body: TransferRequest,
+    copy: bool,
+    roots: &Collection<RootDirectory>,
+    trash: MetaTree<TrashItem>,
+    jobs: MetaTree<Job>,
+    registry: &JobRegistry,
+    config: &Config,
+) -> crate::Result<Job> {
+    let plan = plan_transfer(access, body, copy, roots, config).await?;
+    let (root, path, destination_root, destination) = (
+        access.root().id(),
+        plan.source.display(),
+        plan.destination_root.clone(),
+        plan.target.display(),
+    );
+    let operation = if copy {
+        JobOperation::Copy {
+            root,
+            path,
+            destination_root,
+            destination,
+        }
+    } else {
+        JobOperation::Move {
+            root,
+            path,
+            destination_root,
+            destination,
+        }
+    };
+
+    registry.start(
+        Job::new(access.user().id(), operation),
+        JobTask::Transfer {
+            source: plan.source,
+            target: plan.target,
+            replace: plan.replace.then_some(Replacement {
+                root: plan.destination_root,
+                trash,
+            }),
+            copy,
+        },
+        jobs,
+        config.clone(),
+    )
+}
+
+/// Creates a directory
+#[openapi(tag = "Files")]
+#[post("/<id>/mkdir", data = "<body>")]
+#[allow(
+    unused_variables,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn create_directory(
+    id: Uuid,
+    body: Json<CreateDirectoryRequest>,
+    access: RootAccess<EditAccess>,
+    config: &State<Config>,
+) -> crate::ApiResult<DirectoryEntry> {
+    let relative = normalize_relative(&body.path)?;
+    let components = relative.components().count();
+    if components == 0 {
+        return Err(crate::Error::path_conflict("/"));
+    }
+
+    // Existing parents only need to be inside of the root, not within the caller's scope
+    let mut current = PathBuf::new();
+    for (index, component) in relative.components().enumerate() {
+        current.push(component);
+        let last = index + 1 == components;
+        let absolute = access.root().resolve(config.inner(), &current).await?;
+        match tokio::fs::metadata(&absolute).await {
+            Ok(metadata) if metadata.is_dir() && (body.parents || !last) => continue,
+            Ok(_) if last => return Err(crate::Error::path_conflict(display_relative(&current))),
+            Ok(_) => return Err(crate::Error::not_a_directory(display_relative(&current))),
+            Err(_) if !last && !body.parents => {
+                return Err(crate::Error::path_not_found(display_relative(&current)));
+            }
+            Err(_) => {}
+        }
+
+        let directory = access.resolve(&current).await?;
+        match tokio::fs::create_dir(&directory.absolute).await {
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(crate::Error::path_conflict(directory.display()));
+            }
+            other => other?,
+        }
+    }
+
+    let directory = access.resolve(&relative).await?;
+    Ok(Json(
+        DirectoryEntry::read(&directory.absolute, &directory.relative).await?,
+    ))
+}
+
+#[openapi(tag = "Files")]
+#[post("/<id>/rename", data = "<body>")]
+async fn rename_entry(
+    id: Uuid,
+    body: Json<RenameRequest>,
+    access: RootAccess<EditAccess>,
+    roots: Collection<RootDirectory>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::ApiResult<DirectoryEntry> {
+    let relative = normalize_relative(&body.path)?;
+    let body = TransferRequest {
+        path: body.path.clone(),
+        destination_root: Some(id),
+        destination: display_relative(relative.parent().unwrap_or(Path::new(""))),
+        name: Some(body.name.clone()),
+        conflict: body.conflict.clone(),
+    };
+    Ok(Json(
+        transfer(&access, body, false, &roots, &trash, config.inner()).await?,
+    ))
+}
+
+/// Moves an entry into another directory, possibly in another root.
+/// Requires `edit` on both the entry and the destination.
+#[openapi(tag = "Files")]
+#[post("/<id>/move", data = "<body>")]
+#[allow(
+    unused_variables,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn move_path(
+    id: Uuid,
+    body: Json<TransferRequest>,
+    access: RootAccess<EditAccess>,
+    roots: Collection<RootDirectory>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::ApiResult<DirectoryEntry> {
+    Ok(Json(
+        transfer(
+            &access,
+            body.into_inner(),
+            false,
+            &roots,
+            &trash,
+            config.inner(),
+        )
+        .await?,
+    ))
+}
+
+/// Copies an entry into another directory, possibly in another root.
+/// Requires `read` on the entry and `edit` on the destination.
+#[openapi(tag = "Files")]
+#[post("/<id>/copy", data = "<body>")]
+#[allow(
+    unused_variables,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn copy_path(
+    id: Uuid,
+    body: Json<TransferRequest>,
+    access: RootAccess<ReadAccess>,
+    roots: Collection<RootDirectory>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::ApiResult<DirectoryEntry> {
+    Ok(Json(
+        transfer(
+            &access,
+            body.into_inner(),
+            true,
+            &roots,
+            &trash,
+            config.inner(),
+        )
+        .await?,
+    ))
+}
+
+/// Deletes a file or directory, moving it to the trash unless the trash is disabled or
+/// `permanent` is set. Returns the trash item the entry can be restored from.
+#[openapi(tag = "Files")]
+#[post("/<id>/delete", data = "<body>")]
+async fn delete_path(
+    id: Uuid,
+    body: Json<DeleteRequest>,
+    access: RootAccess<ManageAccess>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::ApiResult<Option<TrashItem>> {
+    let path = access.resolve(&body.path).await?;
+    access.check_modifiable(&path).await?;
+    if body.permanent || !config.filesystem().trash().enabled() {
+        remove_entry(&path.absolute, body.recursive, path.display()).await?;
+        return Ok(Json(None));
+    }
+
+    if !body.recursive
+        && tokio::fs::symlink_metadata(&path.absolute)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir())
+        && tokio::fs::read_dir(&path.absolute)
+            .await?
+            .next_entry()
+            .await?
+            .is_some()
+    {
+        return Err(crate::Error::directory_not_empty(path.display()));
+    }
+
+    let item = TrashItem::trash(config.inner(), &trash, &id, &path, access.user().id()).await?;
+    Ok(Json(Some(item)))
+}
+
+/// Starts copying an entry in the background, with the same requirements as `copy`
+#[openapi(tag = "Jobs")]
+#[post("/<id>/jobs/copy", data = "<body>")]
+#[allow(
+    unused_variables,
+    clippy::too_many_arguments,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn start_copy_job(
+    id: Uuid,
+    body: Json<TransferRequest>,
+    access: RootAccess<ReadAccess>,
+    roots: Collection<RootDirectory>,
+    trash: MetaTree<TrashItem>,
+    jobs: MetaTree<Job>,
+    registry: &State<JobRegistry>,
+    config: &State<Config>,
+) -> crate::ApiResult<Job> {
+    Ok(Json(
+        start_transfer_job(
+            &access,
+            body.into_inner(),
+            true,
+            &roots,
+            trash,
+            jobs,
+            registry,
+            config,
+        )
+        .await?,
+    ))
+}
+
+/// Starts moving an entry in the background, with the same requirements as `move`
+#[openapi(tag = "Jobs")]
+#[post("/<id>/jobs/move", data = "<body>")]
+#[allow(
+    unused_variables,
+    clippy::too_many_arguments,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn start_move_job(
+    id: Uuid,
+    body: Json<TransferRequest>,
+    access: RootAccess<EditAccess>,
+    roots: Collection<RootDirectory>,
+    trash: MetaTree<TrashItem>,
+    jobs: MetaTree<Job>,
+    registry: &State<JobRegistry>,
+    config: &State<Config>,
+) -> crate::ApiResult<Job> {
+    Ok(Json(
+        start_transfer_job(
+            &access,
+            body.into_inner(),
+            false,
+            &roots,
+            trash,
+            jobs,
+            registry,
+            config,
+        )
+        .await?,
+    ))
+}
+
+/// Starts deleting an entry (and everything inside of it) in the background, moving it to
+/// the trash unless the trash is disabled or `permanent` is set
+#[openapi(tag = "Jobs")]
+#[post("/<id>/jobs/delete", data = "<body>")]
+async fn start_delete_job(
+    id: Uuid,
+    body: Json<DeleteJobRequest>,
+    access: RootAccess<ManageAccess>,
+    jobs: MetaTree<Job>,
+    trash: MetaTree<TrashItem>,
+    registry: &State<JobRegistry>,
+    config: &State<Config>,
+) -> crate::ApiResult<Job> {
+    let path = access.resolve(&body.path).await?;
+    access.check_modifiable(&path).await?;
+    if tokio::fs::symlink_metadata(&path.absolute).await.is_err() {
+        return Err(crate::Error::path_not_found(path.display()));
+    }
+
+    let permanent = body.permanent || !config.filesystem().trash().enabled();
+    let job = Job::new(
+        access.user().id(),
+        JobOperation::Delete {
+            root: id.clone(),
+            path: path.display(),
+            permanent,
+        },
+    );
+    let task = if permanent {
+        JobTask::Delete { path }
+    } else {
+        JobTask::Trash {
+            root: id.clone(),
+            path,
+            trash,
+        }
+    };
+    Ok(Json(registry.start(
+        job,
+        task,
+        jobs,
+        config.inner().clone(),
+    )?))
+}
+
+/// Starts creating a zip archive of one or more entries in the background.
+/// Requires `read` on the entries and `edit` on the destination.
+#[openapi(tag = "Jobs")]
+#[post("/<id>/jobs/archive", data = "<body>")]
+async fn start_archive_job(
+    id: Uuid,
+    body: Json<ArchiveRequest>,
+    access: RootAccess<ReadAccess>,
+    trash: MetaTree<TrashItem>,
+    jobs: MetaTree<Job>,
+    registry: &State<JobRegistry>,
+    config: &State<Config>,
+) -> crate::ApiResult<Job> {
+    let mut sources = Vec::with_capacity(body.paths.len());
+    let mut names = Vec::with_capacity(body.paths.len());
+    let mut directories = Vec::new();
+    for path in &body.paths {
+        let source = access.resolve(path).await?;
+        let metadata = tokio::fs::symlink_metadata(&source.absolute)
+            .await
+            .map_err(|_| crate::Error::path_not_found(source.display()))?;
+        if metadata.is_dir() {
+            directories.push(source.clone());
+        }
+        let name = source
+            .relative
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| access.root().name());
+        if names.contains(&name) {
+            return Err(crate::Error::path_conflict(source.display()));
+        }
+        names.push(name);
+        sources.push(source);
+    }
+    let Some(first) = names.first() else {
+        return Err(crate::Error::path_not_found("/"));
+    };
+
+    let destination =
+        RootAccess::<EditAccess>::new(access.user(), access.root(), config.inner().clone())?;
+    let directory = destination.resolve(&body.destination).await?;
+    check_directory(&directory).await?;
+
+    // The archive would end up inside of the tree being archived, and read itself
+    let canonical = tokio::fs::canonicalize(&directory.absolute).await?;
+    if let Some(source) = directories
+        .iter()
+        .find(|source| canonical.starts_with(&source.absolute))
+    {
+        return Err(crate::Error::path_into_itself(source.display()));
+    }
+
+    let name = match body.name.clone() {
+        Some(name) => file_name(name)?,
+        None => format!("{first}.zip"),
+    };
+    let (target, replace) = destination
+        .resolve_target(&directory.relative, name, &body.conflict)
+        .await?;
+
+    let job = Job::new(
+        access.user().id(),
+        JobOperation::Archive {
+            root: id.clone(),
+            paths: sources.iter().map(|source| source.display()).collect(),
+            destination: target.display(),
+        },
+    );
+    Ok(Json(registry.start(
+        job,
+        JobTask::Archive {
+            sources,
+            target,
+            replace: replace.then_some(Replacement { root: id, trash }),
+        },
+        jobs,
+        config.inner().clone(),
+    )?))
+}
+
+export_routes![
+    create_directory,
+    rename_entry,
+    move_path,
+    copy_path,
+    delete_path,
+    start_copy_job,
+    start_move_job,
+    start_delete_job,
+    start_archive_job
+];
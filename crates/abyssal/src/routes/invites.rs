@@ -0,0 +1,225 @@
+use bson::doc;
+use chrono::{DateTime, Utc};
+use rocket::{State, delete, get, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{GenericUser, Invite, PublicInvite, RootDirectory, User, UserMethods},
+    types::{
+        Config, Permission, PermissionCapability, RootTopLevel, Uuid,
+        filesystem::normalize_relative,
+    },
+    util::{Collection, fs_ops::file_name},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateInviteRequest {
+    pub root: Uuid,
+    pub top_level: RootTopLevel,
+    pub capability: PermissionCapability,
+
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+
+    /// Number of times the invite may be redeemed (omit for unlimited)
+    #[serde(default)]
+    pub max_uses: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct RedeemInviteRequest {
+    pub username: String,
+    pub password: String,
+}
+
+fn invite_permission(
+    root: Uuid,
+    capability: PermissionCapability,
+    administrate: bool,
+) -> Permission {
+    Permission::Invites {
+        root,
+        capability,
+        administrate,
+    }
+}
+
+/// Ensures `user` may hand out `capability` within `top_level`, which can never exceed their own access
+fn check_grant(
+    user: &User,
+    root: &Uuid,
+    top_level: &RootTopLevel,
+    capability: PermissionCapability,
+) -> crate::Result<()> {
+    let permissions = user.permissions();
+    if !permissions.has_permission(invite_permission(root.clone(), capability.clone(), false)) {
+        return Err(crate::Error::Forbidden);
+    }
+
+    // Home scopes are checked against their parent, which contains every invitee's home
+    let scope = match top_level {
+        RootTopLevel::Home { parent } => normalize_relative(parent)?,
+        other => other.scope(user.name())?,
+    };
+    if permissions.can_access(root, scope, capability, user.name()) {
+        Ok(())
+    } else {
+        Err(crate::Error::Forbidden)
+    }
+}
+
+fn check_invite(user: &User, invite: &Invite) -> crate::Result<()> {
+    if user.permissions().has_permission(invite_permission(
+        invite.root(),
+        PermissionCapability::Read,
+        invite.owner() != user.id(),
+    )) {
+        Ok(())
+    } else {
+        Err(crate::Error::InviteNotFound)
+    }
+}
+
+async fn usable_invite(code: String, invites: &Collection<Invite>) -> crate::Result<Invite> {
+    let invite = invites
+        .find_one(doc! {"code": code})
+        .await?
+        .ok_or(crate::Error::InviteNotFound)?;
+    if invite.is_usable() {
+        Ok(invite)
+    } else {
+        Err(crate::Error::InviteExpired)
+    }
+}
+
+#[openapi(tag = "Invites")]
+#[get("/")]
+async fn list_invites(user: User, invites: Collection<Invite>) -> crate::ApiResult<Vec<Invite>> {
+    let mut cursor = invites.find(doc! {}).await?;
+    let mut visible = Vec::new();
+    while cursor.advance().await? {
+        let invite = cursor.deserialize_current()?;
+        if check_invite(&user, &invite).is_ok() {
+            visible.push(invite);
+        }
+    }
+
+    Ok(Json(visible))
+}
+
+#[openapi(tag = "Invites")]
+#[post("/", data = "<body>")]
+async fn create_invite(
+    body: Json<CreateInviteRequest>,
+    user: User,
+    invites: Collection<Invite>,
+    roots: Collection<RootDirectory>,
+) -> crate::ApiResult<Invite> {
+    if roots.get(body.root.clone()).await?.is_none() {
+        return Err(crate::Error::RootNotFound(body.root.clone()));
+    }
+    check_grant(&user, &body.root, &body.top_level, body.capability.clone())?;
+
+    let invite = Invite::new(
+        user.id(),
+        body.root.clone(),
+        body.top_level.clone(),
+        body.capability.clone(),
+    )
+    .with_expires(body.expires)
+    .with_max_uses(body.max_uses);
+    let _ = invites.save(invite.clone()).await?;
+    Ok(Json(invite))
+}
+
+#[openapi(tag = "Invites")]
+#[delete("/<id>")]
+async fn revoke_invite(
+    id: Uuid,
+    user: User,
+    invites: Collection<Invite>,
+) -> crate::ApiResult<Invite> {
+    let invite = invites.get(id).await?.ok_or(crate::Error::InviteNotFound)?;
+    check_invite(&user, &invite)?;
+    if !user.permissions().has_permission(invite_permission(
+        invite.root(),
+        PermissionCapability::Manage,
+        invite.owner() != user.id(),
+    )) {
+        return Err(crate::Error::Forbidden);
+    }
+
+    let revoked = invite.with_revoked(true);
+    let _ = invites.save(revoked.clone()).await?;
+    Ok(Json(revoked))
+}
+
+#[openapi(tag = "Invites")]
+#[get("/code/<code>")]
+async fn get_invite_by_code(
+    code: String,
+    invites: Collection<Invite>,
+) -> crate::ApiResult<PublicInvite> {
+    Ok(Json(usable_invite(code, &invites).await?.into()))
+}
+
+/// Redeems an invite, creating a new local user with the invite's root directory permission
+#[openapi(tag = "Invites")]
+#[post("/code/<code>", data = "<body>")]
+async fn redeem_invite(
+    code: String,
+    body: Json<RedeemInviteRequest>,
+    invites: Collection<Invite>,
+    users: Collection<User>,
+    roots: Collection<RootDirectory>,
+    config: &State<Config>,
+) -> crate::ApiResult<GenericUser> {
+    let invite = usable_invite(code, &invites).await?;
+    let username = file_name(&body.username)
+        .map_err(|_| crate::Error::InvalidUsername(body.username.clone()))?;
+    if users.find_one(doc! {"name": &username}).await?.is_some() {
+        return Err(crate::Error::UsernameTaken(username));
+    }
+
+    let created = User::create_local(username.clone(), body.password.clone())?;
+    created.permissions().set_permission(invite.permission());
+
+    // Claim a use atomically, so concurrent redemptions can't exceed `max_uses`
+    let mut filter = doc! {"id": invite.id(), "revoked": false};
+    if let Some(max) = invite.max_uses() {
+        filter.insert("uses", doc! {"$lt": max as i64});
+    }
+    let claimed = invites
+        .update_one(
+            filter,
+            doc! {"$inc": {"uses": 1i64}, "$push": {"redeemed_by": created.id()}},
+        )
+        .await?;
+    if claimed.modified_count == 0 {
+        return Err(crate::Error::InviteExpired);
+    }
+    let _ = users.save(created.clone()).await?;
+
+    if let RootTopLevel::Home { .. } = invite.top_level()
+        && let Some(root) = roots.get(invite.root()).await?
+    {
+        let home = root
+            .resolve(config.inner(), invite.top_level().scope(&username)?)
+            .await;
+        if let Ok(home) = home {
+            let _ = tokio::fs::create_dir_all(home).await;
+        }
+    }
+
+    Ok(Json(created.into()))
+}
+
+export_routes![
+    list_invites,
+    create_invite,
+    revoke_invite,
+    get_invite_by_code,
+    redeem_invite
+];
@@ -0,0 +1,90 @@
+use chrono::Utc;
+use rocket::{State, delete, get, post, serde::json::Json};
+use rocket_okapi::openapi;
+
+use crate::{
+    export_routes,
+    models::{EventPayload, Job, JobStatus, MetaRecord, User, UserMethods},
+    types::Uuid,
+    util::{EventBus, JobRegistry, MetaTree},
+};
+
+fn load_job(id: Uuid, user: &User, jobs: &MetaTree<Job>) -> crate::Result<Job> {
+    jobs.get(id.to_string())?
+        .filter(|job| job.owner() == user.id())
+        .ok_or(crate::Error::JobNotFound(id))
+}
+
+/// Lists the caller's jobs, newest first
+#[openapi(tag = "Jobs")]
+#[get("/")]
+async fn list_jobs(user: User, jobs: MetaTree<Job>) -> crate::ApiResult<Vec<Job>> {
+    let mut owned = jobs
+        .all()?
+        .into_iter()
+        .filter(|job| job.owner() == user.id())
+        .collect::<Vec<_>>();
+    owned.sort_by_key(|job| std::cmp::Reverse(job.created()));
+    Ok(Json(owned))
+}
+
+#[openapi(tag = "Jobs")]
+#[get("/<id>")]
+async fn get_job(id: Uuid, user: User, jobs: MetaTree<Job>) -> crate::ApiResult<Job> {
+    Ok(Json(load_job(id, &user, &jobs)?))
+}
+
+/// Requests a queued or running job to stop. Work already done (such as files
+/// already deleted) isn't undone, but partial copies and archives are removed.
+#[openapi(tag = "Jobs")]
+#[post("/<id>/cancel")]
+async fn cancel_job(
+    id: Uuid,
+    user: User,
+    jobs: MetaTree<Job>,
+    registry: &State<JobRegistry>,
+    events: &State<EventBus>,
+) -> crate::ApiResult<Job> {
+    let job = load_job(id.clone(), &user, &jobs)?;
+    if job.status().is_finished() {
+        return Err(crate::Error::JobFinished(id));
+    }
+
+    if registry.cancel(&id) {
+        return Ok(Json(job));
+    }
+
+    // Not running anymore, either because it finished in the meantime (and must keep its
+    // status), or because nothing will ever update its status
+    let job = load_job(id.clone(), &user, &jobs)?;
+    if job.status().is_finished() {
+        Err(crate::Error::JobFinished(id))
+    } else {
+        let job = job
+            .with_status(JobStatus::Cancelled)
+            .with_finished(Some(Utc::now()));
+        let _ = jobs.save(job.clone())?;
+        events.publish(
+            user.id(),
+            EventPayload::JobUpdated {
+                job: Box::new(job.clone()),
+            },
+        );
+        Ok(Json(job))
+    }
+}
+
+/// Forgets a finished job
+#[openapi(tag = "Jobs")]
+#[delete("/<id>")]
+async fn delete_job(id: Uuid, user: User, jobs: MetaTree<Job>) -> crate::Result<()> {
+    let job = load_job(id.clone(), &user, &jobs)?;
+    if !job.status().is_finished() {
+        return Err(crate::Error::JobRunning(id));
+    }
+
+    let _ = jobs.delete(job.record_id())?;
+    Ok(())
+}
+
+export_routes![list_jobs, get_job, cancel_job, delete_job];
@@ -3,13 +3,47 @@ use rocket_okapi::{
     get_nested_endpoints_and_docs, settings::OpenApiSettings,
 };
 
+mod admin_groups;
+mod admin_users;
+mod applications;
+mod drop;
+mod events;
+mod files;
+mod invites;
+mod jobs;
 mod misc;
+mod passkeys;
+mod roots;
+mod shared;
+mod shares;
+mod totp;
+mod trash;
+mod upload_targets;
+mod uploads;
 mod users;
+mod watch;
 
 pub fn routes(settings: &OpenApiSettings) -> (Vec<rocket::Route>, OpenApi) {
     get_nested_endpoints_and_docs! {
         "/" => misc::routes(settings),
-        "/users" => users::routes(settings)
+        "/users" => users::routes(settings),
+        "/users/self/totp" => totp::routes(settings),
+        "/users/self/passkeys" => passkeys::routes(settings),
+        "/roots" => roots::routes(settings),
+        "/roots" => files::routes(settings),
+        "/roots" => trash::routes(settings),
+        "/uploads" => uploads::routes(settings),
+        "/upload_targets" => upload_targets::routes(settings),
+        "/drop" => drop::routes(settings),
+        "/invites" => invites::routes(settings),
+        "/shares" => shares::routes(settings),
+        "/shared" => shared::routes(settings),
+        "/jobs" => jobs::routes(settings),
+        "/watch" => watch::routes(settings),
+        "/events" => events::routes(settings),
+        "/applications" => applications::routes(settings),
+        "/admin/users" => admin_users::routes(settings),
+        "/admin/groups" => admin_groups::routes(settings)
     }
 }
 
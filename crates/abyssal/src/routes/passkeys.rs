@@ -0,0 +1,172 @@
+use bson::doc;
+use chrono::{DateTime, Utc};
+use rocket::{State, delete, get, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::{CreationChallengeResponse, RegisterPublicKeyCredential};
+
+use crate::{
+    export_routes,
+    models::{CeremonyState, PasskeyCeremony, StoredPasskey, User},
+    types::Config,
+    util::{AuthContext, Collection, MetaTree, WebauthnJson, webauthn::relying_party},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct PasskeyInfo {
+    pub id: String,
+    pub name: String,
+    pub created: DateTime<Utc>,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl From<StoredPasskey> for PasskeyInfo {
+    fn from(value: StoredPasskey) -> Self {
+        Self {
+            id: value.id(),
+            name: value.name(),
+            created: value.created(),
+            last_used: value.last_used(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct StartRegistrationRequest {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct RegistrationChallenge {
+    /// Ceremony ID to pass back when finishing the registration
+    pub ceremony: String,
+
+    /// Options for `navigator.credentials.create()`
+    pub options: WebauthnJson<CreationChallengeResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct FinishRegistrationRequest {
+    pub ceremony: String,
+
+    /// Result of `navigator.credentials.create()`
+    pub credential: WebauthnJson<RegisterPublicKeyCredential>,
+}
+
+#[openapi(tag = "Users")]
+#[get("/")]
+async fn list_passkeys(
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<Vec<PasskeyInfo>> {
+    let user = auth.session_local_user(&users).await?;
+    Ok(Json(user.passkeys().into_iter().map(Into::into).collect()))
+}
+
+/// Starts registering a new passkey
+#[openapi(tag = "Users")]
+#[post("/register", data = "<body>")]
+async fn start_registration(
+    body: Json<StartRegistrationRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+    ceremonies: MetaTree<PasskeyCeremony>,
+    config: &State<Config>,
+) -> crate::ApiResult<RegistrationChallenge> {
+    let user = auth.session_local_user(&users).await?;
+    let webauthn = relying_party(config)?;
+    for stale in ceremonies
+        .all()?
+        .into_iter()
+        .filter(PasskeyCeremony::is_expired)
+    {
+        let _ = ceremonies.delete(stale.id())?;
+    }
+
+    let existing = user
+        .passkeys()
+        .iter()
+        .map(|passkey| passkey.passkey().cred_id().clone())
+        .collect::<Vec<_>>();
+    let (options, state) = webauthn.start_passkey_registration(
+        user.id().into(),
+        &user.name(),
+        &user.display_name().unwrap_or(user.name()),
+        Some(existing),
+    )?;
+
+    let ceremony = PasskeyCeremony::new(
+        user.id(),
+        CeremonyState::Registration {
+            name: body.name.clone(),
+            state,
+        },
+    );
+    let _ = ceremonies.save(ceremony.clone())?;
+    Ok(Json(RegistrationChallenge {
+        ceremony: ceremony.id(),
+        options: WebauthnJson(options),
+    }))
+}
+
+#[openapi(tag = "Users")]
+#[post("/register/finish", data = "<body>")]
+async fn finish_registration(
+    body: Json<FinishRegistrationRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+    ceremonies: MetaTree<PasskeyCeremony>,
+    config: &State<Config>,
+) -> crate::ApiResult<PasskeyInfo> {
+    let user = auth.session_local_user(&users).await?;
+    let ceremony = ceremonies
+        .delete(&body.ceremony)?
+        .filter(|ceremony| !ceremony.is_expired() && ceremony.user() == user.id())
+        .ok_or(crate::Error::WebauthnCeremonyExpired)?;
+    let CeremonyState::Registration { name, state } = ceremony.state() else {
+        return Err(crate::Error::WebauthnCeremonyExpired);
+    };
+
+    let passkey = StoredPasskey::new(
+        name,
+        relying_party(config)?.finish_passkey_registration(&body.credential.0, &state)?,
+    );
+    if users
+        .find_one(doc! {"passkeys.id": passkey.id()})
+        .await?
+        .is_some()
+    {
+        return Err(crate::Error::PasskeyAlreadyRegistered);
+    }
+
+    let mut passkeys = user.passkeys();
+    passkeys.push(passkey.clone());
+    let _ = users.save(user.with_passkeys(passkeys).into()).await?;
+    Ok(Json(passkey.into()))
+}
+
+#[openapi(tag = "Users")]
+#[delete("/<id>")]
+async fn delete_passkey(
+    id: String,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::Result<()> {
+    let user = auth.session_local_user(&users).await?;
+    let mut passkeys = user.passkeys();
+    let count = passkeys.len();
+    passkeys.retain(|passkey| passkey.id() != id);
+    if passkeys.len() == count {
+        return Err(crate::Error::PasskeyNotFound(id));
+    }
+
+    let _ = users.save(user.with_passkeys(passkeys).into()).await?;
+    Ok(())
+}
+
+export_routes![
+    list_passkeys,
+    start_registration,
+    finish_registration,
+    delete_passkey
+];
@@ -0,0 +1,390 @@
+use std::path::Path;
+
+use bson::doc;
+use rocket::{State, delete, get, patch, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{
+        Group, Invite, MetaRecord, RootDirectory, RootDirectoryCollectionExt, Share, TrashItem,
+        UploadSession, UploadTarget, User, UserMethods,
+    },
+    types::{
+        Config, DirectoryEntry, ListingSort, RootScope, SortOrder, Uuid,
+        filesystem::{
+            display_relative, listing_limit, normalize_relative, read_directory, sort_entries,
+        },
+    },
+    util::{
+        Collection, DownloadConditions, EditAccess, FileDownload, MetaTree, ReadAccess, RootAccess,
+    },
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct AccessibleRoot {
+    #[serde(flatten)]
+    pub root: RootDirectory,
+
+    /// Directories within this root the caller may access
+    pub scopes: Vec<RootScope>,
+}
+
+impl AccessibleRoot {
+    fn for_user(root: RootDirectory, user: &User) -> Option<Self> {
+        let scopes = user
+            .permissions()
+            .scopes(&root.id(), user.name())
+            .into_iter()
+            .map(|(path, capability)| RootScope {
+                path: display_relative(path),
+                capability,
+            })
+            .collect::<Vec<_>>();
+        if scopes.is_empty() {
+            None
+        } else {
+            Some(Self { root, scopes })
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct DirectoryListing {
+    pub root: Uuid,
+    pub path: String,
+
+    /// Total number of entries in the directory (before pagination)
+    pub total: usize,
+    pub offset: usize,
+
+    /// Most entries returned in this page
+    pub limit: usize,
+    pub entries: Vec<DirectoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateRootRequest {
+    pub name: String,
+
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    /// Location of the root, relative to the configured filesystem
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UpdateRootRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Set to `null` to clear the display name, or omit to leave it unchanged
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub display_name: Option<Option<String>>,
+
+    /// Location of the root, relative to the configured filesystem
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateUploadRequest {
+    /// Destination path of the uploaded file
+    pub path: String,
+
+    /// Total size of the file in bytes
+    pub size: u64,
+
+    /// Replace an existing file at `path`, moving it into the trash
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Checks that `user` may create/modify roots, and that `name` won't be overwritten by the configuration
+fn check_root_modification(user: &User, config: &Config, name: &str) -> crate::Result<()> {
+    if !user.permissions().is_administrator() {
+        Err(crate::Error::Forbidden)
+    } else if !config.filesystem().allow_root_modification() {
+        Err(crate::Error::RootModificationDisabled)
+    } else if config.filesystem().directories().contains_key(name) {
+        Err(crate::Error::ManagedRoot(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+async fn check_root_name(
+    roots: &Collection<RootDirectory>,
+    name: &str,
+    id: Option<Uuid>,
+) -> crate::Result<()> {
+    match roots.by_name(name).await? {
+        Some(existing) if Some(existing.id()) != id => {
+            Err(crate::Error::RootNameTaken(name.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[openapi(tag = "Roots")]
+#[get("/")]
+async fn list_roots(
+    user: User,
+    roots: Collection<RootDirectory>,
+) -> crate::ApiResult<Vec<AccessibleRoot>> {
+    let mut cursor = roots.find(doc! {}).await?;
+    let mut accessible = Vec::new();
+    while cursor.advance().await? {
+        if let Some(root) = AccessibleRoot::for_user(cursor.deserialize_current()?, &user) {
+            accessible.push(root);
+        }
+    }
+
+    Ok(Json(accessible))
+}
+
+#[openapi(tag = "Roots")]
+#[get("/<id>")]
+async fn get_root(
+    id: Uuid,
+    user: User,
+    roots: Collection<RootDirectory>,
+) -> crate::ApiResult<AccessibleRoot> {
+    roots
+        .get(id.clone())
+        .await?
+        .and_then(|root| AccessibleRoot::for_user(root, &user))
+        .map(Json)
+        .ok_or(crate::Error::RootNotFound(id))
+}
+
+/// Lists a directory, returning up to `limit` entries starting at `offset`
+/// (1000 entries by default, and at most 10000)
+#[openapi(tag = "Roots")]
+#[get("/<id>/list?<path>&<offset>&<limit>&<sort>&<order>&<directories_first>")]
+#[allow(clippy::too_many_arguments)]
+async fn list_directory(
+    id: Uuid,
+    path: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<ListingSort>,
+    order: Option<SortOrder>,
+    directories_first: Option<bool>,
+    access: RootAccess<ReadAccess>,
+    config: &State<Config>,
+) -> crate::ApiResult<DirectoryListing> {
+    let directory = access.resolve(path.unwrap_or_default()).await?;
+    let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+    let mut entries =
+        read_directory(&directory.absolute, &directory.relative, metadata_dir).await?;
+
+    sort_entries(
+        &mut entries,
+        sort.unwrap_or(ListingSort::Name),
+        order.unwrap_or(SortOrder::Asc),
+        directories_first.unwrap_or(true),
+    );
+
+    let total = entries.len();
+    let (offset, limit) = (offset.unwrap_or(0), listing_limit(limit));
+    let entries = entries.into_iter().skip(offset).take(limit).collect();
+
+    Ok(Json(DirectoryListing {
+        root: id,
+        path: directory.display(),
+        total,
+        offset,
+        limit,
+        entries,
+    }))
+}
+
+/// Downloads a file, supporting `Range` requests and conditional (`ETag`/`Last-Modified`) caching.
+/// Set `inline` to have browsers display the file rather than saving it.
+#[openapi(tag = "Roots")]
+#[get("/<id>/download?<path>&<inline>")]
+#[allow(
+    unused_variables,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn download_file(
+    id: Uuid,
+    path: String,
+    inline: Option<bool>,
+    access: RootAccess<ReadAccess>,
+    conditions: DownloadConditions,
+) -> crate::Result<FileDownload> {
+    let file = access.resolve(path).await?;
+    match tokio::fs::metadata(&file.absolute).await {
+        Ok(metadata) if metadata.is_file() => {
+            FileDownload::open(&file.absolute, conditions, inline.unwrap_or(false)).await
+        }
+        Ok(_) => Err(crate::Error::not_a_file(file.display())),
+        Err(_) => Err(crate::Error::path_not_found(file.display())),
+    }
+}
+
+/// Starts a resumable upload into this root. Chunks are then sent to `/uploads/<session>`.
+#[openapi(tag = "Uploads")]
+#[post("/<id>/uploads", data = "<body>")]
+async fn create_upload(
+    id: Uuid,
+    body: Json<CreateUploadRequest>,
+    access: RootAccess<EditAccess>,
+    uploads: MetaTree<UploadSession>,
+    config: &State<Config>,
+) -> crate::ApiResult<UploadSession> {
+    let target = access.resolve(&body.path).await?;
+    if target.relative.as_os_str().is_empty() {
+        return Err(crate::Error::path_conflict(target.display()));
+    }
+    if let Ok(existing) = tokio::fs::metadata(&target.absolute).await
+        && (existing.is_dir() || !body.overwrite)
+    {
+        return Err(crate::Error::path_conflict(target.display()));
+    }
+
+    let session = UploadSession::new(
+        access.user().id(),
+        id,
+        target.display(),
+        body.size,
+        body.overwrite,
+    );
+    let limit = session.limit(config.inner());
+    if body.size > limit.as_u64() {
+        return Err(crate::Error::UploadTooLarge(limit));
+    }
+
+    tokio::fs::create_dir_all(UploadSession::staging_dir(config.inner())).await?;
+    tokio::fs::File::create(session.staging_path(config.inner())).await?;
+    let _ = uploads.save(session.clone())?;
+    Ok(Json(session))
+}
+
+#[openapi(tag = "Roots")]
+#[post("/", data = "<body>")]
+async fn create_root(
+    body: Json<CreateRootRequest>,
+    user: User,
+    roots: Collection<RootDirectory>,
+    config: &State<Config>,
+) -> crate::ApiResult<RootDirectory> {
+    check_root_modification(&user, config.inner(), &body.name)?;
+    check_root_name(&roots, &body.name, None).await?;
+
+    let root = RootDirectory::new(
+        body.name.clone(),
+        body.display_name.clone(),
+        Path::new("/").join(normalize_relative(&body.path)?),
+    );
+    root.validate_path(config.inner()).await?;
+    let _ = roots.save(root.clone()).await?;
+    Ok(Json(root))
+}
+
+#[openapi(tag = "Roots")]
+#[patch("/<id>", data = "<body>")]
+async fn update_root(
+    id: Uuid,
+    body: Json<UpdateRootRequest>,
+    user: User,
+    roots: Collection<RootDirectory>,
+    config: &State<Config>,
+) -> crate::ApiResult<RootDirectory> {
+    let mut root = roots
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::RootNotFound(id.clone()))?;
+    check_root_modification(&user, config.inner(), &root.name())?;
+    if root.managed() {
+        return Err(crate::Error::ManagedRoot(root.name()));
+    }
+
+    if let Some(name) = body.name.clone() {
+        check_root_modification(&user, config.inner(), &name)?;
+        check_root_name(&roots, &name, Some(id.clone())).await?;
+        root = root.with_name(name);
+    }
+
+    if let Some(display_name) = body.display_name.clone() {
+        root = root.with_display_name(display_name);
+    }
+
+    if let Some(path) = body.path.clone() {
+        root = root.with_path(Path::new("/").join(normalize_relative(path)?));
+        root.validate_path(config.inner()).await?;
+    }
+
+    let _ = roots.save(root.clone()).await?;
+    Ok(Json(root))
+}
+
+/// Deletes a root, along with the permissions, shares, upload targets, invites, pending
+/// uploads and trash referencing it. Its directory on disk is left untouched.
+#[openapi(tag = "Roots")]
+#[delete("/<id>")]
+#[allow(clippy::too_many_arguments)]
+async fn delete_root(
+    id: Uuid,
+    user: User,
+    roots: Collection<RootDirectory>,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    shares: Collection<Share>,
+    targets: Collection<UploadTarget>,
+    invites: Collection<Invite>,
+    uploads: MetaTree<UploadSession>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::Result<()> {
+    let root = roots
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::RootNotFound(id.clone()))?;
+    check_root_modification(&user, config.inner(), &root.name())?;
+    if root.managed() {
+        return Err(crate::Error::ManagedRoot(root.name()));
+    }
+
+    let _ = roots.delete(id.clone()).await?;
+
+    // Drop every permission and resource referencing the deleted root
+    let pull = doc! {"$pull": {"permissions": {"root": id.clone()}}};
+    let _ = users.update_many(doc! {}, pull.clone()).await?;
+    let _ = groups.update_many(doc! {}, pull).await?;
+    let _ = shares.delete_many(doc! {"root": id.clone()}).await?;
+    let _ = targets.delete_many(doc! {"root": id.clone()}).await?;
+    let _ = invites.delete_many(doc! {"root": id.clone()}).await?;
+    for session in uploads.all()? {
+        if session.root() == id {
+            session.discard(config.inner(), &uploads).await?;
+        }
+    }
+
+    // Trashed entries are kept in the metadata directory, not inside of the root
+    for item in trash.all()? {
+        if item.root() == id {
+            let _ = trash.delete(item.record_id())?;
+        }
+    }
+    match tokio::fs::remove_dir_all(TrashItem::trash_dir(config.inner(), &id)).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+export_routes![
+    list_roots,
+    get_root,
+    list_directory,
+    download_file,
+    create_upload,
+    create_root,
+    update_root,
+    delete_root
+];
@@ -0,0 +1,258 @@
+use std::path::{Path, PathBuf};
+
+use bson::doc;
+use rocket::{State, get, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{
+        EventPayload, Group, GroupCollectionExt, LoginThrottle, PublicShare, RootDirectory, Share,
+        ShareAccess, ThrottleSubject, User,
+    },
+    types::{
+        Config, DirectoryEntry, EntryKind, ListingSort, SortOrder, Uuid,
+        filesystem::{
+            display_relative, listing_limit, normalize_relative, read_directory, sort_entries,
+        },
+    },
+    util::{
+        AccessPassword, Collection, DownloadConditions, EventBus, FileDownload, MetaTree,
+        ReadAccess, RootAccess, RootPath,
+    },
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct SharedListing {
+    pub share: Uuid,
+
+    /// Listed directory, relative to the shared folder
+    pub path: String,
+
+    /// Total number of entries in the directory (before pagination)
+    pub total: usize,
+    pub offset: usize,
+
+    /// Most entries returned in this page
+    pub limit: usize,
+
+    /// Entries, with paths relative to the shared folder
+    pub entries: Vec<DirectoryEntry>,
+}
+
+/// An accessible share, along with its owner's access to the shared root
+struct OpenShare {
+    share: Share,
+    access: RootAccess<ReadAccess>,
+}
+
+impl OpenShare {
+    /// Loads an unexpired share, checking its password
+    #[allow(clippy::too_many_arguments)]
+    async fn open(
+        id: Uuid,
+        password: AccessPassword,
+        shares: &Collection<Share>,
+        users: &Collection<User>,
+        groups: &Collection<Group>,
+        roots: &Collection<RootDirectory>,
+        throttles: &MetaTree<LoginThrottle>,
+        config: &Config,
+    ) -> crate::Result<Self> {
+        let share = shares
+            .get(id.clone())
+            .await?
+            .ok_or(crate::Error::ShareNotFound(id))?;
+        if share.is_expired() {
+            return Err(crate::Error::ShareExpired);
+        }
+        password.verify(
+            &share,
+            ThrottleSubject::Share(share.id()),
+            throttles,
+            config,
+        )?;
+
+        // Shares are served with the owner's access, so they stop working if the owner loses it
+        let (Some(owner), Some(root)) = (
+            groups.effective_owner(users, share.owner()).await?,
+            roots.get(share.root()).await?,
+        ) else {
+            return Err(crate::Error::ShareNotFound(share.id()));
+        };
+        let access = RootAccess::<ReadAccess>::new(owner, root, config.clone())
+            .map_err(|_| crate::Error::ShareNotFound(share.id()))?;
+        Ok(Self { share, access })
+    }
+
+    /// Resolves a path relative to the shared item, also returning the share-relative path
+    async fn resolve(&self, path: Option<String>) -> crate::Result<(RootPath, PathBuf)> {
+        let relative = normalize_relative(path.unwrap_or_default())?;
+        if self.share.kind() != EntryKind::Directory && !relative.as_os_str().is_empty() {
+            return Err(crate::Error::path_not_found(display_relative(relative)));
+        }
+
+        let hide_forbidden = |err| match err {
+            crate::Error::Forbidden => crate::Error::ShareNotFound(self.share.id()),
+            other => other,
+        };
+        let shared = self
+            .access
+            .resolve(self.share.path())
+            .await
+            .map_err(hide_forbidden)?;
+        let resolved = self
+            .access
+            .resolve(Path::new(&self.share.path()).join(&relative))
+            .await
+            .map_err(hide_forbidden)?;
+
+        // Symlinks inside of a shared folder mustn't expose the rest of the root
+        let scopes = self.access.canonical(&shared).await?;
+        for canonical in self.access.canonical(&resolved).await? {
+            if !scopes.iter().any(|scope| canonical.starts_with(scope)) {
+                return Err(crate::Error::path_not_found(display_relative(relative)));
+            }
+        }
+        Ok((resolved, relative))
+    }
+
+    /// Lets the share's owner know it was accessed
+    fn notify(&self, events: &EventBus, access: ShareAccess, relative: &Path) {
+        events.publish(
+            self.share.owner(),
+            EventPayload::ShareAccessed {
+                share: self.share.id(),
+                access,
+                path: display_relative(relative),
+            },
+        );
+    }
+}
+
+#[openapi(tag = "Shares")]
+#[get("/<id>")]
+async fn get_shared(id: Uuid, shares: Collection<Share>) -> crate::ApiResult<PublicShare> {
+    let share = shares
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::ShareNotFound(id))?;
+    if share.is_expired() {
+        Err(crate::Error::ShareExpired)
+    } else {
+        Ok(Json(share.into()))
+    }
+}
+
+/// Lists a directory within a shared folder, paginated like root directory listings
+#[openapi(tag = "Shares")]
+#[get("/<id>/list?<path>&<offset>&<limit>&<sort>&<order>&<directories_first>")]
+#[allow(clippy::too_many_arguments)]
+async fn list_shared(
+    id: Uuid,
+    path: Option<String>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<ListingSort>,
+    order: Option<SortOrder>,
+    directories_first: Option<bool>,
+    password: AccessPassword,
+    shares: Collection<Share>,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    roots: Collection<RootDirectory>,
+    throttles: MetaTree<LoginThrottle>,
+    events: &State<EventBus>,
+    config: &State<Config>,
+) -> crate::ApiResult<SharedListing> {
+    let shared = OpenShare::open(
+        id.clone(),
+        password,
+        &shares,
+        &users,
+        &groups,
+        &roots,
+        &throttles,
+        config,
+    )
+    .await?;
+    let (directory, relative) = shared.resolve(path).await?;
+    let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+    let mut entries = read_directory(&directory.absolute, &relative, metadata_dir).await?;
+
+    sort_entries(
+        &mut entries,
+        sort.unwrap_or(ListingSort::Name),
+        order.unwrap_or(SortOrder::Asc),
+        directories_first.unwrap_or(true),
+    );
+
+    let total = entries.len();
+    let (offset, limit) = (offset.unwrap_or(0), listing_limit(limit));
+    let entries = entries.into_iter().skip(offset).take(limit).collect();
+
+    shared.notify(events, ShareAccess::List, &relative);
+    Ok(Json(SharedListing {
+        share: id,
+        path: display_relative(relative),
+        total,
+        offset,
+        limit,
+        entries,
+    }))
+}
+
+/// Downloads the shared file, or a file within a shared folder when `path` is given.
+/// Requests count against the share's download limit when they start sending the file from
+/// its beginning, so resuming or seeking with later `Range`s and cache revalidations are free.
+#[openapi(tag = "Shares")]
+#[get("/<id>/download?<path>&<inline>")]
+#[allow(clippy::too_many_arguments)]
+async fn download_shared(
+    id: Uuid,
+    path: Option<String>,
+    inline: Option<bool>,
+    password: AccessPassword,
+    conditions: DownloadConditions,
+    shares: Collection<Share>,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    roots: Collection<RootDirectory>,
+    throttles: MetaTree<LoginThrottle>,
+    events: &State<EventBus>,
+    config: &State<Config>,
+) -> crate::Result<FileDownload> {
+    let shared = OpenShare::open(
+        id, password, &shares, &users, &groups, &roots, &throttles, config,
+    )
+    .await?;
+    let (file, relative) = shared.resolve(path).await?;
+    match tokio::fs::metadata(&file.absolute).await {
+        Ok(metadata) if metadata.is_file() => {}
+        Ok(_) => return Err(crate::Error::not_a_file(display_relative(relative))),
+        Err(_) => return Err(crate::Error::path_not_found(display_relative(relative))),
+    }
+
+    let download = FileDownload::open(&file.absolute, conditions, inline.unwrap_or(false)).await?;
+    if !download.starts_download() {
+        return Ok(download);
+    }
+
+    // Claim a download atomically, so concurrent requests can't exceed `max_downloads`
+    let mut filter = doc! {"id": shared.share.id()};
+    if let Some(max) = shared.share.max_downloads() {
+        filter.insert("downloads", doc! {"$lt": max as i64});
+    }
+    let claimed = shares
+        .update_one(filter, doc! {"$inc": {"downloads": 1i64}})
+        .await?;
+    if claimed.modified_count == 0 {
+        return Err(crate::Error::ShareExhausted);
+    }
+
+    shared.notify(events, ShareAccess::Download, &relative);
+    Ok(download)
+}
+
+export_routes![get_shared, list_shared, download_shared];
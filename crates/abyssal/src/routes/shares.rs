@@ -0,0 +1,239 @@
+use bson::doc;
+use chrono::{DateTime, Utc};
+use rocket::{State, delete, get, patch, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{Group, GroupCollectionExt, RootDirectory, Share, ShareInfo, User, UserMethods},
+    types::{Config, DirectoryEntry, EntryKind, Permission, PermissionCapability, Uuid},
+    util::{Collection, PasswordProtected, ReadAccess, RootAccess},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateShareRequest {
+    pub root: Uuid,
+
+    /// Shared file or folder, relative to the root
+    pub path: String,
+
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub password: Option<String>,
+
+    #[serde(default)]
+    pub max_downloads: Option<u64>,
+}
+
+/// Omitted fields are left unchanged, while `null` clears optional settings
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UpdateShareRequest {
+    #[serde(default)]
+    pub path: Option<String>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub name: Option<Option<String>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<DateTime<Utc>>")]
+    pub expires: Option<Option<DateTime<Utc>>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub password: Option<Option<String>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<u64>")]
+    pub max_downloads: Option<Option<u64>>,
+}
+
+fn share_permission(
+    root: Uuid,
+    capability: PermissionCapability,
+    administrate: bool,
+) -> Permission {
+    Permission::Shares {
+        root,
+        capability,
+        administrate,
+    }
+}
+
+/// Checks `user`'s access to an existing share, requiring `administrate` for shares they don't own
+fn check_share(user: &User, share: &Share, capability: PermissionCapability) -> crate::Result<()> {
+    let administrate = share.owner() != user.id();
+    let permissions = user.permissions();
+    if !permissions.has_permission(share_permission(
+        share.root(),
+        PermissionCapability::Read,
+        administrate,
+    )) {
+        Err(crate::Error::ShareNotFound(share.id()))
+    } else if !permissions.has_permission(share_permission(share.root(), capability, administrate))
+    {
+        Err(crate::Error::Forbidden)
+    } else {
+        Ok(())
+    }
+}
+
+/// Resolves a shared path with the owner's access, returning its display form and kind
+async fn check_source(
+    owner: User,
+    root: Uuid,
+    path: String,
+    roots: &Collection<RootDirectory>,
+    config: &Config,
+) -> crate::Result<(String, EntryKind)> {
+    let root = roots
+        .get(root.clone())
+        .await?
+        .ok_or(crate::Error::RootNotFound(root))?;
+    let source = RootAccess::<ReadAccess>::new(owner, root, config.clone())?
+        .resolve(path)
+        .await?;
+    let entry = DirectoryEntry::read(&source.absolute, &source.relative)
+        .await
+        .map_err(|_| crate::Error::path_not_found(source.display()))?;
+    match entry.kind {
+        EntryKind::File | EntryKind::Directory => Ok((source.display(), entry.kind)),
+        _ => Err(crate::Error::not_a_file(source.display())),
+    }
+}
+
+async fn load_share(
+    id: Uuid,
+    user: &User,
+    shares: &Collection<Share>,
+    capability: PermissionCapability,
+) -> crate::Result<Share> {
+    let share = shares
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::ShareNotFound(id))?;
+    check_share(user, &share, capability)?;
+    Ok(share)
+}
+
+#[openapi(tag = "Shares")]
+#[get("/")]
+async fn list_shares(user: User, shares: Collection<Share>) -> crate::ApiResult<Vec<ShareInfo>> {
+    let mut cursor = shares.find(doc! {}).await?;
+    let mut visible = Vec::new();
+    while cursor.advance().await? {
+        let share = cursor.deserialize_current()?;
+        if check_share(&user, &share, PermissionCapability::Read).is_ok() {
+            visible.push(share.into());
+        }
+    }
+
+    Ok(Json(visible))
+}
+
+#[openapi(tag = "Shares")]
+#[get("/<id>")]
+async fn get_share(id: Uuid, user: User, shares: Collection<Share>) -> crate::ApiResult<ShareInfo> {
+    Ok(Json(
+        load_share(id, &user, &shares, PermissionCapability::Read)
+            .await?
+            .into(),
+    ))
+}
+
+#[openapi(tag = "Shares")]
+#[post("/", data = "<body>")]
+async fn create_share(
+    body: Json<CreateShareRequest>,
+    user: User,
+    shares: Collection<Share>,
+    roots: Collection<RootDirectory>,
+    config: &State<Config>,
+) -> crate::ApiResult<ShareInfo> {
+    if !user.permissions().has_permission(share_permission(
+        body.root.clone(),
+        PermissionCapability::Manage,
+        false,
+    )) {
+        return Err(crate::Error::Forbidden);
+    }
+
+    let (path, kind) = check_source(
+        user.clone(),
+        body.root.clone(),
+        body.path.clone(),
+        &roots,
+        config.inner(),
+    )
+    .await?;
+    let share = Share::new(body.root.clone(), path, kind, user.id())
+        .with_name(body.name.clone())
+        .with_expires(body.expires)
+        .with_max_downloads(body.max_downloads)
+        .with_plain_password(body.password.clone())?;
+    let _ = shares.save(share.clone()).await?;
+    Ok(Json(share.into()))
+}
+
+#[openapi(tag = "Shares")]
+#[patch("/<id>", data = "<body>")]
+#[allow(clippy::too_many_arguments)]
+async fn update_share(
+    id: Uuid,
+    body: Json<UpdateShareRequest>,
+    user: User,
+    shares: Collection<Share>,
+    roots: Collection<RootDirectory>,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    config: &State<Config>,
+) -> crate::ApiResult<ShareInfo> {
+    let mut share = load_share(id, &user, &shares, PermissionCapability::Edit).await?;
+
+    if let Some(path) = body.path.clone() {
+        // Shared paths are always checked against the owner's access, not the editor's
+        let owner = groups
+            .effective_owner(&users, share.owner())
+            .await?
+            .ok_or(crate::Error::ShareNotFound(share.id()))?;
+        let (path, kind) = check_source(owner, share.root(), path, &roots, config.inner()).await?;
+        share = share.with_path(path).with_kind(kind);
+    }
+    if let Some(name) = body.name.clone() {
+        share = share.with_name(name);
+    }
+    if let Some(expires) = body.expires {
+        share = share.with_expires(expires);
+    }
+    if let Some(max_downloads) = body.max_downloads {
+        share = share.with_max_downloads(max_downloads);
+    }
+    if let Some(password) = body.password.clone() {
+        share = share.with_plain_password(password)?;
+    }
+
+    let _ = shares.save(share.clone()).await?;
+    Ok(Json(share.into()))
+}
+
+#[openapi(tag = "Shares")]
+#[delete("/<id>")]
+async fn delete_share(id: Uuid, user: User, shares: Collection<Share>) -> crate::Result<()> {
+    let share = load_share(id, &user, &shares, PermissionCapability::Manage).await?;
+    let _ = shares.delete(share.id()).await?;
+    Ok(())
+}
+
+export_routes![
+    list_shares,
+    get_share,
+    create_share,
+    update_share,
+    delete_share
+];
@@ -0,0 +1,165 @@
+use rocket::{get, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{LocalUser, TotpSettings, User},
+    util::{AuthContext, Collection},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct TotpStatus {
+    pub enabled: bool,
+
+    /// Whether an enrollment was started but not yet confirmed
+    pub pending: bool,
+    pub recovery_codes_remaining: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct TotpEnrollment {
+    /// Base32 secret, for manual entry into an authenticator app
+    pub secret: String,
+
+    /// `otpauth://` provisioning URI, to display as a QR code
+    pub uri: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct TotpCodeRequest {
+    pub code: String,
+}
+
+/// Recovery codes, which are only ever returned once
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct DisableTotpRequest {
+    pub password: String,
+
+    /// Current TOTP code or one of the recovery codes
+    pub code: String,
+}
+
+fn confirmed_totp(user: &LocalUser) -> crate::Result<TotpSettings> {
+    User::from(user.clone())
+        .totp()
+        .ok_or(crate::Error::TotpNotEnabled)
+}
+
+#[openapi(tag = "Users")]
+#[get("/")]
+async fn get_totp_status(
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<TotpStatus> {
+    let user = auth.session_local_user(&users).await?;
+    Ok(Json(match user.totp() {
+        Some(totp) => TotpStatus {
+            enabled: totp.confirmed(),
+            pending: !totp.confirmed(),
+            recovery_codes_remaining: totp.recovery_codes().len(),
+        },
+        None => TotpStatus {
+            enabled: false,
+            pending: false,
+            recovery_codes_remaining: 0,
+        },
+    }))
+}
+
+/// Starts enrolling an authenticator app, replacing any pending enrollment.
+/// Two-factor authentication isn't required until the enrollment is confirmed.
+#[openapi(tag = "Users")]
+#[post("/")]
+async fn enroll_totp(
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<TotpEnrollment> {
+    let user = auth.session_local_user(&users).await?;
+    if confirmed_totp(&user).is_ok() {
+        return Err(crate::Error::TotpAlreadyEnabled);
+    }
+
+    let totp = TotpSettings::generate();
+    let enrollment = TotpEnrollment {
+        secret: totp.secret(),
+        uri: totp.provisioning_uri(user.name())?,
+    };
+    let _ = users.save(user.with_totp(Some(totp)).into()).await?;
+    Ok(Json(enrollment))
+}
+
+/// Confirms a pending enrollment with a code from the authenticator app, enabling
+/// two-factor authentication and returning the initial recovery codes
+#[openapi(tag = "Users")]
+#[post("/confirm", data = "<body>")]
+async fn confirm_totp(
+    body: Json<TotpCodeRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<RecoveryCodes> {
+    let user = auth.session_local_user(&users).await?;
+    let pending = match user.totp() {
+        Some(totp) if totp.confirmed() => return Err(crate::Error::TotpAlreadyEnabled),
+        Some(totp) => totp,
+        None => return Err(crate::Error::TotpNotEnabled),
+    };
+
+    let confirmed = pending
+        .verify_code(&body.code)?
+        .ok_or(crate::Error::IncorrectTotpCode)?
+        .with_confirmed(true);
+    let (confirmed, recovery_codes) = confirmed.regenerate_recovery_codes()?;
+    let _ = users.save(user.with_totp(Some(confirmed)).into()).await?;
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+/// Replaces all recovery codes, invalidating the previous ones
+#[openapi(tag = "Users")]
+#[post("/recovery_codes", data = "<body>")]
+async fn regenerate_recovery_codes(
+    body: Json<TotpCodeRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<RecoveryCodes> {
+    let user = auth.session_local_user(&users).await?;
+    let totp = confirmed_totp(&user)?
+        .verify_code(&body.code)?
+        .ok_or(crate::Error::IncorrectTotpCode)?;
+    let (totp, recovery_codes) = totp.regenerate_recovery_codes()?;
+    let _ = users.save(user.with_totp(Some(totp)).into()).await?;
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
+
+#[openapi(tag = "Users")]
+#[post("/disable", data = "<body>")]
+async fn disable_totp(
+    body: Json<DisableTotpRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::Result<()> {
+    let user = auth.session_local_user(&users).await?;
+    let totp = confirmed_totp(&user)?;
+    if !User::from(user.clone()).verify_password(body.password.clone())? {
+        return Err(crate::Error::IncorrectCredentials);
+    }
+    if totp.verify_code(&body.code)?.is_none() && totp.use_recovery_code(&body.code)?.is_none() {
+        return Err(crate::Error::IncorrectTotpCode);
+    }
+
+    let _ = users.save(user.with_totp(None).into()).await?;
+    Ok(())
+}
+
+export_routes![
+    get_totp_status,
+    enroll_totp,
+    confirm_totp,
+    regenerate_recovery_codes,
+    disable_totp
+];
@@ -0,0 +1,167 @@
+use std::path::Path;
+
+use rocket::{State, delete, get, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{TrashItem, UserMethods},
+    types::{
+        Config, ConflictStrategy, DirectoryEntry, PermissionCapability, Uuid,
+        filesystem::{display_relative, normalize_relative},
+    },
+    util::{AccessLevel, EditAccess, ManageAccess, MetaTree, ReadAccess, RootAccess},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct TrashListing {
+    pub items: Vec<TrashItem>,
+
+    /// Seconds items are kept before being purged automatically (`0` if they are kept forever)
+    pub retention: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct RestoreRequest {
+    /// Directory to restore the entry into, if not the one it was deleted from
+    #[serde(default)]
+    pub destination: Option<String>,
+
+    #[serde(default)]
+    pub conflict: ConflictStrategy,
+}
+
+/// Loads a trash item of the guard's root, checking the caller holds `capability` on its original path
+fn load_item<L: AccessLevel>(
+    item: Uuid,
+    access: &RootAccess<L>,
+    trash: &MetaTree<TrashItem>,
+    capability: PermissionCapability,
+) -> crate::Result<TrashItem> {
+    let found = trash
+        .get(item.to_string())?
+        .filter(|found| found.root() == access.root().id())
+        .ok_or(crate::Error::TrashItemNotFound(item.clone()))?;
+    match access.authorize(found.path(), capability) {
+        Ok(_) => Ok(found),
+        Err(_) => Err(crate::Error::TrashItemNotFound(item)),
+    }
+}
+
+/// Lists the root's trash, limited to items deleted from paths the caller can read
+#[openapi(tag = "Trash")]
+#[get("/<id>/trash")]
+async fn list_trash(
+    id: Uuid,
+    access: RootAccess<ReadAccess>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::ApiResult<TrashListing> {
+    let mut items = trash
+        .all()?
+        .into_iter()
+        .filter(|item| {
+            item.root() == id
+                && access
+                    .authorize(item.path(), PermissionCapability::Read)
+                    .is_ok()
+        })
+        .collect::<Vec<_>>();
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted()));
+
+    Ok(Json(TrashListing {
+        items,
+        retention: config.filesystem().trash().retention(),
+    }))
+}
+
+/// Restores an item to where it was deleted from (or `destination`).
+/// Requires `edit` on both its original path and the destination.
+#[openapi(tag = "Trash")]
+#[post("/<id>/trash/<item>/restore", data = "<body>")]
+#[allow(
+    unused_variables,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn restore_item(
+    id: Uuid,
+    item: Uuid,
+    body: Json<RestoreRequest>,
+    access: RootAccess<EditAccess>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::ApiResult<DirectoryEntry> {
+    let item = load_item(item, &access, &trash, PermissionCapability::Edit)?;
+    let original = normalize_relative(item.path())?;
+    let name = original
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or(crate::Error::protected_path(item.path()))?;
+    let directory = match body.destination.clone() {
+        Some(destination) => destination,
+        None => display_relative(original.parent().unwrap_or(Path::new(""))),
+    };
+
+    let directory = access.resolve(directory).await?;
+    match tokio::fs::metadata(&directory.absolute).await {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => return Err(crate::Error::not_a_directory(directory.display())),
+        Err(_) => return Err(crate::Error::path_not_found(directory.display())),
+    }
+
+    let (target, replace) = access
+        .resolve_target(&directory.relative, name, &body.conflict)
+        .await?;
+    item.restore(config.inner(), &trash, &target, replace, access.user().id())
+        .await?;
+    Ok(Json(
+        DirectoryEntry::read(&target.absolute, &target.relative).await?,
+    ))
+}
+
+/// Permanently deletes an item from the trash
+#[openapi(tag = "Trash")]
+#[delete("/<id>/trash/<item>")]
+#[allow(
+    unused_variables,
+    reason = "`id` is consumed by the `RootAccess` guard"
+)]
+async fn purge_item(
+    id: Uuid,
+    item: Uuid,
+    access: RootAccess<ManageAccess>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::Result<()> {
+    load_item(item, &access, &trash, PermissionCapability::Manage)?
+        .purge(config.inner(), &trash)
+        .await
+}
+
+/// Permanently deletes every item in the root's trash the caller may manage
+#[openapi(tag = "Trash")]
+#[delete("/<id>/trash")]
+async fn empty_trash(
+    id: Uuid,
+    access: RootAccess<ManageAccess>,
+    trash: MetaTree<TrashItem>,
+    config: &State<Config>,
+) -> crate::Result<()> {
+    // Keep purging past failures, so one broken item doesn't keep the rest in the trash
+    let mut result = Ok(());
+    for item in trash.all()? {
+        if item.root() == id
+            && access
+                .authorize(item.path(), PermissionCapability::Manage)
+                .is_ok()
+            && let Err(err) = item.purge(config.inner(), &trash).await
+        {
+            rocket::error!("Failed to purge trash item {}: {err}", item.id());
+            result = Err(err);
+        }
+    }
+    result
+}
+
+export_routes![list_trash, restore_item, purge_item, empty_trash];
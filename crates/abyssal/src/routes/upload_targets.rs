@@ -0,0 +1,279 @@
+use bson::doc;
+use chrono::{DateTime, Utc};
+use rocket::{State, delete, get, patch, post, serde::json::Json};
+use rocket_okapi::{JsonSchema, openapi};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    export_routes,
+    models::{
+        Group, GroupCollectionExt, RootDirectory, UploadTarget, UploadTargetInfo, User, UserMethods,
+    },
+    types::{Config, Permission, PermissionCapability, Uuid},
+    util::{Collection, EditAccess, PasswordProtected, RootAccess},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreateUploadTargetRequest {
+    pub root: Uuid,
+
+    /// Destination directory, relative to the root
+    pub path: String,
+
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+
+    #[serde(default)]
+    pub max_files: Option<u64>,
+
+    #[serde(default)]
+    pub password: Option<String>,
+
+    #[serde(default)]
+    pub allowed_extensions: Option<Vec<String>>,
+}
+
+/// Omitted fields are left unchanged, while `null` clears optional settings
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UpdateUploadTargetRequest {
+    #[serde(default)]
+    pub path: Option<String>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub name: Option<Option<String>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<DateTime<Utc>>")]
+    pub expires: Option<Option<DateTime<Utc>>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<u64>")]
+    pub max_bytes: Option<Option<u64>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<u64>")]
+    pub max_files: Option<Option<u64>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub password: Option<Option<String>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<Vec<String>>")]
+    pub allowed_extensions: Option<Option<Vec<String>>>,
+}
+
+fn target_permission(
+    root: Uuid,
+    capability: PermissionCapability,
+    administrate: bool,
+) -> Permission {
+    Permission::UploadTargets {
+        root,
+        capability,
+        administrate,
+    }
+}
+
+/// Checks `user`'s access to an existing target, requiring `administrate` for targets they don't own
+fn check_target(
+    user: &User,
+    target: &UploadTarget,
+    capability: PermissionCapability,
+) -> crate::Result<()> {
+    let administrate = target.owner() != user.id();
+    let permissions = user.permissions();
+    if !permissions.has_permission(target_permission(
+        target.root(),
+        PermissionCapability::Read,
+        administrate,
+    )) {
+        Err(crate::Error::UploadTargetNotFound(target.id()))
+    } else if !permissions.has_permission(target_permission(
+        target.root(),
+        capability,
+        administrate,
+    )) {
+        Err(crate::Error::Forbidden)
+    } else {
+        Ok(())
+    }
+}
+
+/// Resolves a target's destination with the owner's access, ensuring it is an existing directory
+async fn check_destination(
+    owner: User,
+    root: Uuid,
+    path: String,
+    roots: &Collection<RootDirectory>,
+    config: &Config,
+) -> crate::Result<String> {
+    let root = roots
+        .get(root.clone())
+        .await?
+        .ok_or(crate::Error::RootNotFound(root))?;
+    let destination = RootAccess::<EditAccess>::new(owner, root, config.clone())?
+        .resolve(path)
+        .await?;
+    match tokio::fs::metadata(&destination.absolute).await {
+        Ok(metadata) if metadata.is_dir() => Ok(destination.display()),
+        Ok(_) => Err(crate::Error::not_a_directory(destination.display())),
+        Err(_) => Err(crate::Error::path_not_found(destination.display())),
+    }
+}
+
+async fn load_target(
+    id: Uuid,
+    user: &User,
+    targets: &Collection<UploadTarget>,
+    capability: PermissionCapability,
+) -> crate::Result<UploadTarget> {
+    let target = targets
+        .get(id.clone())
+        .await?
+        .ok_or(crate::Error::UploadTargetNotFound(id))?;
+    check_target(user, &target, capability)?;
+    Ok(target)
+}
+
+#[openapi(tag = "Upload Targets")]
+#[get("/")]
+async fn list_upload_targets(
+    user: User,
+    targets: Collection<UploadTarget>,
+) -> crate::ApiResult<Vec<UploadTargetInfo>> {
+    let mut cursor = targets.find(doc! {}).await?;
+    let mut visible = Vec::new();
+    while cursor.advance().await? {
+        let target = cursor.deserialize_current()?;
+        if check_target(&user, &target, PermissionCapability::Read).is_ok() {
+            visible.push(target.into());
+        }
+    }
+
+    Ok(Json(visible))
+}
+
+#[openapi(tag = "Upload Targets")]
+#[get("/<id>")]
+async fn get_upload_target(
+    id: Uuid,
+    user: User,
+    targets: Collection<UploadTarget>,
+) -> crate::ApiResult<UploadTargetInfo> {
+    Ok(Json(
+        load_target(id, &user, &targets, PermissionCapability::Read)
+            .await?
+            .into(),
+    ))
+}
+
+#[openapi(tag = "Upload Targets")]
+#[post("/", data = "<body>")]
+async fn create_upload_target(
+    body: Json<CreateUploadTargetRequest>,
+    user: User,
+    targets: Collection<UploadTarget>,
+    roots: Collection<RootDirectory>,
+    config: &State<Config>,
+) -> crate::ApiResult<UploadTargetInfo> {
+    if !user.permissions().has_permission(target_permission(
+        body.root.clone(),
+        PermissionCapability::Manage,
+        false,
+    )) {
+        return Err(crate::Error::Forbidden);
+    }
+
+    let path = check_destination(
+        user.clone(),
+        body.root.clone(),
+        body.path.clone(),
+        &roots,
+        config.inner(),
+    )
+    .await?;
+    let target = UploadTarget::new(body.root.clone(), path, user.id())
+        .with_name(body.name.clone())
+        .with_expires(body.expires)
+        .with_max_bytes(body.max_bytes)
+        .with_max_files(body.max_files)
+        .with_allowed_extensions(body.allowed_extensions.clone())
+        .with_plain_password(body.password.clone())?;
+    let _ = targets.save(target.clone()).await?;
+    Ok(Json(target.into()))
+}
+
+#[openapi(tag = "Upload Targets")]
+#[patch("/<id>", data = "<body>")]
+#[allow(clippy::too_many_arguments)]
+async fn update_upload_target(
+    id: Uuid,
+    body: Json<UpdateUploadTargetRequest>,
+    user: User,
+    targets: Collection<UploadTarget>,
+    roots: Collection<RootDirectory>,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    config: &State<Config>,
+) -> crate::ApiResult<UploadTargetInfo> {
+    let mut target = load_target(id, &user, &targets, PermissionCapability::Edit).await?;
+
+    if let Some(path) = body.path.clone() {
+        // Destinations are always checked against the owner's access, not the editor's
+        let owner = groups
+            .effective_owner(&users, target.owner())
+            .await?
+            .ok_or(crate::Error::UploadTargetNotFound(target.id()))?;
+        let path = check_destination(owner, target.root(), path, &roots, config.inner()).await?;
+        target = target.with_path(path);
+    }
+    if let Some(name) = body.name.clone() {
+        target = target.with_name(name);
+    }
+    if let Some(expires) = body.expires {
+        target = target.with_expires(expires);
+    }
+    if let Some(max_bytes) = body.max_bytes {
+        target = target.with_max_bytes(max_bytes);
+    }
+    if let Some(max_files) = body.max_files {
+        target = target.with_max_files(max_files);
+    }
+    if let Some(allowed_extensions) = body.allowed_extensions.clone() {
+        target = target.with_allowed_extensions(allowed_extensions);
+    }
+    if let Some(password) = body.password.clone() {
+        target = target.with_plain_password(password)?;
+    }
+
+    let _ = targets.save(target.clone()).await?;
+    Ok(Json(target.into()))
+}
+
+#[openapi(tag = "Upload Targets")]
+#[delete("/<id>")]
+async fn delete_upload_target(
+    id: Uuid,
+    user: User,
+    targets: Collection<UploadTarget>,
+) -> crate::Result<()> {
+    let target = load_target(id, &user, &targets, PermissionCapability::Manage).await?;
+    let _ = targets.delete(target.id()).await?;
+    Ok(())
+}
+
+export_routes![
+    list_upload_targets,
+    get_upload_target,
+    create_upload_target,
+    update_upload_target,
+    delete_upload_target
+];
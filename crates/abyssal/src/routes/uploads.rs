@@ -0,0 +1,206 @@
+use std::io::SeekFrom;
+
+use rocket::{
+    State,
+    data::{ByteUnit, Data},
+    delete, get, post, put,
+    serde::json::Json,
+};
+use rocket_okapi::openapi;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::{
+    export_routes,
+    models::{
+        EventPayload, MetaRecord, RootDirectory, TrashItem, UploadSession, User, UserMethods,
+    },
+    types::{Config, DirectoryEntry, Uuid},
+    util::{
+        Collection, EditAccess, EventBus, MetaTree, RootAccess, UploadLocks, fs_ops::move_file,
+    },
+};
+
+/// Loads an upload session owned by `user`, re-checking their access to its root
+async fn session_access(
+    id: Uuid,
+    user: User,
+    uploads: &MetaTree<UploadSession>,
+    roots: &Collection<RootDirectory>,
+    config: &Config,
+) -> crate::Result<(UploadSession, RootAccess<EditAccess>)> {
+    let session = uploads
+        .get(id.to_string())?
+        .filter(|session| session.user() == user.id())
+        .ok_or(crate::Error::UploadNotFound(id))?;
+    let root = roots
+        .get(session.root())
+        .await?
+        .ok_or(crate::Error::RootNotFound(session.root()))?;
+    let access = RootAccess::new(user, root, config.clone())?;
+    Ok((session, access))
+}
+
+#[openapi(tag = "Uploads")]
+#[get("/")]
+async fn list_uploads(
+    user: User,
+    uploads: MetaTree<UploadSession>,
+) -> crate::ApiResult<Vec<UploadSession>> {
+    Ok(Json(
+        uploads
+            .all()?
+            .into_iter()
+            .filter(|session| session.user() == user.id())
+            .collect(),
+    ))
+}
+
+#[openapi(tag = "Uploads")]
+#[get("/<id>")]
+async fn get_upload(
+    id: Uuid,
+    user: User,
+    uploads: MetaTree<UploadSession>,
+) -> crate::ApiResult<UploadSession> {
+    uploads
+        .get(id.to_string())?
+        .filter(|session| session.user() == user.id())
+        .map(Json)
+        .ok_or(crate::Error::UploadNotFound(id))
+}
+
+/// Appends a chunk to an upload. `offset` must equal the session's `received` count,
+/// so interrupted chunks can be resumed by re-reading the session and retrying from there.
+/// Concurrent chunks for the same session are handled one after another.
+#[openapi(tag = "Uploads")]
+#[put("/<id>?<offset>", data = "<chunk>")]
+#[allow(clippy::too_many_arguments)]
+async fn upload_chunk(
+    id: Uuid,
+    offset: u64,
+    chunk: Data<'_>,
+    user: User,
+    uploads: MetaTree<UploadSession>,
+    roots: Collection<RootDirectory>,
+    locks: &State<UploadLocks>,
+    config: &State<Config>,
+) -> crate::ApiResult<UploadSession> {
+    let _guard = locks.lock(&id).await;
+    let (session, _) = session_access(id, user, &uploads, &roots, config.inner()).await?;
+    if offset != session.received() {
+        return Err(crate::Error::UploadOffsetMismatch(session.received()));
+    }
+
+    let staging = session.staging_path(config.inner());
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(&staging)
+        .await?;
+
+    // Discard anything left over from a previously interrupted chunk
+    file.set_len(session.received()).await?;
+    file.seek(SeekFrom::Start(session.received())).await?;
+
+    let remaining = session.size() - session.received();
+    let written = chunk
+        .open(ByteUnit::from(remaining))
+        .stream_to(&mut file)
+        .await?;
+    if !written.complete {
+        file.set_len(session.received()).await?;
+        return Err(crate::Error::UploadTooLarge(ByteUnit::from(session.size())));
+    }
+    file.flush().await?;
+
+    let updated = session.record_chunk(written.written);
+    let _ = uploads.save(updated.clone())?;
+    Ok(Json(updated))
+}
+
+/// Moves a fully-received upload into its destination. A file it overwrites is moved into the trash.
+#[openapi(tag = "Uploads")]
+#[post("/<id>/finalize")]
+#[allow(clippy::too_many_arguments)]
+async fn finalize_upload(
+    id: Uuid,
+    user: User,
+    uploads: MetaTree<UploadSession>,
+    roots: Collection<RootDirectory>,
+    trash: MetaTree<TrashItem>,
+    events: &State<EventBus>,
+    locks: &State<UploadLocks>,
+    config: &State<Config>,
+) -> crate::ApiResult<DirectoryEntry> {
+    let _guard = locks.lock(&id).await;
+    let (session, access) = session_access(id, user, &uploads, &roots, config.inner()).await?;
+    if session.received() != session.size() {
+        return Err(crate::Error::UploadIncomplete(
+            session.received(),
+            session.size(),
+        ));
+    }
+
+    let target = access.resolve(session.path()).await?;
+    if tokio::fs::metadata(&target.absolute)
+        .await
+        .is_ok_and(|existing| existing.is_dir())
+    {
+        return Err(crate::Error::path_conflict(target.display()));
+    }
+
+    let staging = session.staging_path(config.inner());
+    tokio::fs::File::open(&staging).await?.sync_all().await?;
+    let placed = move_file(&staging, &target.absolute, false, target.display());
+    if session.overwrite() && tokio::fs::symlink_metadata(&target.absolute).await.is_ok() {
+        TrashItem::replace(
+            config.inner(),
+            &trash,
+            &session.root(),
+            &target,
+            session.user(),
+            placed,
+        )
+        .await?;
+    } else {
+        placed.await?;
+    }
+    let _ = uploads.delete(session.record_id())?;
+    events.publish(
+        session.user(),
+        EventPayload::UploadCompleted {
+            upload: session.id(),
+            root: session.root(),
+            path: target.display(),
+            size: session.size(),
+        },
+    );
+
+    Ok(Json(
+        DirectoryEntry::read(&target.absolute, &target.relative).await?,
+    ))
+}
+
+#[openapi(tag = "Uploads")]
+#[delete("/<id>")]
+async fn cancel_upload(
+    id: Uuid,
+    user: User,
+    uploads: MetaTree<UploadSession>,
+    locks: &State<UploadLocks>,
+    config: &State<Config>,
+) -> crate::Result<()> {
+    let _guard = locks.lock(&id).await;
+    let session = uploads
+        .get(id.to_string())?
+        .filter(|session| session.user() == user.id())
+        .ok_or(crate::Error::UploadNotFound(id))?;
+    session.discard(config.inner(), &uploads).await
+}
+
+export_routes![
+    list_uploads,
+    get_upload,
+    upload_chunk,
+    finalize_upload,
+    cancel_upload
+];
@@ -1,13 +1,25 @@
 use crate::{
     export_routes,
-    models::{GenericUser, Token, User, UserMethods},
-    types::Uuid,
-    util::Collection,
+    models::{
+        CeremonyState, GenericUser, LoginChallenge, LoginThrottle, LoginThrottleTreeExt, OidcLogin,
+        PasskeyCeremony, PersonalAccess, StoredPasskey, ThrottleSubject, Token, TokenCollectionExt,
+        User, UserKind, UserMethods,
+    },
+    types::{Config, Permission, PermissionSet, Uuid},
+    util::{
+        AuthContext, Collection, EventBus, MetaTree, OidcDiscovery, WebauthnJson,
+        fs_ops::file_name, remove_session_cookies, set_oidc_login_cookie, set_session_cookies,
+        take_oidc_login_cookie, webauthn::relying_party,
+    },
 };
+use std::net::IpAddr;
+
 use bson::doc;
-use rocket::{get, post, serde::json::Json};
+use chrono::{DateTime, Utc};
+use rocket::{State, delete, get, http::CookieJar, patch, post, serde::json::Json};
 use rocket_okapi::{JsonSchema, openapi};
 use serde::{Deserialize, Serialize};
+use webauthn_rs::prelude::{PublicKeyCredential, RequestChallengeResponse};
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 struct LoginRequest {
@@ -21,41 +33,574 @@ struct LoginResponse {
     pub user: GenericUser,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum SecondFactor {
+    Totp,
+    Passkey,
+}
+
+/// Result of a password login: either a session, or a challenge to complete with a second factor
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "status")]
+enum LoginOutcome {
+    Authenticated(LoginResponse),
+
+    /// Complete the login with one of `methods` before `expires`,
+    /// at `/users/login/totp` or `/users/login/passkey`
+    SecondFactorRequired {
+        challenge: String,
+        expires: DateTime<Utc>,
+        methods: Vec<SecondFactor>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct TotpLoginRequest {
+    pub challenge: String,
+
+    /// Current code from the user's authenticator app
+    #[serde(default)]
+    pub code: Option<String>,
+
+    /// One of the user's recovery codes (used up once accepted), instead of `code`
+    #[serde(default)]
+    pub recovery_code: Option<String>,
+}
+
+/// Identifies whose passkeys to authenticate with: either a username (logging in with a passkey
+/// alone), or a challenge from `/users/login` (using a passkey as the second factor)
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum PasskeyLoginRequest {
+    Username(String),
+    Challenge(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct PasskeyLoginChallenge {
+    /// Ceremony ID to pass back when finishing the login
+    pub ceremony: String,
+
+    /// Options for `navigator.credentials.get()`
+    pub options: WebauthnJson<RequestChallengeResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct FinishPasskeyLoginRequest {
+    pub ceremony: String,
+
+    /// Result of `navigator.credentials.get()`
+    pub credential: WebauthnJson<PublicKeyCredential>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct SessionInfo {
+    /// Stable identifier of the session (not the session token)
+    pub id: String,
+    pub created: DateTime<Utc>,
+    pub refreshed: DateTime<Utc>,
+
+    /// When the session expires if left unused (`None` if it never does)
+    pub expires: Option<DateTime<Utc>>,
+
+    /// Whether this is the session making the request
+    pub current: bool,
+}
+
+impl SessionInfo {
+    fn new(token: &Token, auth: &AuthContext, config: &Config) -> Self {
+        Self {
+            id: token.session_id(),
+            created: token.created(),
+            refreshed: token.refreshed(),
+            expires: token.expires(&config.authentication()),
+            current: auth.is_current(token),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct PersonalTokenInfo {
+    /// Stable identifier of the token (not the token itself)
+    pub id: String,
+    pub name: String,
+    pub created: DateTime<Utc>,
+
+    /// Last time the token was used
+    pub refreshed: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub permissions: PermissionSet,
+}
+
+impl PersonalTokenInfo {
+    fn new(token: &Token, personal: PersonalAccess) -> Self {
+        Self {
+            id: token.session_id(),
+            name: personal.name(),
+            created: token.created(),
+            refreshed: token.refreshed(),
+            expires: personal.expires(),
+            permissions: personal.permissions(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreatePersonalTokenRequest {
+    pub name: String,
+    pub expires: DateTime<Utc>,
+
+    /// Permissions to limit the token to, which must be covered by the caller's own
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+/// Personal access token info alongside the token itself, which is only ever returned once
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct CreatedPersonalToken {
+    pub token: Uuid,
+    pub info: PersonalTokenInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct UpdateProfileRequest {
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub display_name: Option<Option<String>>,
+
+    #[serde(default, with = "::serde_with::rust::double_option")]
+    #[schemars(with = "Option<String>")]
+    pub email: Option<Option<String>>,
+}
+
+/// Loose sanity check, as addresses are never used to deliver anything
+fn check_email(email: &str) -> crate::Result<()> {
+    match email.split_once('@') {
+        Some((local, domain))
+            if !local.is_empty()
+                && !domain.is_empty()
+                && !domain.contains('@')
+                && !email.chars().any(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => Err(crate::Error::InvalidEmail(email.to_string())),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct OidcAuthorization {
+    /// Provider URL to send the user to
+    pub url: String,
+    pub state: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+struct OidcCallback {
+    /// Parameters the provider redirected back to the client with
+    pub code: String,
+    pub state: String,
+}
+
+fn login_subjects(username: impl Into<String>, ip: Option<IpAddr>) -> Vec<ThrottleSubject> {
+    let mut subjects = vec![ThrottleSubject::Username(username.into())];
+    if let Some(ip) = ip {
+        subjects.push(ThrottleSubject::Ip(ip));
+    }
+    subjects
+}
+
+/// Issues a session token, also setting it as a cookie for browser clients
+async fn start_session(
+    user: User,
+    tokens: &Collection<Token>,
+    cookies: &CookieJar<'_>,
+    config: &Config,
+) -> crate::Result<LoginResponse> {
+    let new_token = Token::new(user.id());
+    let _ = tokens.save(new_token.clone()).await?;
+    set_session_cookies(cookies, &new_token, &config.authentication());
+    Ok(LoginResponse {
+        token: new_token.id(),
+        user: user.into(),
+    })
+}
+
+/// Logs in as a local user. Repeated failures for the same username or IP address are
+/// throttled with increasing delays, and eventually locked out for a while.
+/// Users with two-factor authentication enabled receive a challenge instead of a session.
 #[openapi(tag = "Users")]
 #[post("/login", data = "<login>")]
+#[allow(clippy::too_many_arguments)]
 async fn login(
     login: Json<LoginRequest>,
+    ip: Option<IpAddr>,
     tokens: Collection<Token>,
     users: Collection<User>,
-) -> crate::ApiResult<LoginResponse> {
+    throttles: MetaTree<LoginThrottle>,
+    challenges: MetaTree<LoginChallenge>,
+    cookies: &CookieJar<'_>,
+    config: &State<Config>,
+) -> crate::ApiResult<LoginOutcome> {
+    let throttle_config = config.authentication().login_throttle();
+    let subjects = login_subjects(login.username.clone(), ip);
+    throttles.check(&subjects, &throttle_config)?;
+
     if let Some(user) = users
         .find_one(doc! {"name": login.username.clone()})
         .await?
+        && user.kind() == UserKind::Local
+        && user.verify_password(login.password.clone())?
     {
-        if user.verify_password(login.password.clone())? {
-            let new_token = Token::new(user.id());
-            let _ = tokens.save(new_token.clone()).await?;
-            Ok(Json(LoginResponse {
-                token: new_token.id(),
-                user: user.into(),
-            }))
-        } else {
-            Err(crate::Error::IncorrectCredentials)
+        if user.disabled() {
+            return Err(crate::Error::AccountDisabled);
+        }
+
+        let mut methods = Vec::new();
+        if user.totp().is_some() {
+            methods.push(SecondFactor::Totp);
         }
+        if !user.passkeys().is_empty() {
+            methods.push(SecondFactor::Passkey);
+        }
+
+        if !methods.is_empty() {
+            for stale in challenges
+                .all()?
+                .into_iter()
+                .filter(LoginChallenge::is_expired)
+            {
+                let _ = challenges.delete(stale.id())?;
+            }
+
+            let challenge = LoginChallenge::new(user.id());
+            let _ = challenges.save(challenge.clone())?;
+            return Ok(Json(LoginOutcome::SecondFactorRequired {
+                challenge: challenge.id(),
+                expires: challenge.expires(),
+                methods,
+            }));
+        }
+
+        // Only the username's counter is reset, so one valid account can't clear an IP's failures
+        let _ = throttles.delete(subjects[0].key())?;
+        Ok(Json(LoginOutcome::Authenticated(
+            start_session(user, &tokens, cookies, config).await?,
+        )))
     } else {
+        throttles.record_failure(&subjects, &throttle_config)?;
         Err(crate::Error::IncorrectCredentials)
     }
 }
 
+/// Completes a login challenge with a TOTP or recovery code. Incorrect codes count as failed logins.
+#[openapi(tag = "Users")]
+#[post("/login/totp", data = "<body>")]
+#[allow(clippy::too_many_arguments)]
+async fn login_totp(
+    body: Json<TotpLoginRequest>,
+    ip: Option<IpAddr>,
+    tokens: Collection<Token>,
+    users: Collection<User>,
+    throttles: MetaTree<LoginThrottle>,
+    challenges: MetaTree<LoginChallenge>,
+    cookies: &CookieJar<'_>,
+    config: &State<Config>,
+) -> crate::ApiResult<LoginResponse> {
+    let challenge = challenges
+        .get(&body.challenge)?
+        .filter(|challenge| !challenge.is_expired())
+        .ok_or(crate::Error::LoginChallengeExpired)?;
+    let Some(User::Local(user)) = users.get(challenge.user()).await? else {
+        return Err(crate::Error::LoginChallengeExpired);
+    };
+
+    let throttle_config = config.authentication().login_throttle();
+    let subjects = login_subjects(user.name(), ip);
+    throttles.check(&subjects, &throttle_config)?;
+    if user.disabled() {
+        return Err(crate::Error::AccountDisabled);
+    }
+
+    // Two-factor authentication may have been reset since the challenge was issued
+    let totp = User::from(user.clone())
+        .totp()
+        .ok_or(crate::Error::LoginChallengeExpired)?;
+    let verified = match (&body.code, &body.recovery_code) {
+        (Some(code), _) => totp.verify_code(code)?,
+        (None, Some(recovery_code)) => totp.use_recovery_code(recovery_code)?,
+        (None, None) => None,
+    };
+    let Some(totp) = verified else {
+        let _ = challenges.save(challenge.clone().with_attempts(challenge.attempts() + 1))?;
+        throttles.record_failure(&subjects, &throttle_config)?;
+        return Err(crate::Error::IncorrectTotpCode);
+    };
+
+    let _ = challenges.delete(challenge.id())?;
+    let _ = throttles.delete(subjects[0].key())?;
+    let user: User = user.with_totp(Some(totp)).into();
+    let _ = users.save(user.clone()).await?;
+    Ok(Json(start_session(user, &tokens, cookies, config).await?))
+}
+
+/// Starts a passkey login, returning options to pass to the browser's WebAuthn API
+#[openapi(tag = "Users")]
+#[post("/login/passkey", data = "<body>")]
+async fn start_passkey_login(
+    body: Json<PasskeyLoginRequest>,
+    ip: Option<IpAddr>,
+    users: Collection<User>,
+    throttles: MetaTree<LoginThrottle>,
+    challenges: MetaTree<LoginChallenge>,
+    ceremonies: MetaTree<PasskeyCeremony>,
+    config: &State<Config>,
+) -> crate::ApiResult<PasskeyLoginChallenge> {
+    let webauthn = relying_party(config)?;
+    let (user, challenge) = match body.into_inner() {
+        PasskeyLoginRequest::Username(username) => {
+            throttles.check(
+                &login_subjects(username.clone(), ip),
+                &config.authentication().login_throttle(),
+            )?;
+            let user = users
+                .find_one(doc! {"kind": "local", "name": username})
+                .await?;
+            (user, None)
+        }
+        PasskeyLoginRequest::Challenge(id) => {
+            let challenge = challenges
+                .get(&id)?
+                .filter(|challenge| !challenge.is_expired())
+                .ok_or(crate::Error::LoginChallengeExpired)?;
+            (users.get(challenge.user()).await?, Some(id))
+        }
+    };
+
+    let passkeys = user
+        .as_ref()
+        .map(|user| user.passkeys())
+        .unwrap_or_default();
+    let Some(user) = user.filter(|_| !passkeys.is_empty()) else {
+        return Err(crate::Error::IncorrectCredentials);
+    };
+    if user.disabled() {
+        return Err(crate::Error::AccountDisabled);
+    }
+
+    for stale in ceremonies
+        .all()?
+        .into_iter()
+        .filter(PasskeyCeremony::is_expired)
+    {
+        let _ = ceremonies.delete(stale.id())?;
+    }
+
+    let (options, state) = webauthn.start_passkey_authentication(
+        &passkeys
+            .iter()
+            .map(|passkey| passkey.passkey())
+            .collect::<Vec<_>>(),
+    )?;
+    let ceremony = PasskeyCeremony::new(
+        user.id(),
+        CeremonyState::Authentication { state, challenge },
+    );
+    let _ = ceremonies.save(ceremony.clone())?;
+    Ok(Json(PasskeyLoginChallenge {
+        ceremony: ceremony.id(),
+        options: WebauthnJson(options),
+    }))
+}
+
+/// Completes a passkey login. Failed assertions count as failed logins.
+#[openapi(tag = "Users")]
+#[post("/login/passkey/finish", data = "<body>")]
+#[allow(clippy::too_many_arguments)]
+async fn finish_passkey_login(
+    body: Json<FinishPasskeyLoginRequest>,
+    ip: Option<IpAddr>,
+    tokens: Collection<Token>,
+    users: Collection<User>,
+    throttles: MetaTree<LoginThrottle>,
+    challenges: MetaTree<LoginChallenge>,
+    ceremonies: MetaTree<PasskeyCeremony>,
+    cookies: &CookieJar<'_>,
+    config: &State<Config>,
+) -> crate::ApiResult<LoginResponse> {
+    let ceremony = ceremonies
+        .delete(&body.ceremony)?
+        .filter(|ceremony| !ceremony.is_expired())
+        .ok_or(crate::Error::WebauthnCeremonyExpired)?;
+    let CeremonyState::Authentication { state, challenge } = ceremony.state() else {
+        return Err(crate::Error::WebauthnCeremonyExpired);
+    };
+    let Some(User::Local(user)) = users.get(ceremony.user()).await? else {
+        return Err(crate::Error::WebauthnCeremonyExpired);
+    };
+
+    let throttle_config = config.authentication().login_throttle();
+    let subjects = login_subjects(user.name(), ip);
+    throttles.check(&subjects, &throttle_config)?;
+    if user.disabled() {
+        return Err(crate::Error::AccountDisabled);
+    }
+    if let Some(challenge) = &challenge
+        && challenges.delete(challenge)?.is_none_or(|c| c.is_expired())
+    {
+        return Err(crate::Error::LoginChallengeExpired);
+    }
+
+    let result =
+        match relying_party(config)?.finish_passkey_authentication(&body.credential.0, &state) {
+            Ok(result) => result,
+            Err(err) => {
+                throttles.record_failure(&subjects, &throttle_config)?;
+                return Err(err.into());
+            }
+        };
+
+    let used = StoredPasskey::credential_id(result.cred_id());
+    let passkeys = user
+        .passkeys()
+        .into_iter()
+        .map(|passkey| {
+            if passkey.id() == used {
+                passkey.with_authentication(&result)
+            } else {
+                passkey
+            }
+        })
+        .collect();
+    let _ = throttles.delete(subjects[0].key())?;
+    let user: User = user.with_passkeys(passkeys).into();
+    let _ = users.save(user.clone()).await?;
+    Ok(Json(start_session(user, &tokens, cookies, config).await?))
+}
+
+/// Starts an OIDC login. The client should send the user to `url`, then pass the `code` and
+/// `state` the provider redirects back with to `/users/oidc/callback`. The login is bound to
+/// this client by a cookie, which must be sent along with the callback.
+#[openapi(tag = "Users")]
+#[get("/oidc/login")]
+async fn oidc_login(
+    config: &State<Config>,
+    discovery: &State<OidcDiscovery>,
+    logins: MetaTree<OidcLogin>,
+    cookies: &CookieJar<'_>,
+) -> crate::ApiResult<OidcAuthorization> {
+    let oidc = config
+        .authentication()
+        .oidc()
+        .ok_or(crate::Error::OidcDisabled)?;
+    let client = discovery.client(oidc).await?;
+
+    // Clear out logins that were abandoned at the provider
+    for stale in logins.all()?.into_iter().filter(OidcLogin::is_expired) {
+        let _ = logins.delete(stale.state())?;
+    }
+
+    let login = OidcLogin::new();
+    let url = client.authorization_url(&login)?;
+    let _ = logins.save(login.clone())?;
+    set_oidc_login_cookie(cookies, &login);
+    Ok(Json(OidcAuthorization {
+        url,
+        state: login.state(),
+    }))
+}
+
+/// Completes an OIDC login, creating the user on their first login and syncing their OIDC groups
+#[openapi(tag = "Users")]
+#[post("/oidc/callback", data = "<callback>")]
+#[allow(clippy::too_many_arguments)]
+async fn oidc_callback(
+    callback: Json<OidcCallback>,
+    config: &State<Config>,
+    discovery: &State<OidcDiscovery>,
+    logins: MetaTree<OidcLogin>,
+    tokens: Collection<Token>,
+    users: Collection<User>,
+    cookies: &CookieJar<'_>,
+) -> crate::ApiResult<LoginResponse> {
+    let oidc = config
+        .authentication()
+        .oidc()
+        .ok_or(crate::Error::OidcDisabled)?;
+    if take_oidc_login_cookie(cookies).as_ref() != Some(&callback.state) {
+        return Err(crate::Error::OidcStateMismatch);
+    }
+    let login = logins
+        .delete(&callback.state)?
+        .filter(|login| !login.is_expired())
+        .ok_or(crate::Error::OidcLoginExpired)?;
+    let identity = discovery
+        .client(oidc)
+        .await?
+        .exchange(&login, callback.code.clone())
+        .await?;
+
+    let user: User = match users
+        .find_one(doc! {"kind": "oidc", "issuer": &identity.issuer, "subject": &identity.subject})
+        .await?
+    {
+        Some(User::Oidc(existing)) if existing.disabled() => {
+            return Err(crate::Error::AccountDisabled);
+        }
+        Some(User::Oidc(existing)) => existing.with_oidc_groups(identity.groups).into(),
+        _ => {
+            let username = file_name(&identity.username)
+                .map_err(|_| crate::Error::InvalidUsername(identity.username.clone()))?;
+            if users.find_one(doc! {"name": &username}).await?.is_some() {
+                return Err(crate::Error::UsernameTaken(username));
+            }
+
+            User::create_oidc(username, identity.issuer, identity.subject, identity.groups)
+        }
+    };
+    let _ = users.save(user.clone()).await?;
+    Ok(Json(start_session(user, &tokens, cookies, config).await?))
+}
+
+/// Revokes the session token used to make this request, clearing any session cookies
 #[openapi(tag = "Users")]
 #[post("/logout")]
-async fn logout(user: User, tokens: Collection<Token>) -> crate::Result<()> {
-    if let Some(existing) = tokens.find_one(doc! {"user": user.id()}).await? {
-        let _ = tokens.delete(existing.id()).await?;
-        Ok(())
-    } else {
-        Ok(())
+async fn logout(
+    auth: AuthContext,
+    tokens: Collection<Token>,
+    events: &State<EventBus>,
+    cookies: &CookieJar<'_>,
+) -> crate::Result<()> {
+    if let Some(current) = auth.token() {
+        tokens.revoke(doc! {"id": current.id()}, events).await?;
     }
+    remove_session_cookies(cookies);
+    Ok(())
+}
+
+/// Revokes every session of the caller, including the current one (personal access tokens are kept).
+/// Only available to session tokens, not personal access tokens or applications.
+#[openapi(tag = "Users")]
+#[post("/logout/all")]
+async fn logout_all(
+    auth: AuthContext,
+    tokens: Collection<Token>,
+    events: &State<EventBus>,
+    cookies: &CookieJar<'_>,
+) -> crate::Result<()> {
+    let current = auth.session()?;
+    tokens
+        .revoke(doc! {"user": current.user(), "personal": null}, events)
+        .await?;
+    remove_session_cookies(cookies);
+    Ok(())
 }
 
 #[openapi(tag = "Users")]
@@ -64,4 +609,221 @@ async fn get_user_self(user: User) -> crate::ApiResult<GenericUser> {
     Ok(Json(user.into()))
 }
 
-export_routes![login, logout, get_user_self];
+/// Changes a local user's password, revoking all of their other sessions.
+/// Only available to session tokens, not personal access tokens or applications.
+#[openapi(tag = "Users")]
+#[post("/self/password", data = "<body>")]
+async fn change_password(
+    body: Json<ChangePasswordRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+    tokens: Collection<Token>,
+    events: &State<EventBus>,
+) -> crate::Result<()> {
+    let current = auth.session()?;
+
+    // The guard's user carries effective permissions, so update the stored user instead
+    let user = users
+        .get(auth.user().id())
+        .await?
+        .ok_or(crate::Error::MissingAuthorization)?;
+    if !user.verify_password(body.current_password.clone())? {
+        return Err(crate::Error::IncorrectCredentials);
+    }
+
+    let _ = users
+        .save(user.with_password(body.new_password.clone())?)
+        .await?;
+    tokens
+        .revoke(
+            doc! {"user": current.user(), "personal": null, "id": {"$ne": current.id()}},
+            events,
+        )
+        .await?;
+    Ok(())
+}
+
+/// Updates a local user's profile. Fields set to `null` are cleared.
+/// Only available to session tokens, not personal access tokens or applications.
+#[openapi(tag = "Users")]
+#[patch("/self/profile", data = "<body>")]
+async fn update_profile(
+    body: Json<UpdateProfileRequest>,
+    auth: AuthContext,
+    users: Collection<User>,
+) -> crate::ApiResult<GenericUser> {
+    auth.session()?;
+    let user = auth.user();
+    let Some(User::Local(mut local)) = users.get(user.id()).await? else {
+        return Err(crate::Error::invalid_user_type([UserKind::Local]));
+    };
+
+    if let Some(display_name) = body.display_name.clone() {
+        local = local.with_display_name(display_name.filter(|name| !name.trim().is_empty()));
+    }
+    if let Some(email) = body.email.clone() {
+        if let Some(email) = &email {
+            check_email(email)?;
+        }
+        local = local.with_email(email);
+    }
+
+    let _ = users.save(local.clone().into()).await?;
+    Ok(Json(
+        User::from(local)
+            .with_permissions(user.permissions())
+            .into(),
+    ))
+}
+
+/// Lists the caller's active (unexpired) sessions
+#[openapi(tag = "Users")]
+#[get("/self/sessions")]
+async fn list_sessions(
+    auth: AuthContext,
+    tokens: Collection<Token>,
+    config: &State<Config>,
+) -> crate::ApiResult<Vec<SessionInfo>> {
+    let mut cursor = tokens
+        .find(doc! {"user": auth.user().id(), "personal": null})
+        .await?;
+    let mut sessions = Vec::new();
+    while cursor.advance().await? {
+        let token = cursor.deserialize_current()?;
+        if !token.is_expired(&config.authentication()) {
+            sessions.push(SessionInfo::new(&token, &auth, config.inner()));
+        }
+    }
+
+    Ok(Json(sessions))
+}
+
+/// Revokes one of the caller's sessions.
+/// Only available to session tokens, not personal access tokens or applications.
+#[openapi(tag = "Users")]
+#[delete("/self/sessions/<id>")]
+async fn revoke_session(
+    id: String,
+    auth: AuthContext,
+    tokens: Collection<Token>,
+    events: &State<EventBus>,
+) -> crate::Result<()> {
+    let current = auth.session()?;
+    let mut cursor = tokens
+        .find(doc! {"user": current.user(), "personal": null})
+        .await?;
+    while cursor.advance().await? {
+        let token = cursor.deserialize_current()?;
+        if token.session_id() == id {
+            tokens.revoke(doc! {"id": token.id()}, events).await?;
+            return Ok(());
+        }
+    }
+
+    Err(crate::Error::SessionNotFound(id))
+}
+
+/// Lists the caller's personal access tokens
+#[openapi(tag = "Users")]
+#[get("/self/tokens")]
+async fn list_personal_tokens(
+    user: User,
+    tokens: Collection<Token>,
+) -> crate::ApiResult<Vec<PersonalTokenInfo>> {
+    let mut cursor = tokens
+        .find(doc! {"user": user.id(), "personal": {"$ne": null}})
+        .await?;
+    let mut personal_tokens = Vec::new();
+    while cursor.advance().await? {
+        let token = cursor.deserialize_current()?;
+        if let Some(personal) = token.personal() {
+            personal_tokens.push(PersonalTokenInfo::new(&token, personal));
+        }
+    }
+
+    Ok(Json(personal_tokens))
+}
+
+/// Creates a personal access token, limited to a subset of the caller's permissions.
+/// Only available to session tokens, so tokens can't extend their own expiry or permissions.
+#[openapi(tag = "Users")]
+#[post("/self/tokens", data = "<body>")]
+async fn create_personal_token(
+    body: Json<CreatePersonalTokenRequest>,
+    auth: AuthContext,
+    tokens: Collection<Token>,
+) -> crate::ApiResult<CreatedPersonalToken> {
+    auth.session()?;
+    let user = auth.user();
+    if user.kind() == UserKind::Application {
+        return Err(crate::Error::invalid_user_type([
+            UserKind::Local,
+            UserKind::Oidc,
+        ]));
+    }
+    if body.expires <= Utc::now() {
+        return Err(crate::Error::InvalidExpiry);
+    }
+
+    let permissions = PermissionSet::new();
+    for permission in body.permissions.clone() {
+        if !user
+            .permissions()
+            .covers(&permission, user.name(), user.name())
+        {
+            return Err(crate::Error::Forbidden);
+        }
+        permissions.set_permission(permission);
+    }
+
+    let token = Token::new_personal(user.id(), body.name.clone(), body.expires, permissions);
+    let _ = tokens.save(token.clone()).await?;
+    let Some(personal) = token.personal() else {
+        return Err(crate::Error::PersonalTokenNotFound(token.session_id()));
+    };
+    Ok(Json(CreatedPersonalToken {
+        token: token.id(),
+        info: PersonalTokenInfo::new(&token, personal),
+    }))
+}
+
+#[openapi(tag = "Users")]
+#[delete("/self/tokens/<id>")]
+async fn revoke_personal_token(
+    id: String,
+    user: User,
+    tokens: Collection<Token>,
+    events: &State<EventBus>,
+) -> crate::Result<()> {
+    let mut cursor = tokens
+        .find(doc! {"user": user.id(), "personal": {"$ne": null}})
+        .await?;
+    while cursor.advance().await? {
+        let token = cursor.deserialize_current()?;
+        if token.session_id() == id {
+            tokens.revoke(doc! {"id": token.id()}, events).await?;
+            return Ok(());
+        }
+    }
+
+    Err(crate::Error::PersonalTokenNotFound(id))
+}
+
+export_routes![
+    login,
+    login_totp,
+    start_passkey_login,
+    finish_passkey_login,
+    oidc_login,
+    oidc_callback,
+    logout,
+    logout_all,
+    get_user_self,
+    change_password,
+    update_profile,
+    list_sessions,
+    revoke_session,
+    list_personal_tokens,
+    create_personal_token,
+    revoke_personal_token
+];
@@ -0,0 +1,362 @@
+use std::path::Path;
+
+use rocket::{
+    State,
+    futures::{SinkExt, StreamExt},
+    get,
+};
+use rocket_okapi::{JsonSchema, openapi};
+use rocket_ws::{Message, WebSocket};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    ErrorMeta, export_routes,
+    models::{EventPayload, Group, RootDirectory, User, UserMethods},
+    types::{
+        Config, PermissionCapability, Uuid,
+        filesystem::{display_relative, normalize_relative},
+    },
+    util::{
+        AuthContext, Collection, EventBus, FsChange, FsWatcher, MessageChannel, ReadAccess,
+        RootAccess, RootPath, WatchGuard, json_message,
+    },
+};
+
+/// Directories a single connection may watch at once
+const MAX_SUBSCRIPTIONS: usize = 64;
+
+/// Message sent by the client
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "action")]
+enum WatchRequest {
+    Subscribe { root: Uuid, path: String },
+    Unsubscribe { root: Uuid, path: String },
+}
+
+/// Message sent by the server. Paths are root-relative.
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+#[serde(rename_all = "snake_case", tag = "type")]
+enum WatchMessage {
+    Subscribed {
+        root: Uuid,
+        path: String,
+    },
+    Unsubscribed {
+        root: Uuid,
+        path: String,
+    },
+    Created {
+        root: Uuid,
+        path: String,
+    },
+    Modified {
+        root: Uuid,
+        path: String,
+    },
+    Deleted {
+        root: Uuid,
+        path: String,
+    },
+    Renamed {
+        root: Uuid,
+        from: String,
+        to: String,
+    },
+
+    /// Changes were dropped because the client didn't keep up, so its listings may be stale
+    Lagged {
+        missed: u64,
+    },
+    Error {
+        error: ErrorMeta,
+    },
+}
+
+/// A directory watched by one connection
+struct Subscription {
+    access: RootAccess<ReadAccess>,
+
+    /// Subscribed directory, with its canonical absolute path
+    directory: RootPath,
+    _guard: WatchGuard,
+}
+
+impl Subscription {
+    fn matches(&self, root: &Uuid, path: &Path) -> bool {
+        self.access.root().id() == *root && self.directory.relative == path
+    }
+
+    /// Root-relative path of `absolute`, if it is directly inside of the subscribed
+    /// directory, isn't Abyssal's own, and is readable by the subscriber
+    fn relative(&self, absolute: &Path, metadata_dir: &Path) -> Option<String> {
+        let name = absolute.file_name()?;
+        let hidden = name.to_string_lossy();
+        if absolute.parent()? != self.directory.absolute
+            || absolute == metadata_dir
+            || (hidden.starts_with(".abyssal-") && hidden.ends_with(".partial"))
+        {
+            return None;
+        }
+
+        let relative = self.directory.relative.join(name);
+        self.access
+            .authorize(&relative, PermissionCapability::Read)
+            .ok()
+            .map(display_relative)
+    }
+
+    fn translate(&self, change: &FsChange, metadata_dir: &Path) -> Option<WatchMessage> {
+        let root = self.access.root().id();
+        match change {
+            FsChange::Created(path) => Some(WatchMessage::Created {
+                root,
+                path: self.relative(path, metadata_dir)?,
+            }),
+            FsChange::Modified(path) => Some(WatchMessage::Modified {
+                root,
+                path: self.relative(path, metadata_dir)?,
+            }),
+            FsChange::Deleted(path) => Some(WatchMessage::Deleted {
+                root,
+                path: self.relative(path, metadata_dir)?,
+            }),
+
+            // Renames across the subscription's boundary look like a creation or deletion
+            FsChange::Renamed { from, to } => match (
+                self.relative(from, metadata_dir),
+                self.relative(to, metadata_dir),
+            ) {
+                (Some(from), Some(to)) => Some(WatchMessage::Renamed { root, from, to }),
+                (Some(path), None) => Some(WatchMessage::Deleted { root, path }),
+                (None, Some(path)) => Some(WatchMessage::Created { root, path }),
+                (None, None) => None,
+            },
+        }
+    }
+}
+
+async fn subscribe(
+    user: &User,
+    root: Uuid,
+    path: &str,
+    roots: &Collection<RootDirectory>,
+    watcher: &FsWatcher,
+    config: &Config,
+) -> crate::Result<Subscription> {
+    let root = roots
+        .get(root.clone())
+        .await?
+        .ok_or(crate::Error::RootNotFound(root))?;
+    let access = RootAccess::<ReadAccess>::new(user.clone(), root, config.clone())?;
+    let directory = access.resolve(path).await?;
+    let absolute = tokio::fs::canonicalize(&directory.absolute)
+        .await
+        .map_err(|_| crate::Error::path_not_found(directory.display()))?;
+    if !tokio::fs::metadata(&absolute).await?.is_dir() {
+        return Err(crate::Error::not_a_directory(directory.display()));
+    }
+
+    Ok(Subscription {
+        _guard: watcher.watch(&absolute)?,
+        directory: RootPath {
+            relative: directory.relative,
+            absolute,
+        },
+        access,
+    })
+}
+
+/// Re-authorizes `subscriptions` for `user` (whose permissions changed), dropping the ones
+/// they lost access to. Returns the `unsubscribed` messages for the dropped ones.
+async fn reauthorize(
+    subscriptions: &mut Vec<Subscription>,
+    user: &User,
+    config: &Config,
+) -> Vec<WatchMessage> {
+    let mut dropped = Vec::new();
+    for mut subscription in std::mem::take(subscriptions) {
+        let access =
+            RootAccess::<ReadAccess>::new(user.clone(), subscription.access.root(), config.clone());
+        let resolved = match &access {
+            Ok(access) => access.resolve(&subscription.directory.relative).await.ok(),
+            Err(_) => None,
+        };
+        match (access, resolved) {
+            (Ok(access), Some(_)) => {
+                subscription.access = access;
+                subscriptions.push(subscription);
+            }
+            _ => dropped.push(WatchMessage::Unsubscribed {
+                root: subscription.access.root().id(),
+                path: subscription.directory.display(),
+            }),
+        }
+    }
+
+    dropped
+}
+
+/// Applies a client message to the connection's subscriptions
+async fn handle_request(
+    text: &str,
+    subscriptions: &mut Vec<Subscription>,
+    user: &User,
+    roots: &Collection<RootDirectory>,
+    watcher: &FsWatcher,
+    config: &Config,
+) -> crate::Result<WatchMessage> {
+    let request = serde_json::from_str::<WatchRequest>(text)
+        .map_err(|err| crate::Error::InvalidMessage(err.to_string()))?;
+    match request {
+        WatchRequest::Subscribe { root, path } => {
+            let subscription = subscribe(user, root, &path, roots, watcher, config).await?;
+            let (root, path) = (
+                subscription.access.root().id(),
+                subscription.directory.display(),
+            );
+            if !subscriptions
+                .iter()
+                .any(|existing| existing.matches(&root, &subscription.directory.relative))
+            {
+                if subscriptions.len() >= MAX_SUBSCRIPTIONS {
+                    return Err(crate::Error::TooManySubscriptions(MAX_SUBSCRIPTIONS));
+                }
+                subscriptions.push(subscription);
+            }
+
+            Ok(WatchMessage::Subscribed { root, path })
+        }
+        WatchRequest::Unsubscribe { root, path } => {
+            let relative = normalize_relative(&path)?;
+            subscriptions.retain(|existing| !existing.matches(&root, &relative));
+            Ok(WatchMessage::Unsubscribed {
+                root,
+                path: display_relative(relative),
+            })
+        }
+    }
+}
+
+/// WebSocket notifying the client of changes to the directories it subscribes to.
+///
+/// The client sends `{"action": "subscribe", "root": <root>, "path": <directory>}` (or `unsubscribe`)
+/// text messages, each answered by a `subscribed`/`unsubscribed` or `error` message. The server then
+/// sends `created`, `modified`, `deleted` and `renamed` messages for entries directly inside of the
+/// subscribed directories, limited to paths the caller can read.
+///
+/// When the caller's permissions change, subscriptions they can no longer read are dropped with an
+/// `unsubscribed` message. The connection is closed once the session it was opened with is
+/// revoked, or the caller is disabled. Connections authenticated by the session cookie must be opened from this server's own origin.
+#[openapi(tag = "Events")]
+#[get("/")]
+#[allow(clippy::too_many_arguments)]
+async fn watch(
+    ws: WebSocket,
+    auth: AuthContext,
+    users: Collection<User>,
+    groups: Collection<Group>,
+    roots: Collection<RootDirectory>,
+    events: &State<EventBus>,
+    watcher: &State<FsWatcher>,
+    config: &State<Config>,
+) -> MessageChannel<WatchMessage> {
+    let (watcher, config) = (watcher.inner().clone(), config.inner().clone());
+    let session = auth.token().map(|token| token.session_id());
+    let mut user = auth.user();
+    let mut receiver = events.subscribe(&user.id());
+
+    // Applications are also limited by their owner, whose permission changes go to the owner
+    let mut owner_receiver = match &user {
+        User::Application(application) => Some(events.subscribe(&application.owner())),
+        _ => None,
+    };
+    ws.channel(move |mut stream| {
+        Box::pin(async move {
+            let metadata_dir = tokio::fs::canonicalize(config.filesystem().metadata_dir())
+                .await
+                .unwrap_or_else(|_| config.filesystem().metadata_dir());
+            let mut changes = watcher.changes();
+            let mut subscriptions = Vec::new();
+            loop {
+                let mut reload = false;
+                tokio::select! {
+                    message = stream.next() => match message {
+                        Some(Ok(Message::Text(text))) => {
+                            let reply = handle_request(
+                                &text,
+                                &mut subscriptions,
+                                &user,
+                                &roots,
+                                &watcher,
+                                &config,
+                            )
+                            .await
+                            .unwrap_or_else(|err| WatchMessage::Error {
+                                error: err.metadata(),
+                            });
+                            stream.send(json_message(&reply)).await?;
+                        }
+                        Some(Ok(Message::Close(_))) | None => break,
+                        Some(Ok(_)) => {}
+                        Some(Err(err)) => return Err(err),
+                    },
+                    change = changes.recv() => match change {
+                        Ok(change) => {
+                            for subscription in &subscriptions {
+                                if let Some(message) = subscription.translate(&change, &metadata_dir) {
+                                    stream.send(json_message(&message)).await?;
+                                }
+                            }
+                        }
+                        Err(RecvError::Lagged(missed)) => {
+                            stream.send(json_message(&WatchMessage::Lagged { missed })).await?;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    event = receiver.recv() => match event {
+                        Ok(event) => match event.payload() {
+                            EventPayload::SessionsRevoked { sessions }
+                                if session.as_ref().is_some_and(|session| sessions.contains(session)) =>
+                            {
+                                break;
+                            }
+                            EventPayload::PermissionsChanged => reload = true,
+                            _ => {}
+                        },
+
+                        // Missed events may have changed permissions too
+                        Err(RecvError::Lagged(_)) => reload = true,
+                        Err(RecvError::Closed) => break,
+                    },
+                    event = async { owner_receiver.as_mut().unwrap().recv().await }, if owner_receiver.is_some() => match event {
+                        // Disabling the owner revokes their sessions
+                        Ok(event) => {
+                            reload = matches!(
+                                event.payload(),
+                                EventPayload::PermissionsChanged | EventPayload::SessionsRevoked { .. }
+                            );
+                        }
+                        Err(RecvError::Lagged(_)) => reload = true,
+                        Err(RecvError::Closed) => break,
+                    },
+                }
+
+                if reload {
+                    let Ok(Some(current)) = auth.reload(&users, &groups).await else {
+                        break;
+                    };
+                    user = current;
+                    for message in reauthorize(&mut subscriptions, &user, &config).await {
+                        stream.send(json_message(&message)).await?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    })
+    .into()
+}
+
+export_routes![watch];
@@ -118,6 +118,148 @@ impl ServerConfig {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
+#[serde(rename_all = "snake_case")]
+#[getset(get_clone = "pub")]
+pub struct OidcConfig {
+    /// Issuer URL, used for discovery (`<issuer>/.well-known/openid-configuration`)
+    issuer: String,
+    client_id: String,
+
+    /// Client secret (omit for public clients, which rely on PKCE alone)
+    #[serde(default)]
+    client_secret: Option<String>,
+
+    /// Client URL the provider redirects back to (the client then completes the login)
+    redirect_url: String,
+
+    #[serde(default = "OidcConfig::_d_scopes")]
+    scopes: Vec<String>,
+
+    /// Claim used as the username of new OIDC users
+    #[serde(default = "OidcConfig::_d_username_claim")]
+    username_claim: String,
+
+    /// Claim containing the user's groups, synced into `oidc_groups` on every login
+    #[serde(default = "OidcConfig::_d_groups_claim")]
+    groups_claim: String,
+}
+
+impl OidcConfig {
+    fn _d_scopes() -> Vec<String> {
+        vec![
+            String::from("openid"),
+            String::from("profile"),
+            String::from("email"),
+        ]
+    }
+
+    fn _d_username_claim() -> String {
+        String::from("preferred_username")
+    }
+
+    fn _d_groups_claim() -> String {
+        String::from("groups")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
+#[serde(rename_all = "snake_case")]
+#[getset(get_clone = "pub")]
+pub struct WebauthnConfig {
+    /// Relying party ID: the domain passkeys are bound to (e.g. `files.example.com`)
+    rp_id: String,
+
+    /// Origin the client is served from, which must be within `rp_id`
+    origin: String,
+
+    /// Name shown by authenticators
+    #[serde(default = "WebauthnConfig::_d_rp_name")]
+    rp_name: String,
+}
+
+impl WebauthnConfig {
+    fn _d_rp_name() -> String {
+        String::from("Abyssal")
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
+#[serde(rename_all = "snake_case")]
+#[getset(get_clone = "pub")]
+pub struct LoginThrottleConfig {
+    #[serde(default = "LoginThrottleConfig::_d_enabled")]
+    enabled: bool,
+
+    /// Failed logins allowed (per username & per IP) before delays kick in
+    #[serde(default = "LoginThrottleConfig::_d_free_attempts")]
+    free_attempts: u32,
+
+    /// Delay in seconds after the first throttled failure, doubling with each further failure
+    #[serde(default = "LoginThrottleConfig::_d_base_delay")]
+    base_delay: u64,
+
+    /// Upper bound of the delay between attempts, in seconds
+    #[serde(default = "LoginThrottleConfig::_d_max_delay")]
+    max_delay: u64,
+
+    /// Failed logins after which the username/IP is locked out entirely (`0` to never lock out)
+    #[serde(default = "LoginThrottleConfig::_d_lockout_threshold")]
+    lockout_threshold: u32,
+
+    /// Length of a lockout, in seconds
+    #[serde(default = "LoginThrottleConfig::_d_lockout_duration")]
+    lockout_duration: u64,
+
+    /// Seconds without a failed login after which the failure count resets
+    #[serde(default = "LoginThrottleConfig::_d_reset_after")]
+    reset_after: u64,
+}
+
+impl LoginThrottleConfig {
+    fn _d_enabled() -> bool {
+        true
+    }
+
+    fn _d_free_attempts() -> u32 {
+        5
+    }
+
+    fn _d_base_delay() -> u64 {
+        1
+    }
+
+    fn _d_max_delay() -> u64 {
+        5 * 60
+    }
+
+    fn _d_lockout_threshold() -> u32 {
+        20
+    }
+
+    fn _d_lockout_duration() -> u64 {
+        15 * 60
+    }
+
+    fn _d_reset_after() -> u64 {
+        60 * 60
+    }
+}
+
+impl Default for LoginThrottleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: Self::_d_enabled(),
+            free_attempts: Self::_d_free_attempts(),
+            base_delay: Self::_d_base_delay(),
+            max_delay: Self::_d_max_delay(),
+            lockout_threshold: Self::_d_lockout_threshold(),
+            lockout_duration: Self::_d_lockout_duration(),
+            reset_after: Self::_d_reset_after(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
 #[serde(rename_all = "snake_case")]
 #[getset(get_clone = "pub")]
@@ -127,6 +269,26 @@ pub struct AuthConfig {
 
     #[serde(default = "AuthConfig::_d_admin_password")]
     admin_password: String,
+
+    /// OpenID Connect provider to allow logins from (disabled if omitted)
+    #[serde(default)]
+    oidc: Option<OidcConfig>,
+
+    /// WebAuthn relying party settings, required for passkeys (disabled if omitted)
+    #[serde(default)]
+    webauthn: Option<WebauthnConfig>,
+
+    /// Maximum age of a session token in seconds, regardless of activity (`0` to never expire)
+    #[serde(default = "AuthConfig::_d_session_lifetime")]
+    session_lifetime: u64,
+
+    /// Seconds a session token may go unused before it expires (`0` to never expire)
+    #[serde(default = "AuthConfig::_d_session_idle_timeout")]
+    session_idle_timeout: u64,
+
+    /// Backoff & lockout of repeated failed logins
+    #[serde(default)]
+    login_throttle: LoginThrottleConfig,
 }
 
 impl AuthConfig {
@@ -137,6 +299,14 @@ impl AuthConfig {
     fn _d_admin_password() -> String {
         String::from("admin")
     }
+
+    fn _d_session_lifetime() -> u64 {
+        30 * 24 * 60 * 60
+    }
+
+    fn _d_session_idle_timeout() -> u64 {
+        7 * 24 * 60 * 60
+    }
 }
 
 impl Default for AuthConfig {
@@ -144,6 +314,11 @@ impl Default for AuthConfig {
         Self {
             admin_user: Self::_d_admin_user(),
             admin_password: Self::_d_admin_password(),
+            oidc: None,
+            webauthn: None,
+            session_lifetime: Self::_d_session_lifetime(),
+            session_idle_timeout: Self::_d_session_idle_timeout(),
+            login_throttle: LoginThrottleConfig::default(),
         }
     }
 }
@@ -191,6 +366,38 @@ impl Default for FilesystemRootConfig {
     }
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
+#[serde(rename_all = "snake_case")]
+#[getset(get_clone = "pub")]
+pub struct TrashConfig {
+    /// Move deleted files to the trash instead of unlinking them
+    #[serde(default = "TrashConfig::_d_enabled")]
+    enabled: bool,
+
+    /// Seconds deleted files are kept in the trash before being purged (`0` to keep them forever)
+    #[serde(default = "TrashConfig::_d_retention")]
+    retention: u64,
+}
+
+impl TrashConfig {
+    fn _d_enabled() -> bool {
+        true
+    }
+
+    fn _d_retention() -> u64 {
+        30 * 24 * 60 * 60
+    }
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            enabled: Self::_d_enabled(),
+            retention: Self::_d_retention(),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, CloneGetters)]
 #[serde(rename_all = "snake_case")]
 #[getset(get_clone = "pub")]
@@ -206,6 +413,14 @@ pub struct FilesystemConfig {
     /// Filesystem roots to automatically create/configure
     #[serde(default = "FilesystemConfig::_d_directories")]
     directories: HashMap<String, FilesystemRootConfig>,
+
+    /// Recycle bin for deleted files
+    #[serde(default)]
+    trash: TrashConfig,
+
+    /// Seconds an upload session may go without receiving data before it's discarded (`0` to keep them forever)
+    #[serde(default = "FilesystemConfig::_d_upload_expiry")]
+    upload_expiry: u64,
 }
 
 impl FilesystemConfig {
@@ -220,6 +435,15 @@ impl FilesystemConfig {
     fn _d_directories() -> HashMap<String, FilesystemRootConfig> {
         HashMap::from_iter(vec![("root".to_string(), FilesystemRootConfig::default())])
     }
+
+    fn _d_upload_expiry() -> u64 {
+        24 * 60 * 60
+    }
+
+    /// Directory holding Abyssal's own metadata (never exposed through a root)
+    pub fn metadata_dir(&self) -> PathBuf {
+        self.filesystem.join(".abyssal")
+    }
 }
 
 impl Default for FilesystemConfig {
@@ -227,7 +451,9 @@ impl Default for FilesystemConfig {
         Self {
             filesystem: Self::_d_filesystem(),
             allow_root_modification: Self::_d_allow_root_modification(),
-            directories: Self::_d_directories()
+            directories: Self::_d_directories(),
+            trash: Default::default(),
+            upload_expiry: Self::_d_upload_expiry(),
         }
     }
 }
@@ -0,0 +1,242 @@
+use std::{
+    cmp::Ordering,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use rocket::FromFormField;
+use rocket_okapi::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Directory,
+    File,
+    Symlink,
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq, FromFormField)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingSort {
+    Name,
+    Size,
+    Modified,
+    Kind,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq, FromFormField)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// What to do when the destination of a file operation already exists
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictStrategy {
+    /// Fail with a `path.conflict` error
+    #[default]
+    Fail,
+
+    /// Replace the existing entry (requires `manage` on it), moving it into the trash
+    Overwrite,
+
+    /// Pick the first free name of the form `name (n).ext`
+    Rename,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct DirectoryEntry {
+    pub name: String,
+
+    /// Path of the entry, relative to its root directory
+    pub path: String,
+    pub kind: EntryKind,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+
+    /// Unix permission bits
+    pub mode: u32,
+
+    /// Link target, if this entry is a symlink
+    pub symlink_target: Option<String>,
+}
+
+impl DirectoryEntry {
+    /// Reads an entry's metadata without following symlinks
+    pub async fn read(
+        absolute: impl AsRef<Path>,
+        relative: impl AsRef<Path>,
+    ) -> crate::Result<Self> {
+        let absolute = absolute.as_ref();
+        let metadata = tokio::fs::symlink_metadata(absolute).await?;
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            EntryKind::Symlink
+        } else if file_type.is_dir() {
+            EntryKind::Directory
+        } else if file_type.is_file() {
+            EntryKind::File
+        } else {
+            EntryKind::Other
+        };
+
+        let symlink_target = if kind == EntryKind::Symlink {
+            tokio::fs::read_link(absolute)
+                .await
+                .ok()
+                .map(|target| target.to_string_lossy().to_string())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name: absolute
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: display_relative(relative),
+            kind,
+            size: metadata.len(),
+            modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            mode: metadata.permissions().mode(),
+            symlink_target,
+        })
+    }
+}
+
+/// Reads the entries of the directory at `absolute`, skipping `hidden` (the metadata directory).
+/// Entry paths are reported relative to `relative`.
+pub async fn read_directory(
+    absolute: impl AsRef<Path>,
+    relative: impl AsRef<Path>,
+    hidden: impl AsRef<Path>,
+) -> crate::Result<Vec<DirectoryEntry>> {
+    let (absolute, relative) = (absolute.as_ref(), relative.as_ref());
+    match tokio::fs::metadata(absolute).await {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => return Err(crate::Error::not_a_directory(display_relative(relative))),
+        Err(_) => return Err(crate::Error::path_not_found(display_relative(relative))),
+    }
+
+    let mut reader = tokio::fs::read_dir(absolute).await?;
+    let mut entries = Vec::new();
+    while let Some(item) = reader.next_entry().await? {
+        if item.path() == hidden.as_ref() {
+            continue;
+        }
+
+        // Entries may disappear while the directory is being read
+        if let Ok(entry) = DirectoryEntry::read(item.path(), relative.join(item.file_name())).await
+        {
+            entries.push(entry);
+        }
+    }
+
+    Ok(entries)
+}
+
+pub fn sort_entries(
+    entries: &mut [DirectoryEntry],
+    sort: ListingSort,
+    order: SortOrder,
+    directories_first: bool,
+) {
+    entries.sort_by(|a, b| {
+        let grouping = if directories_first {
+            (b.kind == EntryKind::Directory).cmp(&(a.kind == EntryKind::Directory))
+        } else {
+            Ordering::Equal
+        };
+
+        let ordering = match sort {
+            ListingSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            ListingSort::Size => a.size.cmp(&b.size),
+            ListingSort::Modified => a.modified.cmp(&b.modified),
+            ListingSort::Kind => a.kind.cmp(&b.kind),
+        }
+        .then_with(|| a.name.cmp(&b.name));
+
+        grouping.then(match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        })
+    });
+}
+
+/// Entries a directory listing returns if the client doesn't ask for a number
+pub const DEFAULT_LISTING_LIMIT: usize = 1000;
+
+/// Most entries a single directory listing returns
+pub const MAX_LISTING_LIMIT: usize = 10000;
+
+/// Page size of a directory listing, applying the default and maximum to the requested `limit`
+pub fn listing_limit(limit: Option<usize>) -> usize {
+    limit
+        .unwrap_or(DEFAULT_LISTING_LIMIT)
+        .min(MAX_LISTING_LIMIT)
+}
+
+/// Lexically normalizes a client-supplied path into one relative to its root, rejecting any attempt to climb above it
+pub fn normalize_relative(path: impl AsRef<Path>) -> crate::Result<PathBuf> {
+    let path = path.as_ref();
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return Err(crate::Error::path_outside_root(path));
+                }
+            }
+            Component::Normal(part) => normalized.push(part),
+        }
+    }
+
+    Ok(normalized)
+}
+
+/// Formats a root-relative path for display to clients (always `/`-prefixed)
+pub fn display_relative(path: impl AsRef<Path>) -> String {
+    format!("/{}", path.as_ref().to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_within_the_root() {
+        assert_eq!(normalize_relative("a/./b/").unwrap(), PathBuf::from("a/b"));
+        assert_eq!(normalize_relative("a/../b").unwrap(), PathBuf::from("b"));
+        assert_eq!(normalize_relative("").unwrap(), PathBuf::new());
+        assert_eq!(normalize_relative("a/..").unwrap(), PathBuf::new());
+    }
+
+    #[test]
+    fn treats_absolute_paths_as_root_relative() {
+        assert_eq!(
+            normalize_relative("/etc/passwd").unwrap(),
+            PathBuf::from("etc/passwd")
+        );
+        assert_eq!(normalize_relative("//a//b").unwrap(), PathBuf::from("a/b"));
+        assert_eq!(normalize_relative("/").unwrap(), PathBuf::new());
+    }
+
+    #[test]
+    fn rejects_climbing_above_the_root() {
+        for path in ["..", "../etc", "a/../../b", "/..", "a/b/../../../c"] {
+            assert!(
+                matches!(
+                    normalize_relative(path),
+                    Err(crate::Error::PathOutsideRoot(_))
+                ),
+                "{path} escaped"
+            );
+        }
+    }
+}
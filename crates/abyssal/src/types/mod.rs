@@ -5,4 +5,7 @@ mod str_uuid;
 pub use str_uuid::Uuid;
 
 mod permission;
-pub use permission::{Permission, PermissionCapability, PermissionKind, PermissionSet};
\ No newline at end of file
+pub use permission::{Permission, PermissionCapability, PermissionKind, PermissionSet, RootScope, RootTopLevel};
+
+pub mod filesystem;
+pub use filesystem::{ConflictStrategy, DirectoryEntry, EntryKind, ListingSort, SortOrder};
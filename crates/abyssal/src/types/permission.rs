@@ -1,10 +1,13 @@
-use std::sync::Arc;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use parking_lot::RwLock;
 use rocket_okapi::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::types::Uuid;
+use crate::types::{Uuid, filesystem::normalize_relative};
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -37,12 +40,33 @@ pub enum RootTopLevel {
     Directory { path: String },
 }
 
+impl RootTopLevel {
+    /// Root-relative directory this top level confines a user to
+    pub fn scope(&self, username: impl AsRef<str>) -> crate::Result<PathBuf> {
+        match self {
+            RootTopLevel::Root => Ok(PathBuf::new()),
+            RootTopLevel::Home { parent } => {
+                Ok(normalize_relative(parent)?.join(normalize_relative(username.as_ref())?))
+            }
+            RootTopLevel::Directory { path } => normalize_relative(path),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+pub struct RootScope {
+    /// Root-relative directory the user is confined to
+    pub path: String,
+    pub capability: PermissionCapability,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum PermissionKind {
     Administrator,
     Invites,
     UploadTargets,
+    Shares,
     RootDirectory,
 }
 
@@ -81,6 +105,18 @@ pub enum Permission {
         administrate: bool,
     },
 
+    /// Public share link management
+    Shares {
+        /// Roots allowed to create share links for
+        root: Uuid,
+
+        /// What level of access to grant
+        capability: PermissionCapability,
+
+        /// Whether to allow access to share links owned by other users
+        administrate: bool,
+    },
+
     /// Access to a root directory
     RootDirectory {
         /// Root ID
@@ -100,6 +136,7 @@ impl Permission {
             Permission::Administrator => PermissionKind::Administrator,
             Permission::Invites { .. } => PermissionKind::Invites,
             Permission::UploadTargets { .. } => PermissionKind::UploadTargets,
+            Permission::Shares { .. } => PermissionKind::Shares,
             Permission::RootDirectory { .. } => PermissionKind::RootDirectory,
         }
     }
@@ -109,6 +146,7 @@ impl Permission {
             Permission::Administrator => PermissionCapability::Manage,
             Permission::Invites { capability, .. } => capability,
             Permission::UploadTargets { capability, .. } => capability,
+            Permission::Shares { capability, .. } => capability,
             Permission::RootDirectory { capability, .. } => capability,
         }
     }
@@ -118,6 +156,7 @@ impl Permission {
             Permission::Administrator => None,
             Permission::Invites { root, .. }
             | Permission::UploadTargets { root, .. }
+            | Permission::Shares { root, .. }
             | Permission::RootDirectory { root, .. } => Some(root),
         }
     }
@@ -126,7 +165,8 @@ impl Permission {
         match self.clone() {
             Permission::Administrator => true,
             Permission::Invites { administrate, .. }
-            | Permission::UploadTargets { administrate, .. } => administrate,
+            | Permission::UploadTargets { administrate, .. }
+            | Permission::Shares { administrate, .. } => administrate,
             Permission::RootDirectory { .. } => false,
         }
     }
@@ -158,9 +198,9 @@ impl From<Vec<Permission>> for PermissionSet {
     }
 }
 
-impl Into<Vec<Permission>> for PermissionSet {
-    fn into(self) -> Vec<Permission> {
-        self.0.write().clone()
+impl From<PermissionSet> for Vec<Permission> {
+    fn from(value: PermissionSet) -> Self {
+        value.0.write().clone()
     }
 }
 
@@ -169,7 +209,7 @@ impl PermissionSet {
         Self(Arc::new(RwLock::new(Vec::new())))
     }
 
-    pub fn set_permission(&self, permission: Permission) -> () {
+    pub fn set_permission(&self, permission: Permission) {
         let mut set = self.0.write_arc();
         match permission.clone() {
             Permission::Administrator => {
@@ -204,6 +244,20 @@ impl PermissionSet {
                     .collect();
                 set.push(permission);
             }
+            Permission::Shares { root, .. } => {
+                *set = set
+                    .clone()
+                    .into_iter()
+                    .filter(|perm| match perm.clone() {
+                        Permission::Shares {
+                            root: existing_root,
+                            ..
+                        } => root != existing_root,
+                        _ => true,
+                    })
+                    .collect();
+                set.push(permission);
+            }
             Permission::RootDirectory { root, .. } => {
                 *set = set
                     .clone()
@@ -221,7 +275,7 @@ impl PermissionSet {
         }
     }
 
-    pub fn remove_permission(&self, permission: Permission) -> () {
+    pub fn remove_permission(&self, permission: Permission) {
         let mut set = self.0.write_arc();
         *set = set
             .clone()
@@ -235,6 +289,8 @@ impl PermissionSet {
         set.contains(&Permission::Administrator)
     }
 
+    /// Checks whether this set grants `permission`. Permissions allowing to administrate others'
+    /// resources also grant managing one's own, so they satisfy non-administrating requests.
     pub fn has_permission(&self, permission: Permission) -> bool {
         let set = self.0.read();
         if self.is_administrator() {
@@ -243,15 +299,162 @@ impl PermissionSet {
             for perm in set.clone() {
                 if perm.kind() == permission.kind()
                     && perm.root() == permission.root()
-                    && perm.administrate() == permission.administrate()
+                    && (perm.administrate() || !permission.administrate())
+                    && perm.capability().has_at_least(permission.capability())
                 {
-                    if perm.capability().has_at_least(permission.capability()) {
-                        return true;
-                    }
+                    return true;
                 }
             }
 
             false
         }
     }
+
+    /// Combined set granting everything either set grants
+    pub fn union(&self, other: &PermissionSet) -> PermissionSet {
+        if self.is_administrator() || other.is_administrator() {
+            return vec![Permission::Administrator].into();
+        }
+
+        let mut combined = self.0.read().clone();
+        for perm in other.0.read().iter() {
+            if !combined.contains(perm) {
+                combined.push(perm.clone());
+            }
+        }
+        combined.into()
+    }
+
+    /// Checks whether this set (belonging to `holder`) grants everything `permission` would,
+    /// were it held by `username`. Used to keep delegated permissions within their owner's.
+    pub fn covers(
+        &self,
+        permission: &Permission,
+        holder: impl AsRef<str>,
+        username: impl AsRef<str>,
+    ) -> bool {
+        match permission {
+            Permission::Administrator => self.is_administrator(),
+            Permission::RootDirectory {
+                root,
+                top_level,
+                capability,
+            } => top_level.scope(username.as_ref()).is_ok_and(|scope| {
+                self.can_access(root, scope, capability.clone(), holder.as_ref())
+            }),
+            other => self.has_permission(other.clone()),
+        }
+    }
+
+    /// Copy of this set (held by `username`) without any permission `limit` (held by `holder`) doesn't cover
+    pub fn restrict(
+        &self,
+        limit: &PermissionSet,
+        holder: impl AsRef<str>,
+        username: impl AsRef<str>,
+    ) -> PermissionSet {
+        let set = self.0.read();
+        set.iter()
+            .filter(|perm| limit.covers(perm, holder.as_ref(), username.as_ref()))
+            .cloned()
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// Checks whether `path` (relative to `root`) may be accessed with `capability`,
+    /// honoring the `top_level` scope of each matching permission
+    pub fn can_access(
+        &self,
+        root: &Uuid,
+        path: impl AsRef<Path>,
+        capability: PermissionCapability,
+        username: impl AsRef<str>,
+    ) -> bool {
+        if self.is_administrator() {
+            return true;
+        }
+
+        let Ok(path) = normalize_relative(path) else {
+            return false;
+        };
+        self.scopes(root, username)
+            .into_iter()
+            .any(|(scope, granted)| {
+                granted.has_at_least(capability.clone()) && path.starts_with(scope)
+            })
+    }
+
+    /// All directories within `root` this set grants access to, alongside their capability
+    pub fn scopes(
+        &self,
+        root: &Uuid,
+        username: impl AsRef<str>,
+    ) -> Vec<(PathBuf, PermissionCapability)> {
+        if self.is_administrator() {
+            return vec![(PathBuf::new(), PermissionCapability::Manage)];
+        }
+
+        let set = self.0.read();
+        set.iter()
+            .filter_map(|perm| match perm {
+                Permission::RootDirectory {
+                    root: existing_root,
+                    top_level,
+                    capability,
+                } if existing_root == root => top_level
+                    .scope(username.as_ref())
+                    .ok()
+                    .map(|scope| (scope, capability.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn targets(root: &Uuid, capability: PermissionCapability, administrate: bool) -> Permission {
+        Permission::UploadTargets {
+            root: root.clone(),
+            capability,
+            administrate,
+        }
+    }
+
+    #[test]
+    fn administrating_grants_own_resources() {
+        let root = Uuid::new();
+        let set = PermissionSet::from(vec![targets(&root, PermissionCapability::Edit, true)]);
+        assert!(set.has_permission(targets(&root, PermissionCapability::Edit, false)));
+        assert!(set.has_permission(targets(&root, PermissionCapability::Read, true)));
+    }
+
+    #[test]
+    fn own_resources_dont_grant_administrating() {
+        let root = Uuid::new();
+        let set = PermissionSet::from(vec![targets(&root, PermissionCapability::Manage, false)]);
+        assert!(set.has_permission(targets(&root, PermissionCapability::Manage, false)));
+        assert!(!set.has_permission(targets(&root, PermissionCapability::Read, true)));
+    }
+
+    #[test]
+    fn capability_kind_and_root_must_match() {
+        let (root, other) = (Uuid::new(), Uuid::new());
+        let set = PermissionSet::from(vec![targets(&root, PermissionCapability::Edit, true)]);
+        assert!(!set.has_permission(targets(&root, PermissionCapability::Manage, false)));
+        assert!(!set.has_permission(targets(&other, PermissionCapability::Read, false)));
+        assert!(!set.has_permission(Permission::Invites {
+            root,
+            capability: PermissionCapability::Read,
+            administrate: false,
+        }));
+    }
+
+    #[test]
+    fn administrator_grants_everything() {
+        let set = PermissionSet::from(vec![Permission::Administrator]);
+        assert!(set.has_permission(targets(&Uuid::new(), PermissionCapability::Manage, true)));
+    }
 }
@@ -1,6 +1,7 @@
 use std::{fmt::Display, str::FromStr};
 
 use bson::Bson;
+use rocket::request::FromParam;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid as RawUuid;
 
@@ -49,3 +50,10 @@ impl From<Uuid> for Bson {
         Bson::String(value.to_string())
     }
 }
+
+impl<'a> FromParam<'a> for Uuid {
+    type Error = uuid::Error;
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        Self::from_str(param)
+    }
+}
@@ -0,0 +1,133 @@
+use std::net::IpAddr;
+
+use okapi::openapi3::{Object, Parameter, ParameterValue};
+use rocket::{
+    Request,
+    request::{self, FromRequest},
+};
+use rocket_okapi::{
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::{
+    models::{LoginThrottle, LoginThrottleTreeExt, ThrottleSubject, User},
+    types::Config,
+    util::MetaTree,
+};
+
+/// Optional password protecting a public resource, sent in the `X-Abyssal-Password` header
+#[derive(Clone, Debug)]
+pub struct AccessPassword {
+    password: Option<String>,
+
+    /// Client the password was sent from, which failed attempts are also counted against
+    ip: Option<IpAddr>,
+}
+
+impl AccessPassword {
+    /// Checks this password against `resource`. Repeated failures for the resource or
+    /// the client's IP address are throttled like failed logins.
+    pub fn verify(
+        &self,
+        resource: &impl PasswordProtected,
+        subject: ThrottleSubject,
+        throttles: &MetaTree<LoginThrottle>,
+        config: &Config,
+    ) -> crate::Result<()> {
+        if resource.password_hash().is_none() {
+            return Ok(());
+        }
+
+        let throttle_config = config.authentication().login_throttle();
+        let mut subjects = vec![subject];
+        if let Some(ip) = self.ip {
+            subjects.push(ThrottleSubject::Ip(ip));
+        }
+        throttles.check(&subjects, &throttle_config)?;
+
+        match resource.verify_password(self.password.clone()) {
+            Ok(()) => {
+                // Only the resource's counter is reset, as with logins
+                let _ = throttles.delete(subjects[0].key())?;
+                Ok(())
+            }
+            Err(crate::Error::IncorrectPassword) => {
+                throttles.record_failure(&subjects, &throttle_config)?;
+                Err(crate::Error::IncorrectPassword)
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AccessPassword {
+    type Error = crate::Error;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        request::Outcome::Success(Self {
+            password: req
+                .headers()
+                .get_one("X-Abyssal-Password")
+                .map(|v| v.to_string()),
+            ip: req.client_ip(),
+        })
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for AccessPassword {
+    fn from_request_input(
+        generator: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::Parameter(Parameter {
+            name: "X-Abyssal-Password".to_owned(),
+            location: "header".to_owned(),
+            description: Some(
+                "Password protecting the resource, if any (repeated failures are throttled like logins)"
+                    .to_owned(),
+            ),
+            required: false,
+            deprecated: false,
+            allow_empty_value: false,
+            value: ParameterValue::Schema {
+                style: None,
+                explode: None,
+                allow_reserved: false,
+                schema: generator.json_schema::<String>(),
+                example: None,
+                examples: None,
+            },
+            extensions: Object::default(),
+        }))
+    }
+}
+
+/// Resource that can be protected by an [`AccessPassword`], stored hashed
+pub trait PasswordProtected: Sized {
+    /// Hash of the password, if one is set
+    fn password_hash(&self) -> Option<String>;
+    fn with_password_hash(self, hash: Option<String>) -> Self;
+
+    /// Sets (or clears) the password, hashing it
+    fn with_plain_password(self, password: Option<String>) -> crate::Result<Self> {
+        match password {
+            Some(password) => Ok(self.with_password_hash(Some(User::hash_value(password)?))),
+            None => Ok(self.with_password_hash(None)),
+        }
+    }
+
+    /// Checks a supplied password, passing if the resource isn't protected
+    fn verify_password(&self, password: Option<String>) -> crate::Result<()> {
+        match (self.password_hash(), password) {
+            (None, _) => Ok(()),
+            (Some(hashed), Some(password))
+                if User::verify_value(password.as_str(), hashed.as_str())? =>
+            {
+                Ok(())
+            }
+            _ => Err(crate::Error::IncorrectPassword),
+        }
+    }
+}
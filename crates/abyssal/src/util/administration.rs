@@ -0,0 +1,10 @@
+use crate::models::{User, UserMethods};
+
+/// Ensures `user` is an administrator, as required by the user and group administration routes
+pub fn check_administrator(user: &User) -> crate::Result<()> {
+    if user.permissions().is_administrator() {
+        Ok(())
+    } else {
+        Err(crate::Error::Forbidden)
+    }
+}
@@ -0,0 +1,310 @@
+use std::str::FromStr;
+
+use bson::doc;
+use rocket::{
+    Request,
+    http::{Method, Status},
+    request::{self, FromRequest},
+};
+use rocket_okapi::{
+    JsonSchema,
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{Group, GroupCollectionExt, LocalUser, Token, User, UserKind, UserMethods},
+    types::{Config, Uuid},
+    util::{
+        Collection,
+        session_cookie::{CSRF_HEADER, SESSION_COOKIE},
+    },
+};
+
+/// How a request was authenticated
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// `Authorization: Token <session token>`
+    Token,
+
+    /// `Authorization: Token <personal access token>`
+    PersonalToken,
+
+    /// `Authorization: Application <client_id>:<client_secret>`
+    Application,
+
+    /// Session token from the session cookie (state-changing requests also need the CSRF header,
+    /// and WebSocket upgrades a same-origin `Origin`)
+    Cookie,
+}
+
+/// Request guard resolving the authenticated user, along with how they authenticated.
+/// Resolved at most once per request, so combining it with [`User`] guards is free.
+#[derive(Clone, Debug)]
+pub struct AuthContext {
+    user: User,
+    method: AuthMethod,
+    token: Option<Token>,
+}
+
+impl AuthContext {
+    pub fn user(&self) -> User {
+        self.user.clone()
+    }
+
+    pub fn method(&self) -> AuthMethod {
+        self.method.clone()
+    }
+
+    /// Session token that authenticated this request, if any
+    pub fn token(&self) -> Option<Token> {
+        self.token.clone()
+    }
+
+    /// Session token of this request, refusing personal access tokens & applications.
+    /// Used to guard account security settings.
+    pub fn session(&self) -> crate::Result<Token> {
+        match (&self.method, &self.token) {
+            (AuthMethod::Token | AuthMethod::Cookie, Some(token)) => Ok(token.clone()),
+            _ => Err(crate::Error::Forbidden),
+        }
+    }
+
+    /// Stored local user behind this request's session. Unlike [`AuthContext::user`], this
+    /// doesn't carry effective permissions, so it is safe to modify and save.
+    pub async fn session_local_user(&self, users: &Collection<User>) -> crate::Result<LocalUser> {
+        let _ = self.session()?;
+        match users.get(self.user.id()).await? {
+            Some(User::Local(user)) => Ok(user),
+            Some(_) => Err(crate::Error::invalid_user_type([UserKind::Local])),
+            None => Err(crate::Error::MissingAuthorization),
+        }
+    }
+
+    /// Whether `token` is the session token that authenticated this request
+    pub fn is_current(&self, token: &Token) -> bool {
+        self.token
+            .as_ref()
+            .is_some_and(|current| current.id() == token.id())
+    }
+
+    /// Caller with their current permissions, restricted like when the request was authenticated.
+    /// Used by connections outliving their request. `None` once the caller (or, for applications,
+    /// their owner) was deleted or disabled.
+    pub async fn reload(
+        &self,
+        users: &Collection<User>,
+        groups: &Collection<Group>,
+    ) -> crate::Result<Option<User>> {
+        let Some(user) = groups.effective_owner(users, self.user.id()).await? else {
+            return Ok(None);
+        };
+        let permissions = match (&self.method, &user) {
+            // Personal access tokens only grant what both they and their user hold
+            (AuthMethod::PersonalToken, _) => match self.token.as_ref().and_then(Token::personal) {
+                Some(personal) => {
+                    personal
+                        .permissions()
+                        .restrict(&user.permissions(), user.name(), user.name())
+                }
+                None => return Ok(None),
+            },
+
+            // Applications never exceed their owner's current permissions
+            (AuthMethod::Application, User::Application(application)) => {
+                match groups.effective_owner(users, application.owner()).await? {
+                    Some(owner) => {
+                        user.permissions()
+                            .restrict(&owner.permissions(), owner.name(), user.name())
+                    }
+                    None => return Ok(None),
+                }
+            }
+            (AuthMethod::Application, _) => return Ok(None),
+            _ => return Ok(Some(user)),
+        };
+        Ok(Some(user.with_permissions(permissions)))
+    }
+
+    /// Replaces `user`'s permissions with the union of their own and their groups'
+    async fn with_group_permissions(req: &Request<'_>, user: User) -> crate::Result<User> {
+        let groups = Collection::<Group>::from_request(req).await.unwrap();
+        let permissions = groups.effective_permissions(&user).await?;
+        Ok(user.with_permissions(permissions))
+    }
+
+    async fn from_token(req: &Request<'_>, token: &str) -> crate::Result<Self> {
+        let tokens = Collection::<Token>::from_request(req).await.unwrap();
+        let users = Collection::<User>::from_request(req).await.unwrap();
+        let config = req
+            .rocket()
+            .state::<Config>()
+            .ok_or(crate::Error::MissingState(String::from("abyssal::Config")))?;
+        if let Ok(Some(existing_token)) = tokens.get(Uuid::from_str(token)?).await {
+            if existing_token.is_expired(&config.authentication()) {
+                let _ = tokens.delete(existing_token.id()).await?;
+                Err(crate::Error::SessionExpired)
+            } else if let Ok(Some(existing_user)) = existing_token.resolve_user(users).await {
+                let existing_user = Self::with_group_permissions(req, existing_user).await?;
+                let refreshed = existing_token.refresh_token();
+                let _ = tokens.save(refreshed.clone()).await?;
+                match refreshed.personal() {
+                    // Personal access tokens only grant what both they and their user hold
+                    Some(personal) => {
+                        let permissions = personal.permissions().restrict(
+                            &existing_user.permissions(),
+                            existing_user.name(),
+                            existing_user.name(),
+                        );
+                        Ok(Self {
+                            user: existing_user.with_permissions(permissions),
+                            method: AuthMethod::PersonalToken,
+                            token: Some(refreshed),
+                        })
+                    }
+                    None => Ok(Self {
+                        user: existing_user,
+                        method: AuthMethod::Token,
+                        token: Some(refreshed),
+                    }),
+                }
+            } else {
+                let _ = tokens.delete(existing_token.id()).await?;
+                Err(crate::Error::MissingAuthorization)
+            }
+        } else {
+            Err(crate::Error::MissingAuthorization)
+        }
+    }
+
+    async fn from_application(req: &Request<'_>, app_auth: &str) -> crate::Result<Self> {
+        let users = Collection::<User>::from_request(req).await.unwrap();
+        if let Some((client_id, client_secret)) = app_auth.split_once(":") {
+            if let Ok(Some(existing_user)) = users.find_one(doc! {"client_id": client_id}).await {
+                if existing_user.verify_client_secret(client_secret.to_string())?
+                    && let User::Application(application) = existing_user
+                    && let Some(owner) = users.get(application.owner()).await?
+                    && !owner.disabled()
+                {
+                    let owner = Self::with_group_permissions(req, owner).await?;
+                    let application = Self::with_group_permissions(req, application.into()).await?;
+
+                    // Applications never exceed their owner's current permissions
+                    let permissions = application.permissions().restrict(
+                        &owner.permissions(),
+                        owner.name(),
+                        application.name(),
+                    );
+                    Ok(Self {
+                        user: application.with_permissions(permissions),
+                        method: AuthMethod::Application,
+                        token: None,
+                    })
+                } else {
+                    Err(crate::Error::MissingAuthorization)
+                }
+            } else {
+                Err(crate::Error::MissingAuthorization)
+            }
+        } else {
+            Err(crate::Error::MissingAuthorization)
+        }
+    }
+
+    /// Whether the request's `Origin` is this server itself, as given by the `Host` header
+    fn is_same_origin(req: &Request<'_>) -> bool {
+        let headers = req.headers();
+        match (headers.get_one("Origin"), headers.get_one("Host")) {
+            (Some(origin), Some(host)) => origin
+                .split_once("://")
+                .is_some_and(|(_, authority)| authority.eq_ignore_ascii_case(host)),
+            _ => false,
+        }
+    }
+
+    /// Cookies are sent by browsers automatically, so requests that may change state must also
+    /// prove they can read the CSRF cookie (which other sites can't). WebSocket upgrades are
+    /// `GET`s, but browsers let any site open them, so they must come from this server's origin.
+    fn check_csrf(req: &Request<'_>, token: &Token) -> crate::Result<()> {
+        let upgrade = req
+            .headers()
+            .get_one("Upgrade")
+            .is_some_and(|protocol| protocol.eq_ignore_ascii_case("websocket"));
+        let safe = if upgrade {
+            Self::is_same_origin(req)
+        } else {
+            matches!(req.method(), Method::Get | Method::Head | Method::Options)
+        };
+        if safe || req.headers().get_one(CSRF_HEADER) == Some(token.csrf_token().as_str()) {
+            Ok(())
+        } else {
+            Err(crate::Error::CsrfMismatch)
+        }
+    }
+
+    async fn from_request_inner(req: &Request<'_>) -> crate::Result<Self> {
+        let context = if let Some(authorization) = req
+            .headers()
+            .get_one("Authorization")
+            .map(|v| v.to_string())
+        {
+            match authorization.split_once(" ") {
+                Some(("Token", token)) => Self::from_token(req, token).await,
+                Some(("Application", app_auth)) => Self::from_application(req, app_auth).await,
+                _ => Err(crate::Error::MissingAuthorization),
+            }?
+        } else if let Some(cookie) = req.cookies().get_private(SESSION_COOKIE) {
+            match Self::from_token(req, cookie.value()).await? {
+                Self {
+                    user,
+                    method: AuthMethod::Token,
+                    token: Some(token),
+                } => {
+                    Self::check_csrf(req, &token)?;
+                    Self {
+                        user,
+                        method: AuthMethod::Cookie,
+                        token: Some(token),
+                    }
+                }
+
+                // Only session tokens are ever issued as cookies
+                _ => return Err(crate::Error::MissingAuthorization),
+            }
+        } else {
+            return Err(crate::Error::MissingAuthorization);
+        };
+
+        if context.user.disabled() {
+            Err(crate::Error::AccountDisabled)
+        } else {
+            Ok(context)
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthContext {
+    type Error = crate::Error;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match req
+            .local_cache_async(async { Self::from_request_inner(req).await })
+            .await
+        {
+            Ok(resolved) => request::Outcome::Success(resolved.clone()),
+            Err(err) => request::Outcome::Error((Status::new(err.metadata().status), err.clone())),
+        }
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for AuthContext {
+    fn from_request_input(
+        generator: &mut OpenApiGenerator,
+        name: String,
+        required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        User::from_request_input(generator, name, required)
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    io::{self, SeekFrom},
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll, ready},
+    time::UNIX_EPOCH,
+};
+
+use chrono::{DateTime, Utc};
+use okapi::openapi3::Responses;
+use rocket::{
+    Request, Response,
+    http::{ContentType, Header, Status},
+    request::{self, FromRequest},
+    response::{self, Responder},
+};
+use rocket_okapi::{
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+    response::OpenApiResponderInner,
+    util::ensure_status_code_exists,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, ReadBuf, Take},
+};
+
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Range & conditional request headers relevant to file downloads
+#[derive(Clone, Debug, Default)]
+pub struct DownloadConditions {
+    pub range: Option<String>,
+    pub if_range: Option<String>,
+    pub if_none_match: Option<String>,
+    pub if_modified_since: Option<String>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DownloadConditions {
+    type Error = crate::Error;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let header = |name: &str| req.headers().get_one(name).map(|v| v.to_string());
+        request::Outcome::Success(Self {
+            range: header("Range"),
+            if_range: header("If-Range"),
+            if_none_match: header("If-None-Match"),
+            if_modified_since: header("If-Modified-Since"),
+        })
+    }
+}
+
+impl<'r> OpenApiFromRequest<'r> for DownloadConditions {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+/// Byte range of a file, read and seeked as if it was a whole file
+struct FileSlice {
+    inner: Take<File>,
+    start: u64,
+    length: u64,
+}
+
+impl FileSlice {
+    async fn new(mut file: File, start: u64, length: u64) -> io::Result<Self> {
+        file.seek(SeekFrom::Start(start)).await?;
+        Ok(Self {
+            inner: file.take(length),
+            start,
+            length,
+        })
+    }
+}
+
+impl AsyncRead for FileSlice {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for FileSlice {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let offset = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => this.length.checked_add_signed(delta),
+            SeekFrom::Current(delta) => {
+                (this.length - this.inner.limit()).checked_add_signed(delta)
+            }
+        }
+        .ok_or(io::ErrorKind::InvalidInput)?;
+        this.inner.set_limit(this.length.saturating_sub(offset));
+        Pin::new(this.inner.get_mut()).start_seek(SeekFrom::Start(this.start + offset))
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        let position = ready!(Pin::new(this.inner.get_mut()).poll_complete(cx))?;
+        Poll::Ready(Ok(position - this.start))
+    }
+}
+
+/// Streams a file from disk, honoring `Range`, `If-Range`, `If-None-Match` and `If-Modified-Since`
+pub struct FileDownload {
+    status: Status,
+    headers: Vec<Header<'static>>,
+    body: Option<(FileSlice, u64)>,
+}
+
+impl FileDownload {
+    pub async fn open(
+        path: impl AsRef<Path>,
+        conditions: DownloadConditions,
+        inline: bool,
+    ) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path).await?;
+        let metadata = file.metadata().await?;
+        let size = metadata.len();
+        let modified = metadata.modified().ok();
+        let etag = format!(
+            "\"{:x}-{:x}\"",
+            size,
+            modified
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos())
+                .unwrap_or_default()
+        );
+        let last_modified = modified.map(DateTime::<Utc>::from);
+
+        // Files are user-supplied, so keep browsers from sniffing them into (or running them as)
+        // active content on the API's origin, even when served inline
+        let mut headers = vec![
+            Header::new("ETag", etag.clone()),
+            Header::new("Accept-Ranges", "bytes"),
+            Header::new("X-Content-Type-Options", "nosniff"),
+            Header::new("Content-Security-Policy", "sandbox"),
+        ];
+        if let Some(last_modified) = last_modified {
+            headers.push(Header::new(
+                "Last-Modified",
+                last_modified.format(HTTP_DATE_FORMAT).to_string(),
+            ));
+        }
+
+        if Self::not_modified(&conditions, &etag, last_modified) {
+            return Ok(Self {
+                status: Status::NotModified,
+                headers,
+                body: None,
+            });
+        }
+
+        let content_type = Self::content_type(path, &mut file).await?;
+        headers.push(Header::new("Content-Type", content_type.to_string()));
+        headers.push(Header::new(
+            "Content-Disposition",
+            Self::content_disposition(path, inline),
+        ));
+
+        let range = match conditions.range.clone() {
+            Some(range) if Self::range_applies(&conditions, &etag, last_modified) => {
+                match Self::parse_range(&range, size) {
+                    Some(parsed) => parsed,
+                    None => {
+                        headers.push(Header::new("Content-Range", format!("bytes */{size}")));
+                        return Ok(Self {
+                            status: Status::RangeNotSatisfiable,
+                            headers,
+                            body: None,
+                        });
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        match range {
+            Some((start, end)) => {
+                let length = end - start + 1;
+                headers.push(Header::new(
+                    "Content-Range",
+                    format!("bytes {start}-{end}/{size}"),
+                ));
+                Ok(Self {
+                    status: Status::PartialContent,
+                    headers,
+                    body: Some((FileSlice::new(file, start, length).await?, length)),
+                })
+            }
+            None => Ok(Self {
+                status: Status::Ok,
+                headers,
+                body: Some((FileSlice::new(file, 0, size).await?, size)),
+            }),
+        }
+    }
+
+    /// Whether this starts sending the file from its first byte, as opposed to answering from
+    /// the client's cache or continuing a download (or seeking) with a later range
+    pub fn starts_download(&self) -> bool {
+        self.body
+            .as_ref()
+            .is_some_and(|(slice, _)| slice.start == 0)
+    }
+
+    fn not_modified(
+        conditions: &DownloadConditions,
+        etag: &str,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> bool {
+        // If-None-Match takes precedence over If-Modified-Since (RFC 9110 §13.1.3)
+        if let Some(if_none_match) = conditions.if_none_match.clone() {
+            return if_none_match.split(',').any(|candidate| {
+                let candidate = candidate.trim();
+                candidate == "*" || candidate.trim_start_matches("W/") == etag
+            });
+        }
+
+        match (
+            conditions
+                .if_modified_since
+                .clone()
+                .and_then(|since| DateTime::parse_from_rfc2822(&since).ok()),
+            last_modified,
+        ) {
+            (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+            _ => false,
+        }
+    }
+
+    fn range_applies(
+        conditions: &DownloadConditions,
+        etag: &str,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> bool {
+        match conditions.if_range.clone() {
+            None => true,
+            Some(if_range) if if_range.starts_with('"') => if_range == etag,
+            Some(if_range) => match (DateTime::parse_from_rfc2822(&if_range), last_modified) {
+                (Ok(date), Some(modified)) => modified.timestamp() == date.timestamp(),
+                _ => false,
+            },
+        }
+    }
+
+    /// Parses a single `bytes=` range into inclusive offsets.
+    /// Returns `Some(None)` for ranges that should be ignored (unknown units/multiple ranges),
+    /// and `None` for ranges that cannot be satisfied.
+    fn parse_range(range: &str, size: u64) -> Option<Option<(u64, u64)>> {
+        let Some(spec) = range.trim().strip_prefix("bytes=") else {
+            return Some(None);
+        };
+        if spec.contains(',') {
+            return Some(None);
+        }
+
+        let (start, end) = spec.trim().split_once('-')?;
+        let (start, end) = match (start.trim(), end.trim()) {
+            ("", suffix) => {
+                let suffix = suffix.parse::<u64>().ok()?;
+                if suffix == 0 {
+                    return None;
+                }
+                (size.saturating_sub(suffix), size.checked_sub(1)?)
+            }
+            (start, "") => (start.parse::<u64>().ok()?, size.checked_sub(1)?),
+            (start, end) => (
+                start.parse::<u64>().ok()?,
+                end.parse::<u64>().ok()?.min(size.checked_sub(1)?),
+            ),
+        };
+
+        if start > end || start >= size {
+            None
+        } else {
+            Some(Some((start, end)))
+        }
+    }
+
+    /// Guesses the content type from the file extension, falling back to sniffing its first bytes
+    async fn content_type(path: &Path, file: &mut File) -> crate::Result<ContentType> {
+        if let Some(known) = path
+            .extension()
+            .and_then(|ext| ContentType::from_extension(&ext.to_string_lossy()))
+        {
+            return Ok(known);
+        }
+
+        let mut buffer = vec![0u8; 8192];
+        let read = file.read(&mut buffer).await?;
+        buffer.truncate(read);
+        if let Some(sniffed) =
+            infer::get(&buffer).and_then(|kind| ContentType::parse_flexible(kind.mime_type()))
+        {
+            Ok(sniffed)
+        } else if std::str::from_utf8(&buffer).is_ok() {
+            Ok(ContentType::Plain)
+        } else {
+            Ok(ContentType::Binary)
+        }
+    }
+
+    fn content_disposition(path: &Path, inline: bool) -> String {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let fallback = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        let encoded = name
+            .bytes()
+            .map(|byte| {
+                if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+                    (byte as char).to_string()
+                } else {
+                    format!("%{byte:02X}")
+                }
+            })
+            .collect::<String>();
+
+        format!(
+            "{}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}",
+            if inline { "inline" } else { "attachment" }
+        )
+    }
+}
+
+impl<'r> Responder<'r, 'static> for FileDownload {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = Response::build();
+        response.status(self.status);
+        for header in self.headers {
+            response.header(header);
+        }
+
+        if let Some((body, length)) = self.body {
+            response.sized_body(length as usize, body);
+        }
+
+        response.ok()
+    }
+}
+
+impl OpenApiResponderInner for FileDownload {
+    fn responses(generator: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = <Vec<u8>>::responses(generator)?;
+        for status in [206, 304, 416] {
+            ensure_status_code_exists(&mut responses, status);
+        }
+
+        Ok(responses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_slice_reads_and_seeks_within_its_range() {
+        let path =
+            std::env::temp_dir().join(format!("abyssal-slice-{}", crate::types::Uuid::new()));
+        tokio::fs::write(&path, b"0123456789").await.unwrap();
+        let mut slice = FileSlice::new(File::open(&path).await.unwrap(), 2, 5)
+            .await
+            .unwrap();
+
+        let mut read = String::new();
+        slice.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "23456");
+
+        assert_eq!(slice.seek(SeekFrom::End(-2)).await.unwrap(), 3);
+        read.clear();
+        slice.read_to_string(&mut read).await.unwrap();
+        assert_eq!(read, "56");
+
+        assert_eq!(slice.seek(SeekFrom::Start(0)).await.unwrap(), 0);
+        assert!(slice.seek(SeekFrom::Current(-1)).await.is_err());
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+}
@@ -0,0 +1,93 @@
+use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+
+use okapi::openapi3::{RefOr, Responses};
+use parking_lot::Mutex;
+use rocket::{
+    Request,
+    response::{self, Responder},
+};
+use rocket_okapi::{
+    JsonSchema, r#gen::OpenApiGenerator, response::OpenApiResponderInner, util::add_schema_response,
+};
+use rocket_ws::{Channel, Message};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::{
+    models::{Event, EventPayload},
+    types::Uuid,
+};
+
+/// Events buffered for each connection before it starts missing them
+const EVENT_CAPACITY: usize = 256;
+
+/// Delivers [`Event`]s to the connected event streams of their users
+#[derive(Clone, Default)]
+pub struct EventBus {
+    channels: Arc<Mutex<HashMap<Uuid, broadcast::Sender<Event>>>>,
+}
+
+impl EventBus {
+    /// Receives events published to `user` from now on
+    pub fn subscribe(&self, user: &Uuid) -> broadcast::Receiver<Event> {
+        let mut channels = self.channels.lock();
+        channels.retain(|_, sender| sender.receiver_count() > 0);
+        channels
+            .entry(user.clone())
+            .or_insert_with(|| broadcast::channel(EVENT_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Sends an event to `user`, if they are connected
+    pub fn publish(&self, user: impl Into<Uuid>, payload: EventPayload) {
+        self.publish_all([user.into()], payload);
+    }
+
+    pub fn publish_all(&self, users: impl IntoIterator<Item = Uuid>, payload: EventPayload) {
+        let channels = self.channels.lock();
+        for user in users {
+            if let Some(sender) = channels.get(&user) {
+                let _ = sender.send(Event::new(payload.clone()));
+            }
+        }
+    }
+}
+
+/// Serializes a message sent over a WebSocket
+pub fn json_message(message: &impl Serialize) -> Message {
+    Message::Text(serde_json::to_string(message).unwrap_or_default())
+}
+
+/// WebSocket whose server messages are JSON-serialized `M`s, documented as such in the API specification
+pub struct MessageChannel<M> {
+    channel: Channel<'static>,
+    messages: PhantomData<M>,
+}
+
+impl<M> From<Channel<'static>> for MessageChannel<M> {
+    fn from(channel: Channel<'static>) -> Self {
+        Self {
+            channel,
+            messages: PhantomData,
+        }
+    }
+}
+
+impl<'r, M> Responder<'r, 'static> for MessageChannel<M> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        self.channel.respond_to(req)
+    }
+}
+
+impl<M: JsonSchema> OpenApiResponderInner for MessageChannel<M> {
+    fn responses(generator: &mut OpenApiGenerator) -> rocket_okapi::Result<Responses> {
+        let mut responses = Responses::default();
+        let schema = generator.json_schema::<M>();
+        add_schema_response(&mut responses, 101, "application/json", schema)?;
+        if let Some(RefOr::Object(response)) = responses.responses.get_mut("101") {
+            response.description = String::from("WebSocket, sending each message as JSON text");
+        }
+
+        Ok(responses)
+    }
+}
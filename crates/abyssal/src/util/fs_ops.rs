@@ -0,0 +1,210 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use crate::types::{Uuid, filesystem::normalize_relative};
+
+/// Validates a client-supplied file name, ensuring it is a single path component
+pub fn file_name(name: impl AsRef<str>) -> crate::Result<String> {
+    let name = name.as_ref();
+    match normalize_relative(name) {
+        Ok(normalized)
+            if normalized.components().count() == 1 && normalized.as_os_str() == name =>
+        {
+            Ok(name.to_string())
+        }
+        _ => Err(crate::Error::InvalidFileName(name.to_string())),
+    }
+}
+
+/// Generates the `n`th alternative of a file name (`report.pdf` -> `report (n).pdf`)
+pub fn numbered_name(name: impl AsRef<str>, n: usize) -> String {
+    let name = name.as_ref();
+    if n == 0 {
+        return name.to_string();
+    }
+
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem} ({n}).{extension}"),
+        _ => format!("{name} ({n})"),
+    }
+}
+
+/// Atomically places `source` at `destination` on the same filesystem.
+/// Without `overwrite`, a hard link is used so an existing destination is never replaced.
+async fn place(source: &Path, destination: &Path, overwrite: bool) -> std::io::Result<()> {
+    if overwrite {
+        return tokio::fs::rename(source, destination).await;
+    }
+
+    match tokio::fs::hard_link(source, destination).await {
+        Ok(()) => tokio::fs::remove_file(source).await,
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::AlreadyExists | ErrorKind::CrossesDevices
+            ) =>
+        {
+            Err(err)
+        }
+        // Filesystems without hard link support fall back to a (racy) existence check
+        Err(_) => {
+            if tokio::fs::try_exists(destination).await? {
+                Err(ErrorKind::AlreadyExists.into())
+            } else {
+                tokio::fs::rename(source, destination).await
+            }
+        }
+    }
+}
+
+/// Moves a file into place atomically, copying it next to the destination first
+/// if the two paths live on different devices.
+pub async fn move_file(
+    source: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    overwrite: bool,
+    display: impl AsRef<Path>,
+) -> crate::Result<()> {
+    let (source, destination) = (source.as_ref(), destination.as_ref());
+    match place(source, destination, overwrite).await {
+        Ok(()) => return Ok(()),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+            return Err(crate::Error::path_conflict(display));
+        }
+        Err(err) if err.kind() != ErrorKind::CrossesDevices => return Err(err.into()),
+        Err(_) => {}
+    }
+
+    let staging = staging_path(destination);
+    tokio::fs::copy(source, &staging).await?;
+    match place(&staging, destination, overwrite).await {
+        Ok(()) => {
+            tokio::fs::remove_file(source).await?;
+            Ok(())
+        }
+        Err(err) => {
+            let _ = tokio::fs::remove_file(&staging).await;
+            if err.kind() == ErrorKind::AlreadyExists {
+                Err(crate::Error::path_conflict(display))
+            } else {
+                Err(err.into())
+            }
+        }
+    }
+}
+
+/// Temporary name next to `destination` to assemble copies under before moving them into place
+pub fn staging_path(destination: &Path) -> PathBuf {
+    destination.with_file_name(format!(".abyssal-{}.partial", Uuid::new()))
+}
+
+/// Removes a file, symlink or (with `recursive`) directory tree, never following symlinks
+pub async fn remove_entry(
+    path: impl AsRef<Path>,
+    recursive: bool,
+    display: impl AsRef<Path>,
+) -> crate::Result<()> {
+    let path = path.as_ref();
+    let metadata = tokio::fs::symlink_metadata(path)
+        .await
+        .map_err(|_| crate::Error::path_not_found(&display))?;
+    let result = if !metadata.is_dir() {
+        tokio::fs::remove_file(path).await
+    } else if recursive {
+        tokio::fs::remove_dir_all(path).await
+    } else {
+        tokio::fs::remove_dir(path).await
+    };
+
+    match result {
+        Err(err) if err.kind() == ErrorKind::DirectoryNotEmpty => {
+            Err(crate::Error::directory_not_empty(display))
+        }
+        other => Ok(other?),
+    }
+}
+
+/// Recursively copies `source` to the (nonexistent) `destination`, recreating symlinks rather
+/// than following them and skipping `hidden` (the metadata directory)
+async fn copy_tree(source: &Path, destination: &Path, hidden: &Path) -> std::io::Result<()> {
+    let mut pending = vec![(source.to_path_buf(), destination.to_path_buf())];
+    while let Some((source, destination)) = pending.pop() {
+        let metadata = tokio::fs::symlink_metadata(&source).await?;
+        if metadata.is_symlink() {
+            tokio::fs::symlink(tokio::fs::read_link(&source).await?, &destination).await?;
+        } else if metadata.is_dir() {
+            tokio::fs::create_dir(&destination).await?;
+            let mut reader = tokio::fs::read_dir(&source).await?;
+            while let Some(item) = reader.next_entry().await? {
+                if item.path() != hidden {
+                    pending.push((item.path(), destination.join(item.file_name())));
+                }
+            }
+        } else {
+            tokio::fs::copy(&source, &destination).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Places a staged entry at `destination`, failing if something already exists there
+pub async fn place_staged(staging: &Path, destination: &Path, display: &Path) -> crate::Result<()> {
+    if !tokio::fs::symlink_metadata(staging).await?.is_dir() {
+        return move_file(staging, destination, false, display).await;
+    }
+
+    // Directories can't be hard linked, so this check is racy (like `place`'s fallback)
+    if tokio::fs::symlink_metadata(destination).await.is_ok() {
+        return Err(crate::Error::path_conflict(display));
+    }
+    Ok(tokio::fs::rename(staging, destination).await?)
+}
+
+/// Copies a file or directory tree to `destination`. The copy is assembled under a temporary
+/// name first, so `destination` never holds a partial copy.
+pub async fn copy_entry(
+    source: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    hidden: impl AsRef<Path>,
+    display: impl AsRef<Path>,
+) -> crate::Result<()> {
+    let (destination, display) = (destination.as_ref(), display.as_ref());
+    let staging = staging_path(destination);
+    let result = match copy_tree(source.as_ref(), &staging, hidden.as_ref()).await {
+        Ok(()) => place_staged(&staging, destination, display).await,
+        Err(err) => Err(err.into()),
+    };
+
+    if result.is_err() {
+        let _ = remove_entry(&staging, true, &staging).await;
+    }
+    result
+}
+
+/// Moves a file or directory tree to the (nonexistent) `destination`,
+/// copying it instead if the two paths live on different devices
+pub async fn move_entry(
+    source: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    hidden: impl AsRef<Path>,
+    display: impl AsRef<Path>,
+) -> crate::Result<()> {
+    let (source, destination) = (source.as_ref(), destination.as_ref());
+    if !tokio::fs::symlink_metadata(source).await?.is_dir() {
+        return move_file(source, destination, false, display).await;
+    }
+    if tokio::fs::symlink_metadata(destination).await.is_ok() {
+        return Err(crate::Error::path_conflict(display));
+    }
+
+    match tokio::fs::rename(source, destination).await {
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+            copy_entry(source, destination, hidden, display).await?;
+            remove_entry(source, true, source).await
+        }
+        other => Ok(other?),
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, mpsc},
+    time::{Duration, Instant},
+};
+
+use notify::{
+    EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+    event::{ModifyKind, RenameMode},
+};
+use parking_lot::Mutex;
+use tokio::sync::broadcast;
+
+/// Changes buffered for each subscriber before it starts missing them
+const CHANGE_CAPACITY: usize = 1024;
+
+/// How long the first half of a rename waits for its second half
+const RENAME_PAIRING_DELAY: Duration = Duration::from_millis(100);
+
+/// Change inside of a watched directory (with absolute paths)
+#[derive(Clone, Debug)]
+pub enum FsChange {
+    Created(PathBuf),
+    Modified(PathBuf),
+    Deleted(PathBuf),
+    Renamed { from: PathBuf, to: PathBuf },
+}
+
+impl FsChange {
+    fn from_event(event: notify::Event) -> Option<Self> {
+        let mut paths = event.paths.into_iter();
+        let path = paths.next()?;
+        match event.kind {
+            EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+                Some(Self::Created(path))
+            }
+            EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+                Some(Self::Deleted(path))
+            }
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => Some(Self::Renamed {
+                to: paths.next()?,
+                from: path,
+            }),
+
+            // Renames the backend couldn't pair up: the path either appeared or disappeared
+            EventKind::Modify(ModifyKind::Name(_)) => {
+                if path.symlink_metadata().is_ok() {
+                    Some(Self::Created(path))
+                } else {
+                    Some(Self::Deleted(path))
+                }
+            }
+            EventKind::Modify(_) => Some(Self::Modified(path)),
+            _ => None,
+        }
+    }
+}
+
+/// Pairs up the halves of renames. inotify reports a `From` event, then a `To` event that is
+/// directly followed by a `Both` event if the two halves belong together. Only the `Both`
+/// event becomes a change then, while unpaired halves become deletions or creations.
+#[derive(Default)]
+struct RenamePairing {
+    /// `From` halves waiting for their `Both` event, by tracker
+    from: HashMap<usize, (PathBuf, Instant)>,
+
+    /// `To` half, which is paired if the next event is its `Both` event
+    to: Option<(usize, PathBuf)>,
+}
+
+impl RenamePairing {
+    /// Changes resulting from `event`, along with unpaired halves it settles
+    fn event(&mut self, event: notify::Event) -> Vec<FsChange> {
+        let tracker = event.tracker();
+        let mut changes = Vec::new();
+        if let Some((pending, path)) = self.to.take()
+            && !(event.kind == EventKind::Modify(ModifyKind::Name(RenameMode::Both))
+                && tracker == Some(pending))
+        {
+            changes.push(FsChange::Created(path));
+        }
+
+        match (event.kind, tracker, event.paths.first()) {
+            (EventKind::Modify(ModifyKind::Name(RenameMode::From)), Some(tracker), Some(path)) => {
+                self.from.insert(tracker, (path.clone(), Instant::now()));
+            }
+            (EventKind::Modify(ModifyKind::Name(RenameMode::To)), Some(tracker), Some(path)) => {
+                self.to = Some((tracker, path.clone()));
+            }
+            _ => {
+                if let Some(tracker) = tracker {
+                    self.from.remove(&tracker);
+                }
+                changes.extend(FsChange::from_event(event));
+            }
+        }
+        self.expire(&mut changes);
+        changes
+    }
+
+    /// Unpaired halves left once no events arrived for a while
+    fn settle(&mut self) -> Vec<FsChange> {
+        let mut changes = Vec::new();
+        if let Some((_, path)) = self.to.take() {
+            changes.push(FsChange::Created(path));
+        }
+        self.expire(&mut changes);
+        changes
+    }
+
+    /// `From` halves that waited too long for their `Both` event
+    fn expire(&mut self, changes: &mut Vec<FsChange>) {
+        self.from.retain(|_, (path, since)| {
+            if since.elapsed() < RENAME_PAIRING_DELAY {
+                return true;
+            }
+            changes.push(FsChange::Deleted(path.clone()));
+            false
+        });
+    }
+}
+
+/// Filesystem watcher (inotify on Linux) shared by every subscriber. Directories are
+/// watched non-recursively, for as long as at least one [`WatchGuard`] for them exists.
+#[derive(Clone)]
+pub struct FsWatcher {
+    watcher: Arc<Mutex<RecommendedWatcher>>,
+    watched: Arc<Mutex<HashMap<PathBuf, usize>>>,
+    changes: broadcast::Sender<FsChange>,
+}
+
+impl FsWatcher {
+    pub fn new() -> crate::Result<Self> {
+        let (changes, _) = broadcast::channel(CHANGE_CAPACITY);
+        let (events, received) = mpsc::channel();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                let _ = events.send(event);
+            }
+        })
+        .map_err(anyhow::Error::from)?;
+
+        // Ends once the watcher (and with it the event sender) is dropped
+        let sender = changes.clone();
+        std::thread::spawn(move || {
+            let mut pairing = RenamePairing::default();
+            loop {
+                let changes = match received.recv_timeout(RENAME_PAIRING_DELAY) {
+                    Ok(event) => pairing.event(event),
+                    Err(mpsc::RecvTimeoutError::Timeout) => pairing.settle(),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                };
+                for change in changes {
+                    let _ = sender.send(change);
+                }
+            }
+        });
+
+        Ok(Self {
+            watcher: Arc::new(Mutex::new(watcher)),
+            watched: Default::default(),
+            changes,
+        })
+    }
+
+    /// Receives changes in every watched directory
+    pub fn changes(&self) -> broadcast::Receiver<FsChange> {
+        self.changes.subscribe()
+    }
+
+    /// Watches the (canonical, absolute) `directory` until the returned guard is dropped
+    pub fn watch(&self, directory: impl AsRef<Path>) -> crate::Result<WatchGuard> {
+        let directory = directory.as_ref().to_path_buf();
+        let mut watched = self.watched.lock();
+        match watched.get_mut(&directory) {
+            Some(count) => *count += 1,
+            None => {
+                self.watcher
+                    .lock()
+                    .watch(&directory, RecursiveMode::NonRecursive)
+                    .map_err(anyhow::Error::from)?;
+                watched.insert(directory.clone(), 1);
+            }
+        }
+
+        Ok(WatchGuard {
+            watcher: self.clone(),
+            directory,
+        })
+    }
+
+    fn unwatch(&self, directory: &Path) {
+        let mut watched = self.watched.lock();
+        if let Some(count) = watched.get_mut(directory) {
+            *count -= 1;
+            if *count == 0 {
+                watched.remove(directory);
+
+                // Fails if the directory was deleted, which already removed the watch
+                let _ = self.watcher.lock().unwatch(directory);
+            }
+        }
+    }
+}
+
+/// Keeps a directory watched while alive
+pub struct WatchGuard {
+    watcher: FsWatcher,
+    directory: PathBuf,
+}
+
+impl WatchGuard {
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+}
+
+impl Drop for WatchGuard {
+    fn drop(&mut self) {
+        self.watcher.unwatch(&self.directory);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Uuid;
+
+    struct Fixture {
+        base: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let base = std::env::temp_dir().join(format!("abyssal-watch-{}", Uuid::new()));
+            std::fs::create_dir_all(base.join("watched")).unwrap();
+            std::fs::create_dir_all(base.join("other")).unwrap();
+            Self {
+                base: base.canonicalize().unwrap(),
+            }
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.base);
+        }
+    }
+
+    /// Changes received until none arrived for a while
+    async fn collect(changes: &mut broadcast::Receiver<FsChange>) -> Vec<FsChange> {
+        let mut collected = Vec::new();
+        while let Ok(Ok(change)) =
+            tokio::time::timeout(Duration::from_millis(500), changes.recv()).await
+        {
+            collected.push(change);
+        }
+        collected
+    }
+
+    #[tokio::test]
+    async fn renames_are_a_single_change() {
+        let fixture = Fixture::new();
+        let watched = fixture.base.join("watched");
+        std::fs::write(watched.join("a"), "a").unwrap();
+
+        let watcher = FsWatcher::new().unwrap();
+        let mut changes = watcher.changes();
+        let _guard = watcher.watch(&watched).unwrap();
+        std::fs::rename(watched.join("a"), watched.join("b")).unwrap();
+
+        match collect(&mut changes).await.as_slice() {
+            [FsChange::Renamed { from, to }] => {
+                assert_eq!(from, &watched.join("a"));
+                assert_eq!(to, &watched.join("b"));
+            }
+            changes => panic!("unexpected changes: {changes:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn moves_out_of_watched_directories_are_deletions() {
+        let fixture = Fixture::new();
+        let watched = fixture.base.join("watched");
+        std::fs::write(watched.join("a"), "a").unwrap();
+
+        let watcher = FsWatcher::new().unwrap();
+        let mut changes = watcher.changes();
+        let _guard = watcher.watch(&watched).unwrap();
+        std::fs::rename(watched.join("a"), fixture.base.join("other/a")).unwrap();
+
+        match collect(&mut changes).await.as_slice() {
+            [FsChange::Deleted(path)] => assert_eq!(path, &watched.join("a")),
+            changes => panic!("unexpected changes: {changes:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn moves_into_watched_directories_are_creations() {
+        let fixture = Fixture::new();
+        let watched = fixture.base.join("watched");
+        std::fs::write(fixture.base.join("other/a"), "a").unwrap();
+
+        let watcher = FsWatcher::new().unwrap();
+        let mut changes = watcher.changes();
+        let _guard = watcher.watch(&watched).unwrap();
+        std::fs::rename(fixture.base.join("other/a"), watched.join("a")).unwrap();
+
+        match collect(&mut changes).await.as_slice() {
+            [FsChange::Created(path)] => assert_eq!(path, &watched.join("a")),
+            changes => panic!("unexpected changes: {changes:?}"),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use bson::doc;
 use rocket::fairing::AdHoc;
 
 use crate::{
@@ -14,17 +15,34 @@ async fn generate_filesystem(
         if let Some(existing) = collection.by_name(name.clone()).await? {
             if existing.path() != dir_config.path()
                 || existing.display_name() != dir_config.display_name()
+                || !existing.managed()
             {
                 let new_root =
-                    RootDirectory::new(name, dir_config.display_name(), dir_config.path());
+                    RootDirectory::new(name, dir_config.display_name(), dir_config.path())
+                        .with_managed(true);
                 let _ = collection.save(new_root.with_id(existing.id())).await?;
             }
         } else {
-            let new_root = RootDirectory::new(name, dir_config.display_name(), dir_config.path());
+            let new_root = RootDirectory::new(name, dir_config.display_name(), dir_config.path())
+                .with_managed(true);
             let _ = collection.save(new_root).await?;
         }
     }
 
+    // Roots removed from the configuration become regular, editable roots
+    let configured = config
+        .filesystem()
+        .directories()
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>();
+    let _ = collection
+        .update_many(
+            doc! {"managed": true, "name": {"$nin": configured}},
+            doc! {"$set": {"managed": false}},
+        )
+        .await?;
+
     Ok(())
 }
 
@@ -32,7 +50,7 @@ pub fn generate_resources() -> AdHoc {
     AdHoc::on_liftoff("Generate configured resources", |rocket| {
         Box::pin(async move {
             let config = rocket.state::<Config>().cloned().unwrap();
-            generate_filesystem(config.clone(), Collection::from_rocket(&rocket))
+            generate_filesystem(config.clone(), Collection::from_rocket(rocket))
                 .await
                 .unwrap();
         })
@@ -0,0 +1,585 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{Read, Write},
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use rocket::fairing::AdHoc;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Semaphore,
+};
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    Config,
+    models::{EventPayload, Job, JobProgress, JobStatus, TrashItem},
+    types::{Uuid, filesystem::display_relative},
+    util::{
+        EventBus, MetaTree, RootPath,
+        fs_ops::{move_entry, place_staged, remove_entry, staging_path},
+    },
+};
+
+/// Jobs allowed to run at the same time (others stay queued)
+const MAX_RUNNING_JOBS: usize = 4;
+
+/// Minimum time between persisting the progress of a running job
+const SAVE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Size of the buffer files are copied through
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// How often finished jobs are checked for removal
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Trash that an existing entry replaced by a job's result is moved into
+#[derive(Debug)]
+pub struct Replacement {
+    /// Root of the replaced entry
+    pub root: Uuid,
+    pub trash: MetaTree<TrashItem>,
+}
+
+/// Work performed by a job, on paths that were authorized when it was created
+#[derive(Debug)]
+pub enum JobTask {
+    /// Copies or moves `source` to `target`, replacing whatever is at `target` if `replace` is set
+    Transfer {
+        source: RootPath,
+        target: RootPath,
+        replace: Option<Replacement>,
+        copy: bool,
+    },
+
+    /// Moves an entry into the trash of `root`
+    Trash {
+        root: Uuid,
+        path: RootPath,
+        trash: MetaTree<TrashItem>,
+    },
+
+    /// Permanently deletes an entry
+    Delete { path: RootPath },
+
+    /// Writes `sources` into a zip archive at `target`
+    Archive {
+        sources: Vec<RootPath>,
+        target: RootPath,
+        replace: Option<Replacement>,
+    },
+}
+
+struct JobState {
+    job: Job,
+    saved: Instant,
+}
+
+/// Handle a running job reports its progress through
+#[derive(Clone)]
+struct JobContext {
+    state: Arc<Mutex<JobState>>,
+    jobs: MetaTree<Job>,
+    events: EventBus,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobContext {
+    fn update(&self, force: bool, update: impl FnOnce(Job) -> Job) {
+        let mut state = self.state.lock();
+        state.job = update(state.job.clone());
+        if force || state.saved.elapsed() >= SAVE_INTERVAL {
+            if let Err(err) = self.jobs.save(state.job.clone()) {
+                rocket::error!("Failed to save job {}: {err}", state.job.id());
+            }
+            self.events.publish(
+                state.job.owner(),
+                EventPayload::JobUpdated {
+                    job: Box::new(state.job.clone()),
+                },
+            );
+            state.saved = Instant::now();
+        }
+    }
+
+    fn check_cancelled(&self) -> crate::Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(crate::Error::JobCancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Updates the job's progress, failing with [`crate::Error::JobCancelled`] once it has been cancelled
+    fn progress(&self, update: impl FnOnce(&mut JobProgress)) -> crate::Result<()> {
+        self.update(false, |job| {
+            let mut progress = job.progress();
+            update(&mut progress);
+            job.with_progress(progress)
+        });
+        self.check_cancelled()
+    }
+
+    fn current(&self, relative: impl AsRef<Path>) -> crate::Result<()> {
+        self.progress(|progress| progress.current = Some(display_relative(relative)))
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        self.check_cancelled()?;
+        self.update(true, |job| {
+            job.with_status(JobStatus::Running)
+                .with_started(Some(Utc::now()))
+        });
+        Ok(())
+    }
+
+    fn finish(&self, result: crate::Result<()>) {
+        let (status, error) = match result {
+            Ok(()) => (JobStatus::Completed, None),
+            Err(crate::Error::JobCancelled) => (JobStatus::Cancelled, None),
+            Err(err) => (JobStatus::Failed, Some(err.to_string())),
+        };
+        self.update(true, |job| {
+            let mut progress = job.progress();
+            progress.current = None;
+            job.with_status(status)
+                .with_error(error)
+                .with_progress(progress)
+                .with_finished(Some(Utc::now()))
+        });
+    }
+}
+
+/// Jobs running in this process, and the limit on how many of them run at once
+pub struct JobRegistry {
+    cancellations: Arc<Mutex<HashMap<Uuid, Arc<AtomicBool>>>>,
+    permits: Arc<Semaphore>,
+
+    /// Where the progress of jobs is published to their owners
+    events: EventBus,
+}
+
+impl JobRegistry {
+    pub fn new(events: EventBus) -> Self {
+        Self {
+            cancellations: Default::default(),
+            permits: Arc::new(Semaphore::new(MAX_RUNNING_JOBS)),
+            events,
+        }
+    }
+
+    /// Persists `job` and runs `task` for it in the background
+    pub fn start(
+        &self,
+        job: Job,
+        task: JobTask,
+        jobs: MetaTree<Job>,
+        config: Config,
+    ) -> crate::Result<Job> {
+        let _ = jobs.save(job.clone())?;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancellations
+            .lock()
+            .insert(job.id(), cancelled.clone());
+
+        let context = JobContext {
+            state: Arc::new(Mutex::new(JobState {
+                job: job.clone(),
+                saved: Instant::now(),
+            })),
+            jobs,
+            events: self.events.clone(),
+            cancelled,
+        };
+        let (cancellations, permits, id) =
+            (self.cancellations.clone(), self.permits.clone(), job.id());
+        tokio::spawn(async move {
+            let result = match permits.acquire_owned().await {
+                Ok(_permit) => run(&context, task, &config).await,
+                Err(err) => Err(anyhow::Error::from(err).into()),
+            };
+            context.finish(result);
+            cancellations.lock().remove(&id);
+        });
+
+        Ok(job)
+    }
+
+    /// Requests a job to stop, returning `false` if it isn't running in this process
+    pub fn cancel(&self, id: &Uuid) -> bool {
+        match self.cancellations.lock().get(id) {
+            Some(cancelled) => {
+                cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_running(&self, id: &Uuid) -> bool {
+        self.cancellations.lock().contains_key(id)
+    }
+}
+
+async fn run(context: &JobContext, task: JobTask, config: &Config) -> crate::Result<()> {
+    context.start()?;
+    let hidden = tokio::fs::canonicalize(config.filesystem().metadata_dir()).await?;
+    match task {
+        JobTask::Transfer {
+            source,
+            target,
+            replace,
+            copy,
+        } => {
+            if copy {
+                return copy_into_place(context, config, &source, &target, replace, &hidden).await;
+            }
+
+            // Moves within a device are a single rename, anything else has to be copied
+            let device = tokio::fs::symlink_metadata(&source.absolute).await?.dev();
+            let target_device = match target.absolute.parent() {
+                Some(parent) => tokio::fs::metadata(parent).await?.dev(),
+                None => device,
+            };
+            if device == target_device {
+                context.current(&source.relative)?;
+                let moved = move_entry(
+                    &source.absolute,
+                    &target.absolute,
+                    &hidden,
+                    target.display(),
+                );
+                place_replacing(context, config, &target, replace, moved).await
+            } else {
+                copy_into_place(context, config, &source, &target, replace, &hidden).await?;
+                remove_entry(&source.absolute, true, source.display()).await
+            }
+        }
+        JobTask::Trash { root, path, trash } => {
+            context.current(&path.relative)?;
+            let owner = context.state.lock().job.owner();
+            TrashItem::trash(config, &trash, &root, &path, owner)
+                .await
+                .map(|_| ())
+        }
+        JobTask::Delete { path } => {
+            scan(context, [path.absolute.clone()], &hidden).await?;
+            delete_tree(context, &path).await
+        }
+        JobTask::Archive {
+            sources,
+            target,
+            replace,
+        } => {
+            scan(
+                context,
+                sources.iter().map(|source| source.absolute.clone()),
+                &hidden,
+            )
+            .await?;
+
+            let staging = staging_path(&target.absolute);
+            let written = {
+                let (context, staging) = (context.clone(), staging.clone());
+                tokio::task::spawn_blocking(move || {
+                    write_archive(&context, &sources, &staging, &hidden)
+                })
+                .await
+                .map_err(anyhow::Error::from)?
+            };
+            let result = match written {
+                Ok(()) => {
+                    let display = target.display();
+                    let placed = place_staged(&staging, &target.absolute, Path::new(&display));
+                    place_replacing(context, config, &target, replace, placed).await
+                }
+                Err(err) => Err(err),
+            };
+            if result.is_err() {
+                let _ = tokio::fs::remove_file(&staging).await;
+            }
+            result
+        }
+    }
+}
+
+/// Counts the files (and their bytes) under `paths`, so progress can be reported against totals
+async fn scan(
+    context: &JobContext,
+    paths: impl IntoIterator<Item = PathBuf>,
+    hidden: &Path,
+) -> crate::Result<()> {
+    let (mut files, mut bytes) = (0, 0);
+    let mut pending = paths.into_iter().collect::<Vec<_>>();
+    while let Some(path) = pending.pop() {
+        context.check_cancelled()?;
+        let metadata = tokio::fs::symlink_metadata(&path).await?;
+        if metadata.is_dir() {
+            let mut reader = tokio::fs::read_dir(&path).await?;
+            while let Some(item) = reader.next_entry().await? {
+                if item.path() != hidden {
+                    pending.push(item.path());
+                }
+            }
+        } else {
+            files += 1;
+            if metadata.is_file() {
+                bytes += metadata.len();
+            }
+        }
+    }
+
+    context.progress(|progress| {
+        progress.files_total = Some(files);
+        progress.bytes_total = Some(bytes);
+    })
+}
+
+/// Awaits `place` to put new content at `target`, moving the entry it replaces (if any)
+/// into the trash once it succeeded
+async fn place_replacing(
+    context: &JobContext,
+    config: &Config,
+    target: &RootPath,
+    replace: Option<Replacement>,
+    place: impl Future<Output = crate::Result<()>>,
+) -> crate::Result<()> {
+    let Some(replacement) = replace else {
+        return place.await;
+    };
+    let owner = context.state.lock().job.owner();
+    TrashItem::replace(
+        config,
+        &replacement.trash,
+        &replacement.root,
+        target,
+        owner,
+        place,
+    )
+    .await
+}
+
+/// Copies `source` to `target` through a staging copy, like [`super::fs_ops::copy_entry`]
+async fn copy_into_place(
+    context: &JobContext,
+    config: &Config,
+    source: &RootPath,
+    target: &RootPath,
+    replace: Option<Replacement>,
+    hidden: &Path,
+) -> crate::Result<()> {
+    scan(context, [source.absolute.clone()], hidden).await?;
+    let staging = staging_path(&target.absolute);
+    let result = match copy_tree(context, source, &staging, hidden).await {
+        Ok(()) => {
+            let display = target.display();
+            let placed = place_staged(&staging, &target.absolute, Path::new(&display));
+            place_replacing(context, config, target, replace, placed).await
+        }
+        Err(err) => Err(err),
+    };
+
+    if result.is_err() {
+        let _ = remove_entry(&staging, true, &staging).await;
+    }
+    result
+}
+
+async fn copy_tree(
+    context: &JobContext,
+    source: &RootPath,
+    destination: &Path,
+    hidden: &Path,
+) -> crate::Result<()> {
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    let mut pending = vec![(
+        source.absolute.clone(),
+        destination.to_path_buf(),
+        source.relative.clone(),
+    )];
+    while let Some((source, destination, relative)) = pending.pop() {
+        context.current(&relative)?;
+        let metadata = tokio::fs::symlink_metadata(&source).await?;
+        if metadata.is_symlink() {
+            tokio::fs::symlink(tokio::fs::read_link(&source).await?, &destination).await?;
+        } else if metadata.is_dir() {
+            tokio::fs::create_dir(&destination).await?;
+            let mut reader = tokio::fs::read_dir(&source).await?;
+            while let Some(item) = reader.next_entry().await? {
+                if item.path() != hidden {
+                    pending.push((
+                        item.path(),
+                        destination.join(item.file_name()),
+                        relative.join(item.file_name()),
+                    ));
+                }
+            }
+            continue;
+        } else {
+            let mut reader = tokio::fs::File::open(&source).await?;
+            let mut writer = tokio::fs::File::create(&destination).await?;
+            loop {
+                let read = reader.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                writer.write_all(&buffer[..read]).await?;
+                context.progress(|progress| progress.bytes_done += read as u64)?;
+            }
+            writer.flush().await?;
+            tokio::fs::set_permissions(&destination, metadata.permissions()).await?;
+        }
+
+        context.progress(|progress| progress.files_done += 1)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes a tree bottom-up, one entry at a time so progress can be reported
+async fn delete_tree(context: &JobContext, path: &RootPath) -> crate::Result<()> {
+    let mut pending = vec![(path.absolute.clone(), path.relative.clone(), false)];
+    while let Some((absolute, relative, expanded)) = pending.pop() {
+        let metadata = tokio::fs::symlink_metadata(&absolute).await?;
+        if metadata.is_dir() && !expanded {
+            pending.push((absolute.clone(), relative.clone(), true));
+            let mut reader = tokio::fs::read_dir(&absolute).await?;
+            while let Some(item) = reader.next_entry().await? {
+                pending.push((item.path(), relative.join(item.file_name()), false));
+            }
+            continue;
+        }
+
+        context.current(&relative)?;
+        if metadata.is_dir() {
+            tokio::fs::remove_dir(&absolute).await?;
+        } else {
+            tokio::fs::remove_file(&absolute).await?;
+            context.progress(|progress| {
+                progress.files_done += 1;
+                if metadata.is_file() {
+                    progress.bytes_done += metadata.len();
+                }
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes `sources` (named by their file names) into a new zip archive at `destination`
+fn write_archive(
+    context: &JobContext,
+    sources: &[RootPath],
+    destination: &Path,
+    hidden: &Path,
+) -> crate::Result<()> {
+    let zip_error = |err: zip::result::ZipError| crate::Error::from(anyhow::Error::from(err));
+    let mut archive = ZipWriter::new(std::fs::File::create_new(destination)?);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+
+    for source in sources {
+        let base = source.absolute.parent().unwrap_or(Path::new("/"));
+        let relative_base = source.relative.parent().unwrap_or(Path::new(""));
+        let mut pending = vec![source.absolute.clone()];
+        while let Some(path) = pending.pop() {
+            let name = path
+                .strip_prefix(base)
+                .map_err(anyhow::Error::from)?
+                .to_string_lossy()
+                .to_string();
+            context.current(relative_base.join(&name))?;
+
+            let metadata = std::fs::symlink_metadata(&path)?;
+            let options = options.unix_permissions(metadata.permissions().mode());
+            if metadata.is_symlink() {
+                archive
+                    .add_symlink(name, std::fs::read_link(&path)?.to_string_lossy(), options)
+                    .map_err(zip_error)?;
+            } else if metadata.is_dir() {
+                archive.add_directory(name, options).map_err(zip_error)?;
+                for item in std::fs::read_dir(&path)? {
+                    let item = item?;
+                    if item.path() != hidden {
+                        pending.push(item.path());
+                    }
+                }
+                continue;
+            } else {
+                archive.start_file(name, options).map_err(zip_error)?;
+                let mut file = std::fs::File::open(&path)?;
+                loop {
+                    let read = file.read(&mut buffer)?;
+                    if read == 0 {
+                        break;
+                    }
+                    archive.write_all(&buffer[..read])?;
+                    context.progress(|progress| progress.bytes_done += read as u64)?;
+                }
+            }
+
+            context.progress(|progress| progress.files_done += 1)?;
+        }
+    }
+
+    archive.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+/// Marks jobs that were running when the server last stopped as interrupted
+fn mark_interrupted(jobs: &MetaTree<Job>, registry: &JobRegistry) -> crate::Result<()> {
+    for job in jobs.all()? {
+        if !job.status().is_finished() && !registry.is_running(&job.id()) {
+            let _ = jobs.save(
+                job.with_status(JobStatus::Interrupted)
+                    .with_finished(Some(Utc::now())),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn purge_stale_jobs(jobs: &MetaTree<Job>) -> crate::Result<()> {
+    for job in jobs.all()? {
+        if job.is_stale() {
+            let _ = jobs.delete(job.id().to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Marks jobs interrupted by a restart, and periodically forgets jobs that finished a while ago
+pub fn job_supervisor() -> AdHoc {
+    AdHoc::on_liftoff("Supervise background jobs", |rocket| {
+        Box::pin(async move {
+            let jobs = MetaTree::<Job>::from_rocket(rocket).unwrap();
+            if let Some(registry) = rocket.state::<JobRegistry>()
+                && let Err(err) = mark_interrupted(&jobs, registry)
+            {
+                rocket::error!("Failed to mark interrupted jobs: {err}");
+            }
+
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(PURGE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = purge_stale_jobs(&jobs) {
+                        rocket::error!("Failed to purge finished jobs: {err}");
+                    }
+                }
+            });
+        })
+    })
+}
@@ -0,0 +1,96 @@
+use std::marker::PhantomData;
+
+use rocket::{
+    Orbit, Request, Rocket,
+    http::Status,
+    request::{self, FromRequest},
+};
+use rocket_okapi::{
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::models::MetaRecord;
+
+/// Typed view over a single tree of the sled metadata database
+#[derive(Clone, Debug)]
+pub struct MetaTree<T: MetaRecord>(sled::Tree, PhantomData<T>);
+
+#[rocket::async_trait]
+impl<'r, T: MetaRecord> FromRequest<'r> for MetaTree<T> {
+    type Error = crate::Error;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        if let Some(db) = req.rocket().state::<sled::Db>() {
+            match Self::new(db) {
+                Ok(tree) => request::Outcome::Success(tree),
+                Err(err) => request::Outcome::Error((Status::InternalServerError, err)),
+            }
+        } else {
+            request::Outcome::Error((
+                Status::InternalServerError,
+                crate::Error::MissingState(String::from("sled::Db")),
+            ))
+        }
+    }
+}
+
+impl<'r, T: MetaRecord> OpenApiFromRequest<'r> for MetaTree<T> {
+    fn from_request_input(
+        _gen: &mut OpenApiGenerator,
+        _name: String,
+        _required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        Ok(RequestHeaderInput::None)
+    }
+}
+
+impl<T: MetaRecord> MetaTree<T> {
+    pub fn new(db: &sled::Db) -> crate::Result<Self> {
+        Ok(Self(db.open_tree(T::tree())?, PhantomData))
+    }
+
+    pub fn from_rocket(rocket: &Rocket<Orbit>) -> crate::Result<Self> {
+        Self::new(
+            rocket
+                .state::<sled::Db>()
+                .ok_or(crate::Error::MissingState(String::from("sled::Db")))?,
+        )
+    }
+
+    pub fn get(&self, id: impl AsRef<str>) -> crate::Result<Option<T>> {
+        match self.0.get(id.as_ref())? {
+            Some(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save(&self, record: T) -> crate::Result<Option<T>> {
+        match self
+            .0
+            .insert(record.record_id(), serde_json::to_vec(&record)?)?
+        {
+            Some(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn delete(&self, id: impl AsRef<str>) -> crate::Result<Option<T>> {
+        match self.0.remove(id.as_ref())? {
+            Some(raw) => Ok(Some(serde_json::from_slice(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// All records in the tree (entries that fail to deserialize are skipped)
+    pub fn all(&self) -> crate::Result<Vec<T>> {
+        let mut records = Vec::new();
+        for item in self.0.iter() {
+            let (_, raw) = item?;
+            if let Ok(record) = serde_json::from_slice(&raw) {
+                records.push(record);
+            }
+        }
+
+        Ok(records)
+    }
+}
@@ -2,4 +2,56 @@ pub mod collection;
 pub use collection::Collection;
 
 mod generate_resources;
-pub use generate_resources::generate_resources;
\ No newline at end of file
+pub use generate_resources::generate_resources;
+
+mod session_cleanup;
+pub use session_cleanup::session_cleanup;
+
+mod trash_cleanup;
+pub use trash_cleanup::trash_cleanup;
+
+mod upload_cleanup;
+pub use upload_cleanup::upload_cleanup;
+
+pub mod upload_locks;
+pub use upload_locks::UploadLocks;
+
+pub mod events;
+pub use events::{EventBus, MessageChannel, json_message};
+
+pub mod fs_watch;
+pub use fs_watch::{FsChange, FsWatcher, WatchGuard};
+
+pub mod jobs;
+pub use jobs::{JobRegistry, JobTask, Replacement, job_supervisor};
+
+pub mod administration;
+pub use administration::check_administrator;
+
+pub mod auth_context;
+pub use auth_context::{AuthContext, AuthMethod};
+
+pub mod root_access;
+pub use root_access::{AccessLevel, EditAccess, ManageAccess, ReadAccess, RootAccess, RootPath};
+
+pub mod download;
+pub use download::{DownloadConditions, FileDownload};
+
+pub mod meta;
+pub use meta::MetaTree;
+
+pub mod fs_ops;
+
+pub mod access_password;
+pub use access_password::{AccessPassword, PasswordProtected};
+
+pub mod oidc;
+pub use oidc::{OidcClient, OidcDiscovery, OidcIdentity};
+
+pub mod session_cookie;
+pub use session_cookie::{
+    remove_session_cookies, set_oidc_login_cookie, set_session_cookies, take_oidc_login_cookie,
+};
+
+pub mod webauthn;
+pub use webauthn::WebauthnJson;
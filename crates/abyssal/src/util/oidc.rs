@@ -0,0 +1,415 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use base64::Engine as _;
+use chrono::Utc;
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use tokio::sync::Mutex;
+
+use crate::{models::OidcLogin, types::config::OidcConfig};
+
+fn provider_error(err: impl ToString) -> crate::Error {
+    crate::Error::OidcProvider(err.to_string())
+}
+
+fn invalid_token(reason: impl Into<String>) -> crate::Error {
+    crate::Error::OidcInvalidToken(reason.into())
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+
+    #[serde(default)]
+    userinfo_endpoint: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct TokenResponse {
+    id_token: String,
+    access_token: String,
+}
+
+/// User identity established by a completed OIDC login
+#[derive(Clone, Debug)]
+pub struct OidcIdentity {
+    pub issuer: String,
+    pub subject: String,
+    pub username: String,
+    pub groups: Vec<String>,
+}
+
+/// Looks up a (possibly `.`-separated, nested) claim
+fn claim<'a>(claims: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut segments = path.split('.');
+    let mut current = claims.get(segments.next()?)?;
+    for segment in segments {
+        current = current.as_object()?.get(segment)?;
+    }
+
+    Some(current)
+}
+
+/// How long discovered provider metadata is reused before being fetched again
+const DISCOVERY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Client for the configured OIDC provider
+#[derive(Clone)]
+pub struct OidcClient {
+    config: OidcConfig,
+    metadata: ProviderMetadata,
+    http: reqwest::Client,
+}
+
+impl OidcClient {
+    /// Fetches the provider's metadata through OIDC discovery
+    pub async fn discover(config: OidcConfig) -> crate::Result<Self> {
+        let http = reqwest::Client::new();
+        let metadata = http
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                config.issuer().trim_end_matches('/')
+            ))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(provider_error)?
+            .json::<ProviderMetadata>()
+            .await
+            .map_err(provider_error)?;
+
+        Ok(Self {
+            config,
+            metadata,
+            http,
+        })
+    }
+
+    /// URL to send the user to in order to start `login`
+    pub fn authorization_url(&self, login: &OidcLogin) -> crate::Result<String> {
+        let mut url = Url::parse(&self.metadata.authorization_endpoint).map_err(provider_error)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id())
+            .append_pair("redirect_uri", &self.config.redirect_url())
+            .append_pair("scope", &self.config.scopes().join(" "))
+            .append_pair("state", &login.state())
+            .append_pair("nonce", &login.nonce())
+            .append_pair("code_challenge", &login.challenge())
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.to_string())
+    }
+
+    /// Exchanges an authorization code, validating the returned ID token against `login`
+    pub async fn exchange(&self, login: &OidcLogin, code: String) -> crate::Result<OidcIdentity> {
+        let mut form = vec![
+            ("grant_type", String::from("authorization_code")),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url()),
+            ("client_id", self.config.client_id()),
+            ("code_verifier", login.verifier()),
+        ];
+        if let Some(secret) = self.config.client_secret() {
+            form.push(("client_secret", secret));
+        }
+
+        let tokens = self
+            .http
+            .post(&self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(provider_error)?
+            .json::<TokenResponse>()
+            .await
+            .map_err(provider_error)?;
+
+        // The ID token comes straight from the token endpoint over TLS, so (per OIDC Core 3.1.3.7)
+        // its signature doesn't need to be verified, only its claims.
+        let mut claims = self.id_token_claims(&tokens.id_token, login)?;
+        if let Some(userinfo) = self.userinfo(&tokens.access_token).await? {
+            if userinfo.get("sub") != claims.get("sub") {
+                return Err(invalid_token("userinfo subject mismatch"));
+            }
+            for (key, value) in userinfo {
+                claims.entry(key).or_insert(value);
+            }
+        }
+
+        let subject = claim(&claims, "sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| invalid_token("missing subject"))?
+            .to_string();
+        let username = claim(&claims, &self.config.username_claim())
+            .and_then(Value::as_str)
+            .map(String::from)
+            .unwrap_or_else(|| subject.clone());
+        let groups = match claim(&claims, &self.config.groups_claim()) {
+            Some(Value::Array(groups)) => groups
+                .iter()
+                .filter_map(|group| group.as_str().map(String::from))
+                .collect(),
+            Some(Value::String(group)) => vec![group.clone()],
+            _ => Vec::new(),
+        };
+
+        Ok(OidcIdentity {
+            issuer: self.metadata.issuer.clone(),
+            subject,
+            username,
+            groups,
+        })
+    }
+
+    fn id_token_claims(
+        &self,
+        id_token: &str,
+        login: &OidcLogin,
+    ) -> crate::Result<Map<String, Value>> {
+        let payload = id_token
+            .split('.')
+            .nth(1)
+            .ok_or_else(|| invalid_token("malformed token"))?;
+        let decoded = base64::prelude::BASE64_URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .map_err(|_| invalid_token("malformed token"))?;
+        let claims = serde_json::from_slice::<Map<String, Value>>(&decoded)
+            .map_err(|_| invalid_token("malformed token"))?;
+
+        if claims.get("iss").and_then(Value::as_str) != Some(self.metadata.issuer.as_str()) {
+            return Err(invalid_token("issuer mismatch"));
+        }
+
+        let client_id = self.config.client_id();
+        let audience_matches = match claims.get("aud") {
+            Some(Value::String(audience)) => *audience == client_id,
+            Some(Value::Array(audiences)) => audiences
+                .iter()
+                .any(|audience| audience.as_str() == Some(client_id.as_str())),
+            _ => false,
+        };
+        if !audience_matches {
+            return Err(invalid_token("audience mismatch"));
+        }
+
+        if claims.get("nonce").and_then(Value::as_str) != Some(login.nonce().as_str()) {
+            return Err(invalid_token("nonce mismatch"));
+        }
+
+        match claims.get("exp").and_then(Value::as_i64) {
+            Some(expires) if expires > Utc::now().timestamp() => Ok(claims),
+            _ => Err(invalid_token("token expired")),
+        }
+    }
+
+    async fn userinfo(&self, access_token: &str) -> crate::Result<Option<Map<String, Value>>> {
+        let Some(endpoint) = self.metadata.userinfo_endpoint.clone() else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            self.http
+                .get(endpoint)
+                .bearer_auth(access_token)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(provider_error)?
+                .json::<Map<String, Value>>()
+                .await
+                .map_err(provider_error)?,
+        ))
+    }
+}
+
+/// Caches the discovered provider, so logins don't each fetch its metadata
+#[derive(Clone, Default)]
+pub struct OidcDiscovery {
+    cached: Arc<Mutex<Option<(Instant, OidcClient)>>>,
+}
+
+impl OidcDiscovery {
+    /// Client for the configured provider, discovering it if it hasn't been recently
+    pub async fn client(&self, config: OidcConfig) -> crate::Result<OidcClient> {
+        let mut cached = self.cached.lock().await;
+        if let Some((discovered, client)) = cached.as_ref()
+            && discovered.elapsed() < DISCOVERY_TTL
+        {
+            return Ok(client.clone());
+        }
+
+        let client = OidcClient::discover(config).await?;
+        *cached = Some((Instant::now(), client.clone()));
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Minimal provider serving discovery, token & userinfo endpoints for a single `login`
+    struct MockProvider {
+        issuer: String,
+        discoveries: Arc<AtomicUsize>,
+    }
+
+    impl MockProvider {
+        async fn start(login: OidcLogin) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+            let discoveries = Arc::new(AtomicUsize::new(0));
+
+            let (base, counter) = (issuer.clone(), discoveries.clone());
+            tokio::spawn(async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let request = read_request(&mut stream).await;
+                    let (status, body) = respond(&base, &login, &counter, &request);
+                    let response = format!(
+                        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            });
+
+            Self {
+                issuer,
+                discoveries,
+            }
+        }
+
+        fn config(&self) -> OidcConfig {
+            serde_json::from_value(json!({
+                "issuer": self.issuer,
+                "client_id": "abyssal",
+                "redirect_url": "https://files.example.com/oidc",
+            }))
+            .unwrap()
+        }
+    }
+
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if body.len() >= length || read == 0 {
+                    return text;
+                }
+            }
+        }
+    }
+
+    fn respond(
+        issuer: &str,
+        login: &OidcLogin,
+        discoveries: &AtomicUsize,
+        request: &str,
+    ) -> (&'static str, String) {
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        match path {
+            "/.well-known/openid-configuration" => {
+                discoveries.fetch_add(1, Ordering::SeqCst);
+                let metadata = json!({
+                    "issuer": issuer,
+                    "authorization_endpoint": format!("{issuer}/authorize"),
+                    "token_endpoint": format!("{issuer}/token"),
+                    "userinfo_endpoint": format!("{issuer}/userinfo"),
+                });
+                ("200 OK", metadata.to_string())
+            }
+            "/token" if request.contains(&format!("code_verifier={}", login.verifier())) => {
+                let claims = json!({
+                    "iss": issuer,
+                    "aud": "abyssal",
+                    "sub": "subject-1",
+                    "preferred_username": "alice",
+                    "nonce": login.nonce(),
+                    "exp": Utc::now().timestamp() + 60,
+                });
+                let encode = |value: &Value| {
+                    base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(value.to_string())
+                };
+                let id_token = format!("{}.{}.", encode(&json!({"alg": "none"})), encode(&claims));
+                let tokens = json!({"id_token": id_token, "access_token": "access-1"});
+                ("200 OK", tokens.to_string())
+            }
+            "/userinfo" if request.contains("Bearer access-1") => {
+                let userinfo = json!({"sub": "subject-1", "groups": ["staff"]});
+                ("200 OK", userinfo.to_string())
+            }
+            _ => (
+                "400 Bad Request",
+                json!({"error": "invalid_request"}).to_string(),
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_in_through_cached_provider() {
+        let login = OidcLogin::new();
+        let provider = MockProvider::start(login.clone()).await;
+        let discovery = OidcDiscovery::default();
+
+        let client = discovery.client(provider.config()).await.unwrap();
+        let url = Url::parse(&client.authorization_url(&login).unwrap()).unwrap();
+        let query = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+        assert_eq!(url.path(), "/authorize");
+        assert_eq!(query["state"], login.state());
+        assert_eq!(query["code_challenge"], login.challenge());
+
+        let identity = discovery
+            .client(provider.config())
+            .await
+            .unwrap()
+            .exchange(&login, String::from("code-1"))
+            .await
+            .unwrap();
+        assert_eq!(provider.discoveries.load(Ordering::SeqCst), 1);
+        assert_eq!(identity.issuer, provider.issuer);
+        assert_eq!(identity.subject, "subject-1");
+        assert_eq!(identity.username, "alice");
+        assert_eq!(identity.groups, vec![String::from("staff")]);
+    }
+
+    #[tokio::test]
+    async fn rejects_codes_of_other_logins() {
+        let provider = MockProvider::start(OidcLogin::new()).await;
+        let client = OidcClient::discover(provider.config()).await.unwrap();
+
+        let result = client
+            .exchange(&OidcLogin::new(), String::from("code-1"))
+            .await;
+        assert!(matches!(result, Err(crate::Error::OidcProvider(_))));
+    }
+}
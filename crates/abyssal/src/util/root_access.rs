@@ -0,0 +1,452 @@
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use rocket::{
+    Request,
+    http::Status,
+    request::{self, FromRequest},
+};
+use rocket_okapi::{
+    r#gen::OpenApiGenerator,
+    request::{OpenApiFromRequest, RequestHeaderInput},
+};
+
+use crate::{
+    models::{RootDirectory, User, UserMethods},
+    types::{
+        Config, ConflictStrategy, PermissionCapability, Uuid,
+        filesystem::{display_relative, normalize_relative},
+    },
+    util::{Collection, fs_ops::numbered_name},
+};
+
+/// Capability a [`RootAccess`] guard requires on its root
+pub trait AccessLevel: Send + Sync + 'static {
+    fn capability() -> PermissionCapability;
+}
+
+pub struct ReadAccess;
+pub struct EditAccess;
+pub struct ManageAccess;
+
+impl AccessLevel for ReadAccess {
+    fn capability() -> PermissionCapability {
+        PermissionCapability::Read
+    }
+}
+
+impl AccessLevel for EditAccess {
+    fn capability() -> PermissionCapability {
+        PermissionCapability::Edit
+    }
+}
+
+impl AccessLevel for ManageAccess {
+    fn capability() -> PermissionCapability {
+        PermissionCapability::Manage
+    }
+}
+
+/// A path inside of a root directory that the caller has been authorized to access
+#[derive(Clone, Debug)]
+pub struct RootPath {
+    pub relative: PathBuf,
+    pub absolute: PathBuf,
+}
+
+impl RootPath {
+    /// Client-facing (`/`-prefixed, root-relative) form of this path
+    pub fn display(&self) -> String {
+        display_relative(&self.relative)
+    }
+}
+
+/// Request guard resolving the `<id>` root of a filesystem route and checking that the
+/// caller holds at least `L` somewhere inside of it. Individual paths must then be
+/// authorized through [`RootAccess::resolve`], which applies the caller's `RootTopLevel` scope.
+pub struct RootAccess<L: AccessLevel> {
+    user: User,
+    root: RootDirectory,
+    config: Config,
+    _level: PhantomData<L>,
+}
+
+impl<L: AccessLevel> RootAccess<L> {
+    pub fn new(user: User, root: RootDirectory, config: Config) -> crate::Result<Self> {
+        let scopes = user.permissions().scopes(&root.id(), user.name());
+        if scopes.is_empty() {
+            Err(crate::Error::RootNotFound(root.id()))
+        } else if !scopes
+            .iter()
+            .any(|(_, capability)| capability.has_at_least(L::capability()))
+        {
+            Err(crate::Error::Forbidden)
+        } else {
+            Ok(Self {
+                user,
+                root,
+                config,
+                _level: PhantomData,
+            })
+        }
+    }
+
+    pub fn user(&self) -> User {
+        self.user.clone()
+    }
+
+    pub fn root(&self) -> RootDirectory {
+        self.root.clone()
+    }
+
+    /// Checks the caller holds `capability` on a path without resolving it on disk
+    /// (which may not exist anymore), returning its normalized form
+    pub fn authorize(
+        &self,
+        path: impl AsRef<Path>,
+        capability: PermissionCapability,
+    ) -> crate::Result<PathBuf> {
+        let relative = normalize_relative(path)?;
+        if self.user.permissions().can_access(
+            &self.root.id(),
+            &relative,
+            capability,
+            self.user.name(),
+        ) {
+            Ok(relative)
+        } else {
+            Err(crate::Error::Forbidden)
+        }
+    }
+
+    /// Authorizes and resolves a client-supplied path with this guard's capability
+    pub async fn resolve(&self, path: impl AsRef<Path>) -> crate::Result<RootPath> {
+        self.resolve_with(path, L::capability()).await
+    }
+
+    /// Authorizes and resolves a client-supplied path with an explicit capability
+    pub async fn resolve_with(
+        &self,
+        path: impl AsRef<Path>,
+        capability: PermissionCapability,
+    ) -> crate::Result<RootPath> {
+        let relative = self.authorize(path, capability.clone())?;
+        let absolute = self.root.resolve(&self.config, &relative).await?;
+
+        // Symlinks inside of the caller's scope may point outside of it
+        let canonical = self
+            .root
+            .canonical_relative(&self.config, &absolute)
+            .await?;
+        for path in canonical {
+            self.authorize(path, capability.clone())?;
+        }
+        Ok(RootPath { relative, absolute })
+    }
+
+    /// Root-relative paths `path` refers to once symlinks are followed
+    pub async fn canonical(&self, path: &RootPath) -> crate::Result<Vec<PathBuf>> {
+        self.root
+            .canonical_relative(&self.config, &path.absolute)
+            .await
+    }
+
+    /// Ensures `path` may be moved, renamed or deleted. The root itself, the caller's
+    /// scope directories and anything containing the metadata directory are protected.
+    pub async fn check_modifiable(&self, path: &RootPath) -> crate::Result<()> {
+        let metadata_dir = tokio::fs::canonicalize(self.config.filesystem().metadata_dir()).await?;
+        if path.relative.as_os_str().is_empty()
+            || metadata_dir.starts_with(&path.absolute)
+            || self
+                .user
+                .permissions()
+                .scopes(&self.root.id(), self.user.name())
+                .iter()
+                .any(|(scope, _)| *scope == path.relative)
+        {
+            Err(crate::Error::protected_path(path.display()))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Authorizes the path a new entry called `name` would be created at inside of `directory`,
+    /// applying `conflict` if something already exists there. Also returns whether that
+    /// existing entry has to be replaced.
+    pub async fn resolve_target(
+        &self,
+        directory: impl AsRef<Path>,
+        name: impl AsRef<str>,
+        conflict: &ConflictStrategy,
+    ) -> crate::Result<(RootPath, bool)> {
+        let directory = normalize_relative(directory)?;
+        let mut n = 0;
+        loop {
+            let target = self
+                .resolve(directory.join(numbered_name(name.as_ref(), n)))
+                .await?;
+            if tokio::fs::symlink_metadata(&target.absolute).await.is_err() {
+                return Ok((target, false));
+            }
+
+            match conflict {
+                ConflictStrategy::Fail => {
+                    return Err(crate::Error::path_conflict(target.display()));
+                }
+                ConflictStrategy::Overwrite => {
+                    self.check_modifiable(&target).await?;
+                    let target = self
+                        .resolve_with(&target.relative, PermissionCapability::Manage)
+                        .await?;
+                    return Ok((target, true));
+                }
+                ConflictStrategy::Rename => n += 1,
+            }
+        }
+    }
+
+    async fn from_request_inner(req: &Request<'_>) -> crate::Result<Self> {
+        let user = match User::from_request(req).await {
+            request::Outcome::Success(user) => user,
+            request::Outcome::Error((_, err)) => return Err(err),
+            request::Outcome::Forward(_) => return Err(crate::Error::MissingAuthorization),
+        };
+        let config = req
+            .rocket()
+            .state::<Config>()
+            .cloned()
+            .ok_or(crate::Error::MissingState(String::from("abyssal::Config")))?;
+        let roots = match Collection::<RootDirectory>::from_request(req).await {
+            request::Outcome::Success(roots) => roots,
+            request::Outcome::Error((_, err)) => return Err(err),
+            request::Outcome::Forward(_) => {
+                return Err(crate::Error::MissingState(String::from("mongodb::Client")));
+            }
+        };
+
+        // Find the `<id>` segment in the route so the guard works regardless of mount point
+        let id = req
+            .route()
+            .and_then(|route| {
+                route
+                    .uri
+                    .path()
+                    .split('/')
+                    .filter(|segment| !segment.is_empty())
+                    .position(|segment| segment == "<id>")
+            })
+            .and_then(|index| req.uri().path().segments().get(index))
+            .ok_or(crate::Error::MissingState(String::from(
+                "<id> route segment",
+            )))?
+            .parse::<Uuid>()?;
+        let root = roots
+            .get(id.clone())
+            .await?
+            .ok_or(crate::Error::RootNotFound(id))?;
+
+        Self::new(user, root, config)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, L: AccessLevel> FromRequest<'r> for RootAccess<L> {
+    type Error = crate::Error;
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        match Self::from_request_inner(req).await {
+            Ok(resolved) => request::Outcome::Success(resolved),
+            Err(err) => request::Outcome::Error((Status::new(err.metadata().status), err)),
+        }
+    }
+}
+
+impl<'r, L: AccessLevel> OpenApiFromRequest<'r> for RootAccess<L> {
+    fn from_request_input(
+        generator: &mut OpenApiGenerator,
+        name: String,
+        required: bool,
+    ) -> rocket_okapi::Result<RequestHeaderInput> {
+        User::from_request_input(generator, name, required)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::types::{Permission, PermissionSet, RootTopLevel};
+
+    /// Filesystem holding a `/data` root with two home directories and a shared directory,
+    /// where alice's home contains symlinks into bob's home and out of the root
+    struct Fixture {
+        base: PathBuf,
+        config: Config,
+        root: RootDirectory,
+    }
+
+    impl Fixture {
+        async fn new() -> Self {
+            let base = std::env::temp_dir().join(format!("abyssal-access-{}", Uuid::new()));
+            let data = base.join("data");
+            for directory in [
+                ".abyssal",
+                "outside",
+                "data/home/alice",
+                "data/home/bob",
+                "data/shared",
+            ] {
+                tokio::fs::create_dir_all(base.join(directory))
+                    .await
+                    .unwrap();
+            }
+            for file in [
+                "outside/secret",
+                "data/home/alice/notes",
+                "data/home/bob/secret",
+                "data/shared/doc",
+            ] {
+                tokio::fs::write(base.join(file), b"").await.unwrap();
+            }
+            tokio::fs::symlink("../bob", data.join("home/alice/bob"))
+                .await
+                .unwrap();
+            tokio::fs::symlink(base.join("outside"), data.join("home/alice/outside"))
+                .await
+                .unwrap();
+
+            let config =
+                serde_json::from_value(json!({"filesystem": {"filesystem": base}})).unwrap();
+            Self {
+                base,
+                config,
+                root: RootDirectory::new("data", None::<String>, "/data"),
+            }
+        }
+
+        fn access<L: AccessLevel>(
+            &self,
+            top_level: RootTopLevel,
+            capability: PermissionCapability,
+        ) -> RootAccess<L> {
+            let permissions = PermissionSet::from(vec![Permission::RootDirectory {
+                root: self.root.id(),
+                top_level,
+                capability,
+            }]);
+            let user = User::create_oidc("alice", "issuer", "subject", vec![])
+                .with_permissions(permissions);
+            RootAccess::new(user, self.root.clone(), self.config.clone()).unwrap()
+        }
+
+        fn home<L: AccessLevel>(&self) -> RootAccess<L> {
+            self.access(
+                RootTopLevel::Home {
+                    parent: String::from("/home"),
+                },
+                PermissionCapability::Manage,
+            )
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.base);
+        }
+    }
+
+    #[tokio::test]
+    async fn home_scope_confines_to_the_users_home() {
+        let fixture = Fixture::new().await;
+        let access = fixture.home::<ReadAccess>();
+
+        let notes = access.resolve("/home/alice/notes").await.unwrap();
+        assert_eq!(notes.relative, PathBuf::from("home/alice/notes"));
+        assert!(notes.absolute.ends_with("data/home/alice/notes"));
+
+        for path in [
+            "/home/bob/secret",
+            "/home/alice/../bob/secret",
+            "/shared/doc",
+            "/",
+        ] {
+            assert!(
+                matches!(access.resolve(path).await, Err(crate::Error::Forbidden)),
+                "{path} was accessible"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn directory_scope_confines_to_its_directory() {
+        let fixture = Fixture::new().await;
+        let access = fixture.access::<ReadAccess>(
+            RootTopLevel::Directory {
+                path: String::from("/shared"),
+            },
+            PermissionCapability::Read,
+        );
+
+        assert!(access.resolve("shared/doc").await.is_ok());
+        assert!(matches!(
+            access.resolve("/home/alice/notes").await,
+            Err(crate::Error::Forbidden)
+        ));
+        assert!(matches!(
+            access
+                .resolve_with("/shared/doc", PermissionCapability::Edit)
+                .await,
+            Err(crate::Error::Forbidden)
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_escaping_the_root() {
+        let fixture = Fixture::new().await;
+        let access = fixture.access::<ReadAccess>(RootTopLevel::Root, PermissionCapability::Read);
+
+        for path in ["..", "../outside/secret", "/home/../../outside"] {
+            assert!(
+                matches!(
+                    access.resolve(path).await,
+                    Err(crate::Error::PathOutsideRoot(_))
+                ),
+                "{path} escaped"
+            );
+        }
+
+        // Absolute paths are relative to the root, never to the host filesystem
+        let absolute = access.resolve("/outside").await.unwrap();
+        assert_eq!(
+            absolute.absolute,
+            fixture.base.canonicalize().unwrap().join("data/outside")
+        );
+    }
+
+    #[tokio::test]
+    async fn symlinks_cant_lead_out_of_a_scope() {
+        let fixture = Fixture::new().await;
+        let access = fixture.home::<ReadAccess>();
+
+        // Into another user's home, through the link itself or a path beneath it
+        for path in ["/home/alice/bob", "/home/alice/bob/secret"] {
+            assert!(
+                matches!(access.resolve(path).await, Err(crate::Error::Forbidden)),
+                "{path} was accessible"
+            );
+        }
+
+        // Out of the root entirely
+        for path in ["/home/alice/outside", "/home/alice/outside/secret"] {
+            assert!(
+                matches!(
+                    access.resolve(path).await,
+                    Err(crate::Error::PathOutsideRoot(_))
+                ),
+                "{path} escaped"
+            );
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use bson::doc;
+use rocket::fairing::AdHoc;
+
+use crate::{
+    Config,
+    models::{LoginThrottle, Token},
+    util::{Collection, MetaTree},
+};
+
+/// How often expired session tokens are purged
+const PURGE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+async fn purge_expired_tokens(config: &Config, tokens: &Collection<Token>) -> crate::Result<()> {
+    let mut cursor = tokens.find(doc! {}).await?;
+    let mut expired = Vec::new();
+    while cursor.advance().await? {
+        let token = cursor.deserialize_current()?;
+        if token.is_expired(&config.authentication()) {
+            expired.push(token.id());
+        }
+    }
+
+    if !expired.is_empty() {
+        let _ = tokens.delete_many(doc! {"id": {"$in": expired}}).await?;
+    }
+    Ok(())
+}
+
+/// Forgets failed login counters that have reset, so the tree doesn't grow forever
+fn purge_stale_throttles(
+    config: &Config,
+    throttles: &MetaTree<LoginThrottle>,
+) -> crate::Result<()> {
+    let throttle_config = config.authentication().login_throttle();
+    for throttle in throttles.all()? {
+        if throttle.is_stale(&throttle_config) {
+            let _ = throttles.delete(throttle.subject().key())?;
+        }
+    }
+    Ok(())
+}
+
+/// Periodically deletes session tokens past their lifetime or idle timeout, and stale login throttles
+pub fn session_cleanup() -> AdHoc {
+    AdHoc::on_liftoff("Purge expired sessions", |rocket| {
+        Box::pin(async move {
+            let config = rocket.state::<Config>().cloned().unwrap();
+            let tokens = Collection::<Token>::from_rocket(rocket);
+            let throttles = MetaTree::<LoginThrottle>::from_rocket(rocket).unwrap();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(PURGE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = purge_expired_tokens(&config, &tokens).await {
+                        rocket::error!("Failed to purge expired sessions: {err}");
+                    }
+                    if let Err(err) = purge_stale_throttles(&config, &throttles) {
+                        rocket::error!("Failed to purge stale login throttles: {err}");
+                    }
+                }
+            });
+        })
+    })
+}
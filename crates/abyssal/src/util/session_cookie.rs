@@ -0,0 +1,82 @@
+use rocket::{
+    http::{Cookie, CookieJar, SameSite},
+    time::Duration,
+};
+
+use crate::{
+    models::{OidcLogin, Token, oidc_login::LOGIN_LIFETIME},
+    types::config::AuthConfig,
+};
+
+/// Private (encrypted) cookie holding the session token
+pub const SESSION_COOKIE: &str = "abyssal_session";
+
+/// Script-readable cookie holding the session's CSRF token
+pub const CSRF_COOKIE: &str = "abyssal_csrf";
+
+/// Header state-changing requests authenticated by [`SESSION_COOKIE`] must echo [`CSRF_COOKIE`] in
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Private (encrypted) cookie binding a pending OIDC login to the client that started it
+pub const OIDC_LOGIN_COOKIE: &str = "abyssal_oidc_login";
+
+fn session_cookie<'c>(
+    name: &'static str,
+    value: String,
+    http_only: bool,
+    config: &AuthConfig,
+) -> Cookie<'c> {
+    let mut cookie = Cookie::build((name, value))
+        .path("/")
+        .http_only(http_only)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .build();
+    if config.session_lifetime() > 0 {
+        cookie.set_max_age(Duration::seconds(config.session_lifetime() as i64));
+    }
+    cookie
+}
+
+/// Sets the session & CSRF cookies for `token`, letting browsers authenticate without
+/// keeping the token in script-accessible storage
+pub fn set_session_cookies(cookies: &CookieJar<'_>, token: &Token, config: &AuthConfig) {
+    cookies.add_private(session_cookie(
+        SESSION_COOKIE,
+        token.id().to_string(),
+        true,
+        config,
+    ));
+    cookies.add(session_cookie(
+        CSRF_COOKIE,
+        token.csrf_token(),
+        false,
+        config,
+    ));
+}
+
+pub fn remove_session_cookies(cookies: &CookieJar<'_>) {
+    cookies.remove_private(Cookie::build(SESSION_COOKIE).path("/"));
+    cookies.remove(Cookie::build(CSRF_COOKIE).path("/"));
+}
+
+/// Remembers `login` as started by this client, so its callback can't be completed by another
+pub fn set_oidc_login_cookie(cookies: &CookieJar<'_>, login: &OidcLogin) {
+    cookies.add_private(
+        Cookie::build((OIDC_LOGIN_COOKIE, login.state()))
+            .path("/")
+            .http_only(true)
+            .secure(true)
+            .same_site(SameSite::Lax)
+            .max_age(Duration::seconds(LOGIN_LIFETIME.num_seconds())),
+    );
+}
+
+/// Removes the pending OIDC login cookie, returning the `state` of the login it bound
+pub fn take_oidc_login_cookie(cookies: &CookieJar<'_>) -> Option<String> {
+    let state = cookies
+        .get_private(OIDC_LOGIN_COOKIE)
+        .map(|cookie| cookie.value().to_string());
+    cookies.remove_private(Cookie::build(OIDC_LOGIN_COOKIE).path("/"));
+    state
+}
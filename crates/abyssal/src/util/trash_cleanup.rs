@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use rocket::fairing::AdHoc;
+
+use crate::{Config, models::TrashItem, util::MetaTree};
+
+/// How often the trash is checked for items past the retention period
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Purges every expired item, carrying on past (and logging) items that fail to purge
+async fn purge_expired_items(config: &Config, trash: &MetaTree<TrashItem>) -> crate::Result<()> {
+    for item in trash.all()? {
+        if item.is_expired(config)
+            && let Err(err) = item.purge(config, trash).await
+        {
+            rocket::error!("Failed to purge trash item {}: {err}", item.id());
+        }
+    }
+    Ok(())
+}
+
+/// Periodically purges trashed files older than the configured retention period
+pub fn trash_cleanup() -> AdHoc {
+    AdHoc::on_liftoff("Purge expired trash", |rocket| {
+        Box::pin(async move {
+            let config = rocket.state::<Config>().cloned().unwrap();
+            let trash = MetaTree::<TrashItem>::from_rocket(rocket).unwrap();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(PURGE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = purge_expired_items(&config, &trash).await {
+                        rocket::error!("Failed to purge expired trash: {err}");
+                    }
+                }
+            });
+        })
+    })
+}
@@ -0,0 +1,97 @@
+use std::{
+    collections::HashSet,
+    io::ErrorKind,
+    time::{Duration, SystemTime},
+};
+
+use rocket::fairing::AdHoc;
+
+use crate::{
+    Config,
+    models::{MetaRecord, UploadSession},
+    util::{MetaTree, UploadLocks},
+};
+
+/// How often upload sessions are checked for expiry
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+async fn purge_expired_sessions(
+    config: &Config,
+    uploads: &MetaTree<UploadSession>,
+    locks: &UploadLocks,
+) -> crate::Result<()> {
+    for session in uploads.all()? {
+        if !session.is_expired(config) {
+            continue;
+        }
+
+        // Re-check under the lock, in case a chunk arrived in the meantime
+        let _guard = locks.lock(&session.id()).await;
+        if let Some(session) = uploads.get(session.record_id())?
+            && session.is_expired(config)
+        {
+            session.discard(config, uploads).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes staged data no session refers to (left behind by interrupted drops or a crash)
+/// once it's older than the upload expiry
+async fn purge_orphaned_staging(
+    config: &Config,
+    uploads: &MetaTree<UploadSession>,
+) -> crate::Result<()> {
+    let expiry = match config.filesystem().upload_expiry() {
+        0 => return Ok(()),
+        expiry => Duration::from_secs(expiry),
+    };
+
+    let mut entries = match tokio::fs::read_dir(UploadSession::staging_dir(config)).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let staged = uploads
+        .all()?
+        .into_iter()
+        .map(|session| session.staging_path(config))
+        .collect::<HashSet<_>>();
+    while let Some(entry) = entries.next_entry().await? {
+        if staged.contains(&entry.path()) {
+            continue;
+        }
+
+        let modified = entry.metadata().await?.modified()?;
+        if SystemTime::now()
+            .duration_since(modified)
+            .is_ok_and(|age| age >= expiry)
+        {
+            let _ = tokio::fs::remove_file(entry.path()).await;
+        }
+    }
+    Ok(())
+}
+
+/// Periodically discards upload sessions that stopped receiving data, along with their staged data
+pub fn upload_cleanup() -> AdHoc {
+    AdHoc::on_liftoff("Purge expired uploads", |rocket| {
+        Box::pin(async move {
+            let config = rocket.state::<Config>().cloned().unwrap();
+            let locks = rocket.state::<UploadLocks>().cloned().unwrap();
+            let uploads = MetaTree::<UploadSession>::from_rocket(rocket).unwrap();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(PURGE_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = purge_expired_sessions(&config, &uploads, &locks).await {
+                        rocket::error!("Failed to purge expired uploads: {err}");
+                    }
+                    if let Err(err) = purge_orphaned_staging(&config, &uploads).await {
+                        rocket::error!("Failed to purge orphaned upload data: {err}");
+                    }
+                }
+            });
+        })
+    })
+}
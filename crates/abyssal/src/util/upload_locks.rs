@@ -0,0 +1,26 @@
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::types::Uuid;
+
+/// Serializes work on each upload session, so concurrent chunks can't interleave
+/// their writes and a session isn't discarded while a chunk is being written
+#[derive(Clone, Default)]
+pub struct UploadLocks {
+    locks: Arc<Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>>,
+}
+
+impl UploadLocks {
+    /// Waits until nothing else holds the session `id`, then holds it until the guard is dropped.
+    /// The session should be (re-)loaded after this, as it may have changed while waiting.
+    pub async fn lock(&self, id: &Uuid) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock();
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(id.clone()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
@@ -0,0 +1,270 @@
+use rocket_okapi::JsonSchema;
+use schemars::{
+    r#gen::SchemaGenerator,
+    schema::{InstanceType, Metadata, Schema, SchemaObject},
+};
+use serde::{Deserialize, Serialize};
+use webauthn_rs::{Webauthn, WebauthnBuilder, prelude::Url};
+
+use crate::types::Config;
+
+/// Builds the WebAuthn relying party from the configuration
+pub fn relying_party(config: &Config) -> crate::Result<Webauthn> {
+    let settings = config
+        .authentication()
+        .webauthn()
+        .ok_or(crate::Error::WebauthnDisabled)?;
+    let origin = Url::parse(&settings.origin())
+        .map_err(|e| anyhow::anyhow!("invalid WebAuthn origin: {e}"))?;
+    Ok(WebauthnBuilder::new(&settings.rp_id(), &origin)?
+        .rp_name(&settings.rp_name())
+        .build()?)
+}
+
+/// WebAuthn protocol structure, passed through to/from the browser's credential APIs as-is
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(transparent)]
+pub struct WebauthnJson<T>(pub T);
+
+impl<T> JsonSchema for WebauthnJson<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        String::from("WebauthnJson")
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            metadata: Some(Box::new(Metadata {
+                description: Some(String::from(
+                    "WebAuthn structure, as used by `navigator.credentials` (see the WebAuthn specification)",
+                )),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use base64::{Engine as _, prelude::BASE64_URL_SAFE_NO_PAD};
+    use openssl::{
+        bn::{BigNum, BigNumContext},
+        ec::{EcGroup, EcKey},
+        hash::MessageDigest,
+        nid::Nid,
+        pkey::{PKey, Private},
+        sign::Signer,
+    };
+    use serde_cbor_2::Value as Cbor;
+    use serde_json::{Value, json};
+    use sha2::{Digest, Sha256};
+    use webauthn_rs::prelude::{PublicKeyCredential, RegisterPublicKeyCredential, WebauthnError};
+
+    use super::*;
+    use crate::{
+        models::{CeremonyState, PasskeyCeremony, StoredPasskey},
+        types::Uuid,
+    };
+
+    const RP_ID: &str = "files.example.com";
+    const ORIGIN: &str = "https://files.example.com";
+
+    fn encode(data: impl AsRef<[u8]>) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(data)
+    }
+
+    /// Software authenticator with a single P-256 credential, answering the JSON options
+    /// returned by the API like a browser's `navigator.credentials` would
+    struct SoftAuthenticator {
+        key: PKey<Private>,
+        credential_id: Vec<u8>,
+        counter: u32,
+    }
+
+    impl SoftAuthenticator {
+        fn new() -> Self {
+            let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+            Self {
+                key: PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap(),
+                credential_id: Sha256::digest(Uuid::new().to_string()).to_vec(),
+                counter: 0,
+            }
+        }
+
+        fn client_data(kind: &str, options: &Value) -> Vec<u8> {
+            json!({
+                "type": kind,
+                "challenge": options["publicKey"]["challenge"],
+                "origin": ORIGIN,
+                "crossOrigin": false,
+            })
+            .to_string()
+            .into_bytes()
+        }
+
+        /// Authenticator data, with user presence & verification asserted
+        fn authenticator_data(&mut self, attested: Option<Vec<u8>>) -> Vec<u8> {
+            self.counter += 1;
+            let mut data = Sha256::digest(RP_ID).to_vec();
+            data.push(if attested.is_some() { 0x45 } else { 0x05 });
+            data.extend(self.counter.to_be_bytes());
+            data.extend(attested.unwrap_or_default());
+            data
+        }
+
+        /// Public key in COSE format (EC2, ES256, P-256)
+        fn cose_key(&self) -> Vec<u8> {
+            let key = self.key.ec_key().unwrap();
+            let (mut x, mut y) = (BigNum::new().unwrap(), BigNum::new().unwrap());
+            key.public_key()
+                .affine_coordinates(
+                    key.group(),
+                    &mut x,
+                    &mut y,
+                    &mut BigNumContext::new().unwrap(),
+                )
+                .unwrap();
+            serde_cbor_2::to_vec(&Cbor::Map(BTreeMap::from([
+                (Cbor::Integer(1), Cbor::Integer(2)),
+                (Cbor::Integer(3), Cbor::Integer(-7)),
+                (Cbor::Integer(-1), Cbor::Integer(1)),
+                (Cbor::Integer(-2), Cbor::Bytes(x.to_vec_padded(32).unwrap())),
+                (Cbor::Integer(-3), Cbor::Bytes(y.to_vec_padded(32).unwrap())),
+            ])))
+            .unwrap()
+        }
+
+        fn create(&mut self, options: &Value) -> RegisterPublicKeyCredential {
+            let mut attested = vec![0u8; 16];
+            attested.extend((self.credential_id.len() as u16).to_be_bytes());
+            attested.extend(&self.credential_id);
+            attested.extend(self.cose_key());
+            let attestation = serde_cbor_2::to_vec(&Cbor::Map(BTreeMap::from([
+                (
+                    Cbor::Text(String::from("fmt")),
+                    Cbor::Text(String::from("none")),
+                ),
+                (
+                    Cbor::Text(String::from("attStmt")),
+                    Cbor::Map(BTreeMap::new()),
+                ),
+                (
+                    Cbor::Text(String::from("authData")),
+                    Cbor::Bytes(self.authenticator_data(Some(attested))),
+                ),
+            ])))
+            .unwrap();
+
+            serde_json::from_value(json!({
+                "id": encode(&self.credential_id),
+                "rawId": encode(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "attestationObject": encode(attestation),
+                    "clientDataJSON": encode(Self::client_data("webauthn.create", options)),
+                },
+                "extensions": {},
+            }))
+            .unwrap()
+        }
+
+        fn get(&mut self, options: &Value) -> PublicKeyCredential {
+            let authenticator_data = self.authenticator_data(None);
+            let client_data = Self::client_data("webauthn.get", options);
+            let mut signer = Signer::new(MessageDigest::sha256(), &self.key).unwrap();
+            signer.update(&authenticator_data).unwrap();
+            signer.update(&Sha256::digest(&client_data)).unwrap();
+
+            serde_json::from_value(json!({
+                "id": encode(&self.credential_id),
+                "rawId": encode(&self.credential_id),
+                "type": "public-key",
+                "response": {
+                    "authenticatorData": encode(authenticator_data),
+                    "clientDataJSON": encode(client_data),
+                    "signature": encode(signer.sign_to_vec().unwrap()),
+                    "userHandle": null,
+                },
+                "extensions": {},
+            }))
+            .unwrap()
+        }
+    }
+
+    /// Round-trips a ceremony through its stored form, as the routes do between requests
+    fn stored(user: &Uuid, state: CeremonyState) -> CeremonyState {
+        let ceremony = PasskeyCeremony::new(user.clone(), state);
+        serde_json::from_str::<PasskeyCeremony>(&serde_json::to_string(&ceremony).unwrap())
+            .unwrap()
+            .state()
+    }
+
+    #[test]
+    fn passkey_registers_logs_in_and_rejects_replays() {
+        let config: Config = serde_json::from_value(json!({
+            "authentication": {"webauthn": {"rp_id": RP_ID, "origin": ORIGIN}},
+        }))
+        .unwrap();
+        let webauthn = relying_party(&config).unwrap();
+        let mut authenticator = SoftAuthenticator::new();
+        let user = Uuid::new();
+
+        let (options, state) = webauthn
+            .start_passkey_registration(user.clone().into(), "alice", "Alice", None)
+            .unwrap();
+        let CeremonyState::Registration { name, state } = stored(
+            &user,
+            CeremonyState::Registration {
+                name: String::from("Laptop"),
+                state,
+            },
+        ) else {
+            panic!("registration state should be stored as such");
+        };
+        let credential = authenticator.create(&serde_json::to_value(options).unwrap());
+        let passkey = StoredPasskey::new(
+            name,
+            webauthn
+                .finish_passkey_registration(&credential, &state)
+                .unwrap(),
+        );
+        assert_eq!(passkey.id(), encode(&authenticator.credential_id));
+
+        let (options, state) = webauthn
+            .start_passkey_authentication(&[passkey.passkey()])
+            .unwrap();
+        let CeremonyState::Authentication { state, .. } = stored(
+            &user,
+            CeremonyState::Authentication {
+                state,
+                challenge: None,
+            },
+        ) else {
+            panic!("authentication state should be stored as such");
+        };
+        let assertion = authenticator.get(&serde_json::to_value(options).unwrap());
+        let result = webauthn
+            .finish_passkey_authentication(&assertion, &state)
+            .unwrap();
+        assert_eq!(StoredPasskey::credential_id(result.cred_id()), passkey.id());
+        let passkey = passkey.with_authentication(&result);
+        assert!(passkey.last_used().is_some());
+
+        // Ceremonies are single-use, so a captured assertion can only be replayed against a new one
+        let (_, state) = webauthn
+            .start_passkey_authentication(&[passkey.passkey()])
+            .unwrap();
+        assert!(matches!(
+            webauthn.finish_passkey_authentication(&assertion, &state),
+            Err(WebauthnError::MismatchedChallenge)
+        ));
+    }
+}
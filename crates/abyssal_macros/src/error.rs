@@ -26,9 +26,16 @@ struct Error {
 
     #[darling(default)]
     pub arc: bool,
+
+    /// Response header to send the variant's (single) field as
+    #[darling(default)]
+    pub header: Option<String>,
 }
 
-fn process_item(parent: Ident, item: Variant, meta_ident: Ident) -> manyhow::Result<(Variant, Arm, Option<ItemImpl>, u16)> {
+/// Rewritten variant, its metadata arm, optional `From` impl, optional header arm & status code
+type ProcessedVariant = (Variant, Arm, Option<ItemImpl>, Option<Arm>, u16);
+
+fn process_item(parent: Ident, item: Variant, meta_ident: Ident) -> manyhow::Result<ProcessedVariant> {
     let args = Error::from_attributes(&item.attrs).map_err(|e| syn::Error::new(Span::call_site(), e.to_string()))?;
 
     let fields = item.fields.clone();
@@ -39,6 +46,7 @@ fn process_item(parent: Ident, item: Variant, meta_ident: Ident) -> manyhow::Res
         description: args_description,
         from: args_from,
         arc: args_arc,
+        header: args_header,
     } = args;
 
     
@@ -139,7 +147,18 @@ fn process_item(parent: Ident, item: Variant, meta_ident: Ident) -> manyhow::Res
         None
     };
 
-    Ok((new_variant, meta_arm, impl_from, args_status))
+    let header_arm: Option<Arm> = if let Some(header) = args_header {
+        if tuple_type.is_none() {
+            return Err(syn::Error::new(Span::call_site(), "Can only use `header` on tuple variants with exactly one field.").into());
+        }
+        Some(syn::parse2(quote! {
+            Self::#item_ident (value) => Some(Header::new(#header, value.to_string()))
+        })?)
+    } else {
+        None
+    };
+
+    Ok((new_variant, meta_arm, impl_from, header_arm, args_status))
 }
 
 pub fn impl_error(_: TokenStream, item: TokenStream) -> manyhow::Result<TokenStream> {
@@ -152,14 +171,18 @@ pub fn impl_error(_: TokenStream, item: TokenStream) -> manyhow::Result<TokenStr
     let mut error_variants: Punctuated<Variant, Token![,]> = Punctuated::new();
     let mut meta_arms: Punctuated<Arm, Token![,]> = Punctuated::new();
     let mut impl_froms: Vec<ItemImpl> = vec![];
+    let mut header_arms: Vec<Arm> = vec![];
     let mut status_codes: Vec<u16> = vec![];
     for variant in input.variants {
-        let (new_variant, meta_arm, impl_from, status_code) = process_item(enum_ident.clone(), variant, metadata_ident.clone())?;
+        let (new_variant, meta_arm, impl_from, header_arm, status_code) = process_item(enum_ident.clone(), variant, metadata_ident.clone())?;
         error_variants.push(new_variant);
         meta_arms.push(meta_arm);
         if let Some(ifr) = impl_from {
             impl_froms.push(ifr);
         }
+        if let Some(arm) = header_arm {
+            header_arms.push(arm);
+        }
         if !status_codes.contains(&status_code) {
             status_codes.push(status_code);
         }
@@ -197,7 +220,7 @@ pub fn impl_error(_: TokenStream, item: TokenStream) -> manyhow::Result<TokenStr
             use rocket::{
                 response::{self, Response, Responder},
                 request::Request,
-                http::{ContentType, Status},
+                http::{ContentType, Header, Status},
                 serde::json::Json
             };
             use okapi::openapi3::{Responses, Response as OpenApiResponse, RefOr, MediaType};
@@ -225,11 +248,25 @@ pub fn impl_error(_: TokenStream, item: TokenStream) -> manyhow::Result<TokenStr
                         #meta_arms
                     }
                 }
+
+                /// Extra response header carried by this error, if any
+                pub fn header(&self) -> Option<Header<'static>> {
+                    #[allow(unreachable_patterns)]
+                    match self {
+                        #(#header_arms,)*
+                        _ => None
+                    }
+                }
             }
 
             impl<'r> Responder<'r, 'static> for #enum_ident {
                 fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
-                    Response::build_from(Json(self.metadata()).respond_to(req)?).status(Status::new(self.metadata().status)).ok()
+                    let mut response = Response::build_from(Json(self.metadata()).respond_to(req)?);
+                    response.status(Status::new(self.metadata().status));
+                    if let Some(header) = self.header() {
+                        response.header(header);
+                    }
+                    response.ok()
                 }
             }
 